@@ -0,0 +1,38 @@
+use std::{env, fs, process};
+
+use pbrt4::{format::format, Result};
+
+/// Formats pbrt files in place, like `pbrt --format`.
+/// Usage:
+/// `❯ cargo run --example format -- [--check] scene.pbrt ...`
+///
+/// With `--check` files are not modified, the exit code is 1 if any of them is not formatted.
+fn main() -> Result<()> {
+    // Flags apply to all files, wherever they are given.
+    let (flags, files): (Vec<_>, Vec<_>) = env::args().skip(1).partition(|arg| arg == "--check");
+    let check = !flags.is_empty();
+
+    let mut unformatted = false;
+
+    for arg in files {
+        let source = fs::read_to_string(&arg)?;
+        let formatted = format(&source)?;
+
+        if formatted == source {
+            continue;
+        }
+
+        if check {
+            println!("{arg} is not formatted");
+            unformatted = true;
+        } else {
+            fs::write(&arg, formatted)?;
+        }
+    }
+
+    if unformatted {
+        process::exit(1);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,23 @@
+use std::{env, fs};
+
+use pbrt4::{upgrade::upgrade, Result};
+
+/// Converts pbrt-v3 files to pbrt-v4 in place, like `pbrt --upgrade`.
+/// Usage:
+/// `❯ cargo run --example upgrade -- scene.pbrt ...`
+///
+/// Anything that could not be converted is printed as `file:line:column: message`.
+fn main() -> Result<()> {
+    for arg in env::args().skip(1) {
+        let source = fs::read_to_string(&arg)?;
+        let upgrade = upgrade(&source)?;
+
+        for warning in &upgrade.warnings {
+            eprintln!("{arg}:{warning}");
+        }
+
+        fs::write(&arg, upgrade.text)?;
+    }
+
+    Ok(())
+}
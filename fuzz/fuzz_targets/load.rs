@@ -0,0 +1,34 @@
+#![no_main]
+
+use std::{fs, path::PathBuf};
+
+use libfuzzer_sys::fuzz_target;
+use pbrt4::Scene;
+
+/// Number of files besides the main one that `Include` and `Import` can refer to.
+const FILES: usize = 3;
+
+/// Scratch directory of this fuzzing process.
+fn directory() -> PathBuf {
+    std::env::temp_dir().join(format!("pbrt4-fuzz-{}", std::process::id()))
+}
+
+fuzz_target!(|data: &str| {
+    // The input is split on NUL characters into the main file and files
+    // named "1.pbrt", "2.pbrt", ... for `Include` and `Import` directives.
+    let mut parts = data.split('\0');
+    let main = parts.next().unwrap_or_default();
+
+    let directory = directory();
+    fs::create_dir_all(&directory).unwrap();
+    for index in 1..=FILES {
+        let part = parts.next().unwrap_or_default();
+        fs::write(directory.join(format!("{index}.pbrt")), part).unwrap();
+    }
+
+    if let Ok(scene) = Scene::load(main, Some(&directory)) {
+        // Loaded scenes can always be written back, unless they contain
+        // infinite or NaN values.
+        let _ = scene.write_to(&mut Vec::new());
+    }
+});
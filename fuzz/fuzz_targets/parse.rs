@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pbrt4::{Parser, Version};
+
+fuzz_target!(|data: &str| {
+    for version in [Version::V3, Version::V4] {
+        let mut parser = Parser::with_version(data, version);
+        while let Ok(element) = parser.parse_next() {
+            // Elements with infinite or NaN values can't be written.
+            let _ = element.write_to(&mut Vec::new());
+        }
+    }
+});
@@ -0,0 +1,153 @@
+//! RGB color spaces.
+
+use std::{fmt, str::FromStr};
+
+use glam::{Mat3, Vec3};
+
+use crate::Error;
+
+/// Named RGB color spaces supported by pbrt.
+///
+/// The color space determines how RGB values in the scene are interpreted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// sRGB (ITU-R BT.709 primaries, D65 white point), the default.
+    #[default]
+    Srgb,
+    /// ITU-R BT.2020 primaries, D65 white point.
+    Rec2020,
+    /// ACES primaries (AP0), ACES white point (approximately D60).
+    Aces2065_1,
+    /// DCI-P3 primaries, D65 white point.
+    DciP3,
+}
+
+impl FromStr for ColorSpace {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let color_space = match s {
+            "srgb" => ColorSpace::Srgb,
+            "rec2020" => ColorSpace::Rec2020,
+            "aces2065-1" => ColorSpace::Aces2065_1,
+            "dci-p3" => ColorSpace::DciP3,
+            _ => return Err(Error::UnknownColorSpace),
+        };
+
+        Ok(color_space)
+    }
+}
+
+impl fmt::Display for ColorSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Convert chromaticity coordinates to XYZ with `Y` equal to 1.
+fn xy_to_xyz([x, y]: [f32; 2]) -> Vec3 {
+    Vec3::new(x / y, 1.0, (1.0 - x - y) / y)
+}
+
+impl ColorSpace {
+    /// Name of the color space, as used by the `ColorSpace` directive.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "srgb",
+            ColorSpace::Rec2020 => "rec2020",
+            ColorSpace::Aces2065_1 => "aces2065-1",
+            ColorSpace::DciP3 => "dci-p3",
+        }
+    }
+
+    /// Chromaticities (xy) of the red, green and blue primaries.
+    pub fn primaries(&self) -> [[f32; 2]; 3] {
+        match self {
+            ColorSpace::Srgb => [[0.64, 0.33], [0.3, 0.6], [0.15, 0.06]],
+            ColorSpace::Rec2020 => [[0.708, 0.292], [0.170, 0.797], [0.131, 0.046]],
+            ColorSpace::Aces2065_1 => [[0.7347, 0.2653], [0.0, 1.0], [0.0001, -0.077]],
+            ColorSpace::DciP3 => [[0.68, 0.32], [0.265, 0.69], [0.15, 0.06]],
+        }
+    }
+
+    /// Chromaticity (xy) of the white point.
+    pub fn white_point(&self) -> [f32; 2] {
+        match self {
+            ColorSpace::Srgb | ColorSpace::Rec2020 | ColorSpace::DciP3 => [0.3127, 0.3290],
+            ColorSpace::Aces2065_1 => [0.32168, 0.33767],
+        }
+    }
+
+    /// Matrix that converts linear RGB values in this color space to XYZ.
+    pub fn rgb_to_xyz(&self) -> Mat3 {
+        let [r, g, b] = self.primaries();
+        let primaries = Mat3::from_cols(xy_to_xyz(r), xy_to_xyz(g), xy_to_xyz(b));
+
+        // Scale primaries so that RGB (1, 1, 1) maps to the white point.
+        let scale = primaries.inverse() * xy_to_xyz(self.white_point());
+
+        primaries * Mat3::from_diagonal(scale)
+    }
+
+    /// Matrix that converts XYZ values to linear RGB in this color space.
+    pub fn xyz_to_rgb(&self) -> Mat3 {
+        self.rgb_to_xyz().inverse()
+    }
+
+    /// Convert linear RGB value from this color space to `other`.
+    ///
+    /// Just like pbrt, no chromatic adaptation is done between different white points.
+    pub fn convert(&self, rgb: [f32; 3], other: ColorSpace) -> [f32; 3] {
+        if *self == other {
+            return rgb;
+        }
+
+        let xyz = self.rgb_to_xyz() * Vec3::from(rgb);
+        (other.xyz_to_rgb() * xyz).to_array()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-4), "{} != {}", a, b);
+    }
+
+    #[test]
+    fn parse_color_space() {
+        assert_eq!("srgb".parse::<ColorSpace>().unwrap(), ColorSpace::Srgb);
+        assert_eq!(
+            "aces2065-1".parse::<ColorSpace>().unwrap(),
+            ColorSpace::Aces2065_1
+        );
+
+        assert!("foo".parse::<ColorSpace>().is_err());
+    }
+
+    #[test]
+    fn srgb_to_xyz() {
+        let m = ColorSpace::Srgb.rgb_to_xyz();
+
+        assert_near(m.col(0), Vec3::new(0.4124, 0.2126, 0.0193));
+        assert_near(m.col(1), Vec3::new(0.3576, 0.7152, 0.1192));
+        assert_near(m.col(2), Vec3::new(0.1805, 0.0722, 0.9505));
+    }
+
+    #[test]
+    fn white_is_preserved() {
+        for color_space in [
+            ColorSpace::Srgb,
+            ColorSpace::Rec2020,
+            ColorSpace::Aces2065_1,
+            ColorSpace::DciP3,
+        ] {
+            let white = color_space.rgb_to_xyz() * Vec3::ONE;
+            assert_near(white, xy_to_xyz(color_space.white_point()));
+
+            let rgb = color_space.xyz_to_rgb() * white;
+            assert_near(rgb, Vec3::ONE);
+        }
+    }
+}
@@ -0,0 +1,393 @@
+//! Lossless concrete syntax tree.
+//!
+//! Unlike [Parser], the syntax tree keeps whitespace and comments, so a file can be
+//! edited and written back with all untouched bytes intact.
+//!
+//! ```
+//! # use pbrt4::cst::Cst;
+//! let mut cst = Cst::parse("Camera \"perspective\" \"float fov\" 45 # wide\n")?;
+//! cst.set_param(0, "fov", "50")?;
+//!
+//! assert_eq!(cst.to_string(), "Camera \"perspective\" \"float fov\" 50 # wide\n");
+//! # Ok::<(), pbrt4::Error>(())
+//! ```
+
+use std::{fmt, io, ops::Range};
+
+use crate::{
+    param::Param, token::Token, tokenizer::Tokenizer, Element, Error, Parser, Result, Span, Version,
+};
+
+/// Kind of a syntax token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// Run of spaces, tabs and line breaks.
+    Whitespace,
+    /// Comment from `#` up to the end of the line.
+    Comment,
+    Directive,
+    /// Quoted string, including quotes.
+    String,
+    OpenBracket,
+    CloseBracket,
+    /// Number or any other unquoted value.
+    Value,
+}
+
+/// A token with its position in the source, trivia included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyntaxToken<'a> {
+    pub kind: TokenKind,
+    /// Source text of the token.
+    pub text: &'a str,
+    pub span: Span,
+}
+
+impl SyntaxToken<'_> {
+    /// Whether the token is whitespace or a comment.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self.kind, TokenKind::Whitespace | TokenKind::Comment)
+    }
+}
+
+/// A parsed directive and the range of tokens it covers.
+#[derive(Debug)]
+pub struct Node<'a> {
+    pub element: Element<'a>,
+    /// Byte range of the directive, from the directive name to the last value.
+    pub span: Span,
+    /// Range of the directive tokens in [Cst::tokens].
+    pub tokens: Range<usize>,
+}
+
+/// Scene file with all tokens including whitespace and comments.
+///
+/// Edits are recorded as replacements of byte ranges of the original source
+/// and applied when the tree is written.
+#[derive(Debug)]
+pub struct Cst<'a> {
+    source: &'a str,
+    tokens: Vec<SyntaxToken<'a>>,
+    nodes: Vec<Node<'a>>,
+    /// Non-overlapping edits, sorted by position.
+    edits: Vec<(Range<usize>, String)>,
+}
+
+impl<'a> Cst<'a> {
+    /// Parse a scene file, included and imported files are not read.
+    pub fn parse(source: &'a str) -> Result<Self> {
+        Self::with_version(source, Version::V4)
+    }
+
+    /// Parse a scene file of the given format version.
+    pub fn with_version(source: &'a str, version: Version) -> Result<Self> {
+        let tokens = Tokenizer::with_trivia(source)
+            .map(|token| SyntaxToken {
+                kind: kind(&token),
+                text: &source[token.span().start..token.span().end],
+                span: token.span(),
+            })
+            .collect::<Vec<_>>();
+
+        let mut parser = Parser::with_version(source, version);
+        let mut nodes = Vec::new();
+
+        loop {
+            let element = match parser.parse_next() {
+                Ok(element) => element,
+                Err(Error::EndOfFile) => break,
+                Err(err) => return Err(err),
+            };
+
+            let mut span = parser.directive_span().unwrap_or_default();
+            span.end = parser.offset();
+
+            let start = tokens.partition_point(|token| token.span.start < span.start);
+            let end = tokens.partition_point(|token| token.span.start < span.end);
+
+            nodes.push(Node {
+                element,
+                span,
+                tokens: start..end,
+            });
+        }
+
+        Ok(Cst {
+            source,
+            tokens,
+            nodes,
+            edits: Vec::new(),
+        })
+    }
+
+    /// Original source.
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    /// All tokens, concatenated they give the original source.
+    pub fn tokens(&self) -> &[SyntaxToken<'a>] {
+        &self.tokens
+    }
+
+    /// Parsed directives in the order of appearance.
+    pub fn nodes(&self) -> &[Node<'a>] {
+        &self.nodes
+    }
+
+    /// Replace a byte range of the original source with `text`.
+    ///
+    /// Replacing the same range again overrides the previous edit, while insertions
+    /// at the same position are appended. Other edits must not overlap.
+    pub fn replace(&mut self, range: Range<usize>, text: impl Into<String>) -> Result<()> {
+        if range.start > range.end
+            || range.end > self.source.len()
+            || !self.source.is_char_boundary(range.start)
+            || !self.source.is_char_boundary(range.end)
+        {
+            return Err(Error::InvalidEdit);
+        }
+
+        let index = self
+            .edits
+            .partition_point(|(edit, _)| (edit.start, edit.end) < (range.start, range.end));
+
+        if let Some((edit, replacement)) = self.edits.get_mut(index) {
+            if *edit == range {
+                // Insertions at the same position are kept in order.
+                if range.is_empty() {
+                    replacement.push_str(&text.into());
+                } else {
+                    *replacement = text.into();
+                }
+                return Ok(());
+            }
+        }
+
+        let overlaps = |edit: &Range<usize>| edit.start < range.end && range.start < edit.end;
+
+        let before = index.checked_sub(1).map(|index| &self.edits[index].0);
+        let after = self.edits.get(index).map(|(edit, _)| edit);
+        if before.into_iter().chain(after).any(overlaps) {
+            return Err(Error::InvalidEdit);
+        }
+
+        self.edits.insert(index, (range, text.into()));
+        Ok(())
+    }
+
+    /// Replace the value of parameter `name` of the directive at `index`.
+    ///
+    /// `value` is written as is, arrays must include the brackets.
+    pub fn set_param(&mut self, index: usize, name: &str, value: &str) -> Result<()> {
+        let range = self.param_value(index, name)?;
+        self.replace(range, value)
+    }
+
+    /// Append a parameter to the directive at `index`.
+    pub fn add_param(&mut self, index: usize, type_and_name: &str, value: &str) -> Result<()> {
+        let param = Param::new(type_and_name, value)?;
+        let node = self.nodes.get(index).ok_or(Error::NotFound)?;
+
+        let Some(params) = node.element.params() else {
+            return Err(Error::ElementNotAllowed);
+        };
+
+        if params.get(param.name).is_some() {
+            return Err(Error::DuplicatedParamName.with_param(param.name));
+        }
+
+        let end = node.span.end;
+        self.replace(end..end, format!(" \"{type_and_name}\" {value}"))
+    }
+
+    /// Remove parameter `name` of the directive at `index`, along with the preceding whitespace.
+    pub fn remove_param(&mut self, index: usize, name: &str) -> Result<()> {
+        let value = self.param_value(index, name)?;
+        let start = self.param_start(index, name)?;
+
+        self.replace(self.leading_whitespace(start)..value.end, "")
+    }
+
+    /// Start of the whitespace right before `offset`, or `offset` if there is none.
+    pub(crate) fn leading_whitespace(&self, offset: usize) -> usize {
+        let token = self
+            .tokens
+            .partition_point(|token| token.span.start < offset);
+
+        match token.checked_sub(1).map(|index| &self.tokens[index]) {
+            Some(token) if token.kind == TokenKind::Whitespace => token.span.start,
+            _ => offset,
+        }
+    }
+
+    /// Find the position of the parameter name token.
+    fn param_start(&self, index: usize, name: &str) -> Result<usize> {
+        let node = self.nodes.get(index).ok_or(Error::NotFound)?;
+
+        let param = match &node.element {
+            Element::Option(param) if param.name == name => Some(param),
+            element => element.params().and_then(|params| params.get(name)),
+        };
+
+        let param = param.ok_or_else(|| Error::NotFound.with_param(name))?;
+        Ok(param.span().map_or(node.span.start, |span| span.start))
+    }
+
+    /// Byte range of the parameter value, including brackets.
+    pub(crate) fn param_value(&self, index: usize, name: &str) -> Result<Range<usize>> {
+        let start = self.param_start(index, name)?;
+
+        let mut tokens = self.tokens[self.nodes[index].tokens.clone()]
+            .iter()
+            .skip_while(|token| token.span.start <= start)
+            .filter(|token| !token.is_trivia());
+
+        let first = tokens.next().ok_or(Error::NoToken)?;
+        let last = if first.kind == TokenKind::OpenBracket {
+            tokens
+                .find(|token| token.kind == TokenKind::CloseBracket)
+                .ok_or(Error::UnexpectedToken)?
+        } else {
+            first
+        };
+
+        Ok(first.span.start..last.span.end)
+    }
+
+    /// Write the source with all edits applied.
+    pub fn write_to<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(writer, "{self}")
+    }
+}
+
+impl fmt::Display for Cst<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut offset = 0;
+
+        for (range, text) in &self.edits {
+            f.write_str(&self.source[offset..range.start])?;
+            f.write_str(text)?;
+            offset = range.end;
+        }
+
+        f.write_str(&self.source[offset..])
+    }
+}
+
+fn kind(token: &Token) -> TokenKind {
+    let text = token.value();
+
+    if text.is_empty() {
+        TokenKind::Whitespace
+    } else if text.starts_with('#') {
+        TokenKind::Comment
+    } else if text.starts_with('"') {
+        TokenKind::String
+    } else if token.is_open_brace() {
+        TokenKind::OpenBracket
+    } else if token.is_close_brace() {
+        TokenKind::CloseBracket
+    } else if token.is_directive() {
+        TokenKind::Directive
+    } else {
+        TokenKind::Value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"# Camera setup
+LookAt 0 0 -5  0 0 0  0 1 0
+Camera "perspective"   "float fov" 45  # degrees
+    "float lensradius" [ 0.1 ]
+
+WorldBegin
+Shape "trianglemesh" "point3 P" [ 0 0 0
+    1 0 0
+    0 1 0 ] "integer indices" [ 0 1 2 ]
+"#;
+
+    #[test]
+    fn lossless() -> Result<()> {
+        let cst = Cst::parse(SOURCE)?;
+
+        let text = cst
+            .tokens()
+            .iter()
+            .map(|token| token.text)
+            .collect::<String>();
+        assert_eq!(text, SOURCE);
+        assert_eq!(cst.to_string(), SOURCE);
+
+        assert_eq!(cst.tokens()[0].kind, TokenKind::Comment);
+        assert_eq!(cst.tokens()[0].text, "# Camera setup");
+
+        let nodes = cst.nodes();
+        assert_eq!(nodes.len(), 4);
+        assert!(matches!(nodes[1].element, Element::Camera { .. }));
+
+        let camera = &SOURCE[nodes[1].span.start..nodes[1].span.end];
+        assert!(camera.starts_with("Camera"));
+        assert!(camera.ends_with("[ 0.1 ]"));
+
+        let tokens = &cst.tokens()[nodes[1].tokens.clone()];
+        assert!(tokens.iter().any(|token| token.text == "# degrees"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn edit_params() -> Result<()> {
+        let mut cst = Cst::parse(SOURCE)?;
+
+        cst.set_param(1, "fov", "50")?;
+        cst.set_param(1, "lensradius", "[ 0.2 ]")?;
+        cst.set_param(1, "fov", "60")?;
+        cst.add_param(1, "float focaldistance", "10")?;
+        cst.add_param(1, "float screenwindow", "[ -1 1 -1 1 ]")?;
+        cst.remove_param(3, "P")?;
+
+        let expected = SOURCE
+            .replace("45", "60")
+            .replace(
+                "[ 0.1 ]",
+                "[ 0.2 ] \"float focaldistance\" 10 \"float screenwindow\" [ -1 1 -1 1 ]",
+            )
+            .replace(" \"point3 P\" [ 0 0 0\n    1 0 0\n    0 1 0 ]", "");
+        assert_eq!(cst.to_string(), expected);
+
+        // The written file is still valid.
+        let cst = Cst::parse(&expected)?;
+        assert_eq!(cst.nodes().len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn edit_errors() -> Result<()> {
+        let mut cst = Cst::parse(SOURCE)?;
+
+        let err = cst.set_param(1, "foo", "1").unwrap_err();
+        assert!(matches!(err.kind(), Error::NotFound));
+
+        let err = cst.add_param(1, "float fov", "1").unwrap_err();
+        assert!(matches!(err.kind(), Error::DuplicatedParamName));
+
+        let err = cst.add_param(2, "float fov", "1").unwrap_err();
+        assert!(matches!(err.kind(), Error::ElementNotAllowed));
+
+        cst.remove_param(3, "P")?;
+        let err = cst.set_param(3, "P", "[ ]").unwrap_err();
+        assert!(matches!(err.kind(), Error::InvalidEdit));
+
+        assert!(matches!(
+            cst.replace(0..SOURCE.len() + 1, ""),
+            Err(Error::InvalidEdit)
+        ));
+
+        Ok(())
+    }
+}
@@ -1,11 +1,14 @@
 use std::{
-    io,
+    fmt, io,
     num::{ParseFloatError, ParseIntError},
+    path::PathBuf,
     str::ParseBoolError,
 };
 
 use thiserror::Error;
 
+use crate::token::Span;
+
 #[derive(Error, Debug)]
 pub enum Error {
     /// No more tokens.
@@ -19,6 +22,10 @@ pub enum Error {
     #[error("Failed to read file")]
     Io(#[from] io::Error),
 
+    /// File is compressed, but the crate was built without the `gzip` feature.
+    #[error("Compressed files require the \"gzip\" feature")]
+    CompressionNotSupported,
+
     /// Token didn't pass basic validation checks.
     #[error("Invalid token")]
     InvalidToken,
@@ -50,9 +57,20 @@ pub enum Error {
     #[error("Unable to parse option value")]
     InvalidOptionValue,
 
+    /// `Option` name is not one of the pbrt-v4 options.
+    #[error("Unknown option")]
+    UnknownOption,
+
     #[error("Unsupported coordinate system")]
     UnknownCoordinateSystem,
 
+    #[error("Unknown color space")]
+    UnknownColorSpace,
+
+    /// `ActiveTransform` argument is not one of `StartTime`, `EndTime` or `All`.
+    #[error("Unknown ActiveTransform argument")]
+    UnknownActiveTransform,
+
     #[error("Invalid parameter name")]
     InvalidParamName,
 
@@ -66,12 +84,22 @@ pub enum Error {
     #[error("Duplicated WorldBegin statement")]
     WorldAlreadyStarted,
 
+    #[error("WorldBegin statement is missing")]
+    MissingWorldBegin,
+
+    /// A file is included or imported by itself, directly or through other files.
+    #[error("Recursive Include or Import")]
+    RecursiveInclude,
+
     #[error("Element is not allowed")]
     ElementNotAllowed,
 
     #[error("Too many AttributeEnd")]
     TooManyEndAttributes,
 
+    #[error("AttributeBegin without matching AttributeEnd")]
+    MissingEndAttribute,
+
     #[error("Attempt to restore CoordSysTransform matrix with invalid name")]
     InvalidMatrixName,
 
@@ -92,4 +120,203 @@ pub enum Error {
 
     #[error("Not found")]
     NotFound,
+
+    /// PLY mesh file is malformed or uses unsupported features.
+    #[error("Invalid PLY file: {0}")]
+    InvalidPly(&'static str),
+
+    /// NanoVDB file is malformed or uses unsupported features.
+    #[error("Invalid NanoVDB file: {0}")]
+    InvalidNanoVdb(&'static str),
+
+    /// Referenced texture is a float texture where a spectrum one is expected, or vice versa.
+    #[error("Texture has unexpected type")]
+    InvalidTextureType,
+
+    /// Syntax tree edit is out of bounds or overlaps another edit.
+    #[error("Invalid edit")]
+    InvalidEdit,
+
+    /// An error annotated with the place in the scene file where it happened.
+    #[error("{error}\n{location}")]
+    Located {
+        location: Box<Location>,
+        error: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Returns the underlying error, skipping the location information.
+    pub fn kind(&self) -> &Error {
+        match self {
+            Error::Located { error, .. } => error.kind(),
+            err => err,
+        }
+    }
+
+    /// Returns where the error happened, if known.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Error::Located { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// Returns a mutable reference to the error's location,
+    /// wrapping the error into [Error::Located] if needed.
+    pub(crate) fn location_mut(&mut self) -> &mut Location {
+        if !matches!(self, Error::Located { .. }) {
+            let error = std::mem::replace(self, Error::NotFound);
+            *self = Error::Located {
+                location: Box::default(),
+                error: Box::new(error),
+            };
+        }
+
+        match self {
+            Error::Located { location, .. } => location,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Attach a source position to the error, unless it already has one.
+    pub(crate) fn at(mut self, span: Span) -> Error {
+        self.location_mut().span.get_or_insert(span);
+        self
+    }
+
+    /// Attach a parameter name to the error, unless it already has one.
+    pub(crate) fn with_param(mut self, name: &str) -> Error {
+        self.location_mut()
+            .param
+            .get_or_insert_with(|| name.to_string());
+        self
+    }
+
+    /// Fill in location details the error doesn't have yet.
+    ///
+    /// The snippet is only taken along with the span, so they always match.
+    pub(crate) fn with_location(mut self, other: &Location) -> Error {
+        let location = self.location_mut();
+
+        if location.span.is_none() {
+            location.span = other.span;
+            location.snippet = other.snippet.clone();
+        }
+
+        if location.path.is_none() {
+            location.path = other.path.clone();
+        }
+
+        if location.directive.is_none() {
+            location.directive = other.directive.clone();
+        }
+
+        if location.param.is_none() {
+            location.param = other.param.clone();
+        }
+
+        if location.included_from.is_empty() {
+            location.included_from = other.included_from.clone();
+        }
+
+        self
+    }
+
+    /// Attach a file path to the error, unless it already has one.
+    pub(crate) fn with_path(mut self, path: impl Into<PathBuf>) -> Error {
+        self.location_mut().path.get_or_insert_with(|| path.into());
+        self
+    }
+}
+
+/// Describes where in a scene file an error happened.
+#[derive(Debug, Default, Clone)]
+pub struct Location {
+    /// Path to the file, `None` when loading from a string.
+    pub path: Option<PathBuf>,
+    /// Position of the offending token.
+    pub span: Option<Span>,
+    /// Directive being parsed or loaded.
+    pub directive: Option<String>,
+    /// Name of the offending parameter.
+    pub param: Option<String>,
+    /// Source line the span points to.
+    pub snippet: Option<String>,
+    /// Chain of `Include` directives that led to the file, innermost first.
+    pub included_from: Vec<IncludeSite>,
+}
+
+/// Position of an `Include` directive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncludeSite {
+    /// Path to the file with the directive, `None` when loading from a string.
+    pub path: Option<PathBuf>,
+    /// Line number of the directive, starting from 1.
+    pub line: usize,
+}
+
+impl fmt::Display for IncludeSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}:{}", path.display(), self.line),
+            None => write!(f, "<string>:{}", self.line),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, " --> ")?;
+
+        match &self.path {
+            Some(path) => write!(f, "{}", path.display())?,
+            None => write!(f, "<string>")?,
+        }
+
+        if let Some(span) = &self.span {
+            write!(f, ":{}:{}", span.line, span.column)?;
+        }
+
+        match (&self.directive, &self.param) {
+            (Some(directive), Some(param)) => {
+                write!(f, " ({}, parameter \"{}\")", directive, param)?
+            }
+            (Some(directive), None) => write!(f, " ({})", directive)?,
+            (None, Some(param)) => write!(f, " (parameter \"{}\")", param)?,
+            (None, None) => {}
+        }
+
+        for site in &self.included_from {
+            write!(f, "\n  included from {}", site)?;
+        }
+
+        let (Some(span), Some(snippet)) = (&self.span, &self.snippet) else {
+            return Ok(());
+        };
+
+        let line = span.line.to_string();
+        let pad = " ".repeat(line.len());
+
+        writeln!(f)?;
+        writeln!(f, "{} |", pad)?;
+        writeln!(f, "{} | {}", line, snippet)?;
+
+        // Keep tabs so the caret lines up with the snippet.
+        let indent = snippet
+            .chars()
+            .take(span.column.saturating_sub(1))
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+
+        // Underline the token, but don't go past the end of the line.
+        let width = snippet
+            .chars()
+            .skip(span.column.saturating_sub(1))
+            .count()
+            .min(span.end.saturating_sub(span.start))
+            .max(1);
+
+        write!(f, "{} | {}{}", pad, indent, "^".repeat(width))
+    }
 }
@@ -0,0 +1,42 @@
+//! File reading helpers.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+
+use crate::Result;
+
+/// Returns `true` if the file is expected to be gzip compressed.
+///
+/// Just like pbrt, this relies on the ".gz" suffix of the file name.
+pub(crate) fn is_gzip(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == "gz")
+}
+
+/// Open a file for reading, decompressing it on the fly if needed.
+pub(crate) fn open(path: &Path) -> Result<Box<dyn BufRead + Send>> {
+    let file = BufReader::new(File::open(path)?);
+
+    if !is_gzip(path) {
+        return Ok(Box::new(file));
+    }
+
+    #[cfg(feature = "gzip")]
+    {
+        let decoder = flate2::bufread::MultiGzDecoder::new(file);
+        Ok(Box::new(BufReader::new(decoder)))
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    Err(crate::Error::CompressionNotSupported)
+}
+
+/// Read the entire file into a string, decompressing it if needed.
+pub(crate) fn read_to_string(path: &Path) -> Result<String> {
+    let mut data = String::new();
+    open(path)?.read_to_string(&mut data)?;
+
+    Ok(data)
+}
@@ -0,0 +1,338 @@
+//! Canonical formatting of scene files, similar to `pbrt --format`.
+//!
+//! Directives are indented by `AttributeBegin` and `ObjectBegin` nesting, each parameter
+//! goes to a separate line and arrays that don't fit into a line are wrapped.
+//! Values are kept as written, comments and single blank lines are preserved.
+
+use crate::{param::ParamType, tokenizer::Tokenizer, Element, Error, Parser, Result};
+
+/// Number of spaces per nesting level.
+const INDENT: usize = 4;
+
+/// Arrays longer than this are wrapped.
+const MAX_WIDTH: usize = 80;
+
+/// Format scene file contents.
+///
+/// Only the given text is formatted, included and imported files are not read.
+pub fn format(source: &str) -> Result<String> {
+    let mut parser = Parser::new(source);
+    let mut writer = Writer::default();
+    let mut offset = 0;
+
+    loop {
+        let element = match parser.parse_next() {
+            Ok(element) => element,
+            Err(Error::EndOfFile) => break,
+            Err(err) => return Err(err),
+        };
+
+        let start = parser.directive_span().map_or(offset, |span| span.start);
+        let end = parser.offset();
+
+        writer.gap(&source[offset..start]);
+        writer.element(&element, &source[start..end], start);
+
+        offset = end;
+    }
+
+    writer.gap(&source[offset..]);
+
+    Ok(writer.out)
+}
+
+#[derive(Default)]
+struct Writer {
+    out: String,
+    /// Current nesting level.
+    depth: usize,
+    /// Whether a blank line goes before the next line.
+    blank: bool,
+}
+
+impl Writer {
+    fn line(&mut self, indent: usize, text: &str) {
+        if self.blank && !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        self.blank = false;
+
+        self.out.extend(std::iter::repeat(' ').take(indent));
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    /// Handle comments and blank lines between elements.
+    fn gap(&mut self, text: &str) {
+        let lines = text.split('\n').collect::<Vec<_>>();
+
+        for (index, line) in lines.iter().enumerate() {
+            let comment = line.find('#').map(|pos| line[pos..].trim_end());
+
+            // The first line continues the line of the previous element.
+            if index == 0 && !self.out.is_empty() {
+                if let Some(comment) = comment {
+                    self.out.pop();
+                    self.out.push_str("  ");
+                    self.out.push_str(comment);
+                    self.out.push('\n');
+                }
+                continue;
+            }
+
+            match comment {
+                Some(comment) => self.line(self.depth * INDENT, comment),
+                // The last line is followed by the next element.
+                None if index > 0 && index + 1 < lines.len() && line.trim().is_empty() => {
+                    self.blank = true
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Write a single element, `text` is its source starting at `offset`.
+    fn element(&mut self, element: &Element, text: &str, offset: usize) {
+        if matches!(element, Element::AttributeEnd | Element::ObjectEnd) {
+            self.depth = self.depth.saturating_sub(1);
+        }
+
+        let indent = self.depth * INDENT;
+
+        // Comments within the element are moved before it.
+        for comment in comments(text) {
+            self.line(indent, comment);
+        }
+
+        let tokens = Tokenizer::new(text)
+            .map(|token| (token.span().start, token.value()))
+            .collect::<Vec<_>>();
+
+        let mut params = element
+            .params()
+            .into_iter()
+            .flat_map(|params| params.iter())
+            .chain(match element {
+                Element::Option(param) => Some(param),
+                _ => None,
+            })
+            .map(|param| {
+                let start = param.span().map_or(0, |span| span.start - offset);
+                (start, param)
+            })
+            .collect::<Vec<_>>();
+        params.sort_by_key(|(start, _)| *start);
+
+        let header_end = params.first().map_or(text.len(), |(start, _)| *start);
+        let header = tokens
+            .iter()
+            .filter(|(start, _)| *start < header_end)
+            .map(|(_, value)| *value)
+            .collect::<Vec<_>>();
+
+        // Values of the parameter, without the name token.
+        let param_values = |index: usize| {
+            let start = params[index].0;
+            let end = params
+                .get(index + 1)
+                .map_or(text.len(), |(start, _)| *start);
+
+            let tokens = tokens
+                .iter()
+                .filter(|(offset, _)| *offset > start && *offset < end)
+                .map(|(_, value)| *value)
+                .collect::<Vec<_>>();
+
+            values(&tokens)
+        };
+
+        match element {
+            Element::Transform { .. } | Element::ConcatTransform { .. } => {
+                self.values(indent, header[0], &values(&header[1..]), 4);
+            }
+            // Option has a single parameter, keep it on the same line.
+            Element::Option(param) => {
+                let prefix = format!("Option \"{} {}\"", param.ty, param.name);
+                self.values(indent, &prefix, &param_values(0), group(param.ty));
+            }
+            _ => {
+                self.line(indent, &header.join(" "));
+
+                for (index, (_, param)) in params.iter().enumerate() {
+                    let prefix = format!("\"{} {}\"", param.ty, param.name);
+                    self.values(
+                        indent + INDENT,
+                        &prefix,
+                        &param_values(index),
+                        group(param.ty),
+                    );
+                }
+            }
+        }
+
+        if matches!(
+            element,
+            Element::AttributeBegin | Element::ObjectBegin { .. }
+        ) {
+            self.depth += 1;
+        }
+    }
+
+    /// Write `prefix` followed by values, wrapped by `group` values if too long.
+    fn values(&mut self, indent: usize, prefix: &str, values: &[&str], group: usize) {
+        if let [value] = values {
+            return self.line(indent, &format!("{prefix} {value}"));
+        }
+
+        let line = format!("{prefix} [ {} ]", values.join(" ")).replace("[  ]", "[ ]");
+        if indent + line.len() <= MAX_WIDTH {
+            return self.line(indent, &line);
+        }
+
+        self.line(indent, &format!("{prefix} ["));
+
+        let inner = indent + INDENT;
+        let mut line = String::new();
+        for chunk in values.chunks(group) {
+            let chunk = chunk.join(" ");
+
+            if !line.is_empty() && inner + line.len() + 1 + chunk.len() > MAX_WIDTH {
+                self.line(inner, &line);
+                line.clear();
+            }
+
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&chunk);
+        }
+        self.line(inner, &line);
+
+        self.line(indent, "]");
+    }
+}
+
+/// Values without enclosing brackets.
+fn values<'a>(tokens: &[&'a str]) -> Vec<&'a str> {
+    tokens
+        .iter()
+        .copied()
+        .filter(|token| *token != "[" && *token != "]")
+        .collect()
+}
+
+/// Number of values that belong to a single item and are kept on the same line.
+fn group(ty: ParamType) -> usize {
+    match ty {
+        ParamType::Point2 | ParamType::Vector2 => 2,
+        ParamType::Point3
+        | ParamType::Vector3
+        | ParamType::Normal3
+        | ParamType::Normal
+        | ParamType::Rgb => 3,
+        _ => 1,
+    }
+}
+
+/// Find comments outside of quoted strings.
+fn comments(text: &str) -> Vec<&str> {
+    let mut comments = Vec::new();
+    let mut quoted = false;
+    let mut pos = 0;
+
+    while let Some(ch) = text[pos..].chars().next() {
+        match ch {
+            '"' => quoted = !quoted,
+            // Comments last until the end of the line.
+            '#' if !quoted => {
+                let len = text[pos..].find(['\r', '\n']).unwrap_or(text.len() - pos);
+                comments.push(&text[pos..pos + len]);
+                pos += len;
+                continue;
+            }
+            _ => {}
+        }
+
+        pos += ch.len_utf8();
+    }
+
+    comments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reindent() -> Result<()> {
+        let source = r#"
+# Scene
+LookAt 0 5.5 24   0 11 -10
+       0 1 0 # up
+Camera "perspective" "float fov" [30]
+
+
+WorldBegin
+AttributeBegin
+Material "diffuse"   "rgb reflectance" [.5 .5 .5] "texture bump" "bumps"
+ObjectBegin "x"
+Shape "sphere" # ball
+ObjectEnd
+AttributeEnd
+"#;
+
+        let expected = r#"# Scene
+LookAt 0 5.5 24 0 11 -10 0 1 0  # up
+Camera "perspective"
+    "float fov" 30
+
+WorldBegin
+AttributeBegin
+    Material "diffuse"
+        "rgb reflectance" [ .5 .5 .5 ]
+        "texture bump" "bumps"
+    ObjectBegin "x"
+        Shape "sphere"  # ball
+    ObjectEnd
+AttributeEnd
+"#;
+
+        let formatted = format(source)?;
+        assert_eq!(formatted, expected);
+        assert_eq!(format(&formatted)?, formatted);
+
+        Ok(())
+    }
+
+    #[test]
+    fn wrap_arrays() -> Result<()> {
+        let source = r#"Shape "trianglemesh" "integer indices" [ 0 1 2 ]
+"point3 P" [ 0.000000 0.000000 0.000000 1.000000 0.000000 0.000000
+1.000000 1.000000 0.000000 0.000000 1.000000 0.000000 ]
+# comment "inside" the parameter list
+"string name" "a # b"
+"#;
+
+        let expected = r#"# comment "inside" the parameter list
+Shape "trianglemesh"
+    "integer indices" [ 0 1 2 ]
+    "point3 P" [
+        0.000000 0.000000 0.000000 1.000000 0.000000 0.000000
+        1.000000 1.000000 0.000000 0.000000 1.000000 0.000000
+    ]
+    "string name" "a # b"
+"#;
+
+        let formatted = format(source)?;
+        assert_eq!(formatted, expected);
+        assert_eq!(format(&formatted)?, formatted);
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid() {
+        let err = format("WorldBegin\nShape \"sphere\" \"float radius\" [ 1").unwrap_err();
+        assert!(matches!(err.kind(), Error::NoToken));
+    }
+}
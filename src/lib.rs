@@ -1,15 +1,27 @@
 //! PBRT v4 file format parser and loader.
 
+pub mod color;
+pub mod cst;
 mod error;
+mod file;
+pub mod format;
+#[cfg(feature = "nanovdb")]
+pub mod nanovdb;
 pub mod param;
 mod parser;
+pub mod photometric;
+#[cfg(feature = "ply")]
+pub mod ply;
 mod scene;
 mod token;
 mod tokenizer;
 pub mod types;
+pub mod upgrade;
+mod writer;
 
-pub use error::Error;
+pub use error::{Error, IncludeSite, Location};
 pub use parser::*;
 pub use scene::*;
+pub use token::Span;
 
 pub type Result<T> = std::result::Result<T, Error>;
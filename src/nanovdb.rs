@@ -0,0 +1,755 @@
+//! NanoVDB grid reader.
+//!
+//! Reads float grids (such as "density" and "temperature") from ".nvdb" files referenced
+//! by "nanovdb" media. Grids may be stored uncompressed or zip compressed, blosc
+//! compression is not supported. Files written by NanoVDB 32.3 and later are supported.
+
+use std::{io::Read, path::Path};
+
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::{file, Error, Result, Scene};
+
+/// "NanoVDB0", used by both files and grids before NanoVDB 32.6.
+const MAGIC: u64 = 0x304244566f6e614e;
+/// "NanoVDB1", grid magic number since NanoVDB 32.6.
+const MAGIC_GRID: u64 = 0x314244566f6e614e;
+/// "NanoVDB2", file magic number since NanoVDB 32.6.
+const MAGIC_FILE: u64 = 0x324244566f6e614e;
+
+const FILE_HEADER_SIZE: usize = 16;
+const FILE_META_SIZE: usize = 176;
+
+const CODEC_NONE: u16 = 0;
+const CODEC_ZIP: u16 = 1;
+
+const GRID_TYPE_FLOAT: u32 = 1;
+
+/// Size of `GridData`, the tree follows it.
+const GRID_SIZE: usize = 672;
+/// Size of the root node without its tiles.
+const ROOT_SIZE: usize = 64;
+const ROOT_TILE_SIZE: usize = 32;
+
+/// Node layout of a tree level.
+struct Level {
+    /// log2 of the number of children along each axis.
+    log2_dim: u32,
+    /// log2 of the number of voxels covered along each axis.
+    total: u32,
+    /// Size of a node in bytes.
+    size: usize,
+    /// Offset of the child mask.
+    child_mask: usize,
+    /// Offset of the table of child offsets and tile values.
+    table: usize,
+}
+
+const UPPER: Level = Level {
+    log2_dim: 5,
+    total: 12,
+    size: 270400,
+    child_mask: 32 + 4096,
+    table: 8256,
+};
+
+const LOWER: Level = Level {
+    log2_dim: 4,
+    total: 7,
+    size: 33856,
+    child_mask: 32 + 512,
+    table: 1088,
+};
+
+const LEAF_SIZE: usize = 2144;
+const LEAF_VALUES: usize = 96;
+const LEAF_VOXELS: usize = 512;
+
+/// Float grids read from a NanoVDB file.
+#[derive(Debug, Default, Clone)]
+pub struct NanoVdb {
+    /// Grids of other value types are skipped.
+    pub grids: Vec<Grid>,
+}
+
+impl NanoVdb {
+    /// Read grids from a file.
+    ///
+    /// Files with ".gz" extension are decompressed if the "gzip" feature is enabled.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<NanoVdb> {
+        let path = path.as_ref();
+
+        file::open(path)
+            .and_then(NanoVdb::from_reader)
+            .map_err(|err| err.with_path(path))
+    }
+
+    /// Read grids from a reader.
+    pub fn from_reader(mut reader: impl Read) -> Result<NanoVdb> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        let mut grids = Vec::new();
+        let mut bytes = Bytes(&data);
+
+        // A file consists of one or more segments, each with a header, metadata of
+        // all its grids and then the grid data.
+        while !bytes.0.is_empty() {
+            let magic = bytes.u64(0)?;
+            if magic != MAGIC && magic != MAGIC_FILE {
+                return Err(Error::InvalidNanoVdb("missing magic number"));
+            }
+            check_version(bytes.u32(8)?)?;
+
+            let grid_count = bytes.u16(12)? as usize;
+            let codec = bytes.u16(14)?;
+
+            let mut offset = FILE_HEADER_SIZE;
+            let mut metas = Vec::with_capacity(grid_count.min(1024));
+            for _ in 0..grid_count {
+                let grid_size = bytes.u64(offset)? as usize;
+                let file_size = bytes.u64(offset + 8)? as usize;
+                let grid_type = bytes.u32(offset + 32)?;
+                let name_size = bytes.u32(offset + 136)? as usize;
+
+                metas.push((grid_size, file_size, grid_type));
+                offset = checked_add(offset, FILE_META_SIZE + name_size)?;
+            }
+
+            for (grid_size, file_size, grid_type) in metas {
+                let end = checked_add(offset, file_size)?;
+                let data = bytes.slice(offset, end)?;
+                offset = end;
+
+                if grid_type != GRID_TYPE_FLOAT {
+                    continue;
+                }
+
+                let grid = match codec {
+                    CODEC_NONE => Grid::new(data)?,
+                    CODEC_ZIP => Grid::new(&unzip(data, grid_size)?)?,
+                    _ => return Err(Error::InvalidNanoVdb("unsupported codec")),
+                };
+                grids.push(grid);
+            }
+
+            bytes = Bytes(bytes.slice(offset, bytes.0.len())?);
+        }
+
+        Ok(NanoVdb { grids })
+    }
+
+    /// Find a grid by name.
+    pub fn grid(&self, name: &str) -> Option<&Grid> {
+        self.grids.iter().find(|grid| grid.name == name)
+    }
+}
+
+impl Scene {
+    /// Read the grids of a "nanovdb" medium, `filename` is resolved relative to the working directory.
+    pub fn load_nanovdb(&self, filename: &str) -> Result<NanoVdb> {
+        NanoVdb::from_file(self.resolve_path(filename))
+    }
+}
+
+/// Sparse float grid.
+#[derive(Debug, Clone)]
+pub struct Grid {
+    pub name: String,
+    /// Transformation from index space (voxel coordinates) to world space.
+    pub index_to_world: Mat4,
+    pub world_to_index: Mat4,
+    /// World space bounds of the active voxels.
+    pub world_bounds: [[f32; 3]; 2],
+    /// Index space bounds of the active voxels, inclusive.
+    pub index_bounds: [[i32; 3]; 2],
+    /// Value of voxels outside of the tree.
+    pub background: f32,
+    /// Minimum of the active values.
+    pub minimum: f32,
+    /// Maximum of the active values, a majorant of the grid.
+    pub maximum: f32,
+    root: Vec<(u64, Slot)>,
+    upper: Vec<Node>,
+    lower: Vec<Node>,
+    /// Voxel values of all leaves, 512 per leaf.
+    leaves: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Slot {
+    Value(f32),
+    /// Index of the child node in the next level.
+    Child(usize),
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    table: Vec<Slot>,
+}
+
+impl Grid {
+    fn new(data: &[u8]) -> Result<Grid> {
+        let bytes = Bytes(data);
+
+        let magic = bytes.u64(0)?;
+        if magic != MAGIC && magic != MAGIC_GRID {
+            return Err(Error::InvalidNanoVdb("missing grid magic number"));
+        }
+        check_version(bytes.u32(16)?)?;
+
+        if bytes.u32(636)? != GRID_TYPE_FLOAT {
+            return Err(Error::InvalidNanoVdb("grid is not a float grid"));
+        }
+
+        let name = bytes.slice(40, 40 + 256)?;
+        let len = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+        let name = String::from_utf8_lossy(&name[..len]).into_owned();
+
+        // Double precision map, the matrices are row major.
+        let map = 296;
+        let matrix = |offset: usize| -> Result<[f32; 9]> {
+            let mut m = [0.0; 9];
+            for (i, value) in m.iter_mut().enumerate() {
+                *value = bytes.f64(offset + i * 8)? as f32;
+            }
+            Ok(m)
+        };
+        let mat = matrix(map + 88)?;
+        let inv_mat = matrix(map + 160)?;
+        let vec = Vec3::new(
+            bytes.f64(map + 232)? as f32,
+            bytes.f64(map + 240)? as f32,
+            bytes.f64(map + 248)? as f32,
+        );
+
+        let affine = |m: [f32; 9], translation: Vec3| {
+            Mat4::from_cols(
+                Vec4::new(m[0], m[3], m[6], 0.0),
+                Vec4::new(m[1], m[4], m[7], 0.0),
+                Vec4::new(m[2], m[5], m[8], 0.0),
+                translation.extend(1.0),
+            )
+        };
+        let index_to_world = affine(mat, vec);
+        let mut world_to_index = affine(inv_mat, Vec3::ZERO);
+        world_to_index.w_axis = (-world_to_index.transform_vector3(vec)).extend(1.0);
+
+        let mut world_bounds = [[0.0; 3]; 2];
+        for (i, value) in world_bounds.iter_mut().flatten().enumerate() {
+            *value = bytes.f64(560 + i * 8)? as f32;
+        }
+
+        // Byte offsets of the first node of each level, relative to the tree.
+        let tree = GRID_SIZE;
+        let level_offset = |level: usize| -> Result<usize> {
+            checked_add(tree, bytes.u64(tree + level * 8)? as usize)
+        };
+        let node_count =
+            |level: usize| -> Result<usize> { Ok(bytes.u32(tree + 32 + level * 4)? as usize) };
+
+        let leaf_start = level_offset(0)?;
+        let leaf_count = node_count(0)?;
+        let lower_start = level_offset(1)?;
+        let lower_count = node_count(1)?;
+        let upper_start = level_offset(2)?;
+        let upper_count = node_count(2)?;
+        let root = level_offset(3)?;
+
+        // Offsets within nodes are only used on node sized slices, so they can't overflow.
+        let root_node = bytes.node(root, ROOT_SIZE)?;
+
+        let mut index_bounds = [[0; 3]; 2];
+        for (i, value) in index_bounds.iter_mut().flatten().enumerate() {
+            *value = root_node.i32(i * 4)?;
+        }
+
+        let table_size = root_node.u32(24)? as usize;
+        let background = root_node.f32(28)?;
+        let minimum = root_node.f32(32)?;
+        let maximum = root_node.f32(36)?;
+
+        let tiles_size = table_size
+            .checked_mul(ROOT_TILE_SIZE)
+            .ok_or(Error::InvalidNanoVdb("unexpected end of file"))?;
+        let table = bytes.node(checked_add(root, ROOT_SIZE)?, tiles_size)?;
+
+        let mut tiles = Vec::with_capacity(table_size);
+        for i in 0..table_size {
+            let tile = Bytes(table.slice(i * ROOT_TILE_SIZE, (i + 1) * ROOT_TILE_SIZE)?);
+            let key = tile.u64(0)?;
+            let child = tile.i64(8)?;
+
+            let slot = match child {
+                0 => Slot::Value(tile.f32(20)?),
+                child => Slot::Child(child_index(
+                    root,
+                    child,
+                    upper_start,
+                    upper_count,
+                    UPPER.size,
+                )?),
+            };
+            tiles.push((key, slot));
+        }
+
+        let read_nodes =
+            |level: &Level, start: usize, count: usize, child: (usize, usize, usize)| {
+                (0..count)
+                    .map(|index| {
+                        let node = checked_add(start, index.saturating_mul(level.size))?;
+                        read_node(&bytes, level, node, child)
+                    })
+                    .collect::<Result<Vec<_>>>()
+            };
+
+        let upper = read_nodes(
+            &UPPER,
+            upper_start,
+            upper_count,
+            (lower_start, lower_count, LOWER.size),
+        )?;
+        let lower = read_nodes(
+            &LOWER,
+            lower_start,
+            lower_count,
+            (leaf_start, leaf_count, LEAF_SIZE),
+        )?;
+
+        // Leaves that fit into the data, the rest would fail to read anyway.
+        let leaf_capacity = leaf_count.min(data.len() / LEAF_SIZE);
+
+        let mut leaves = Vec::with_capacity(leaf_capacity * LEAF_VOXELS);
+        for index in 0..leaf_count {
+            let leaf = checked_add(leaf_start, index.saturating_mul(LEAF_SIZE))?;
+            let leaf = bytes.node(leaf, LEAF_SIZE)?;
+
+            for voxel in 0..LEAF_VOXELS {
+                leaves.push(leaf.f32(LEAF_VALUES + voxel * 4)?);
+            }
+        }
+
+        Ok(Grid {
+            name,
+            index_to_world,
+            world_to_index,
+            world_bounds,
+            index_bounds,
+            background,
+            minimum,
+            maximum,
+            root: tiles,
+            upper,
+            lower,
+            leaves,
+        })
+    }
+
+    /// Value of the voxel at index space coordinates.
+    pub fn value(&self, [x, y, z]: [i32; 3]) -> f32 {
+        // Root keys pack the coordinates of the upper node.
+        let key = (z as u32 >> UPPER.total) as u64
+            | ((y as u32 >> UPPER.total) as u64) << 21
+            | ((x as u32 >> UPPER.total) as u64) << 42;
+
+        let slot = self
+            .root
+            .iter()
+            .find(|(tile, _)| *tile == key)
+            .map(|(_, slot)| *slot);
+
+        let upper = match slot {
+            Some(Slot::Child(index)) => &self.upper[index],
+            Some(Slot::Value(value)) => return value,
+            None => return self.background,
+        };
+
+        let lower = match upper.table[offset(&UPPER, [x, y, z])] {
+            Slot::Child(index) => &self.lower[index],
+            Slot::Value(value) => return value,
+        };
+
+        let leaf = match lower.table[offset(&LOWER, [x, y, z])] {
+            Slot::Child(index) => index,
+            Slot::Value(value) => return value,
+        };
+
+        let voxel = ((x & 7) << 6 | (y & 7) << 3 | (z & 7)) as usize;
+        self.leaves[leaf * LEAF_VOXELS + voxel]
+    }
+
+    /// Trilinearly interpolated value at a world space point.
+    ///
+    /// Voxel centers are located at integer index coordinates, matching pbrt's sampling.
+    pub fn density(&self, p: [f32; 3]) -> f32 {
+        let p = self.world_to_index.transform_point3(Vec3::from(p));
+        let base = p.floor();
+        let t = p - base;
+
+        let [x, y, z] = base.to_array().map(|value| value as i32);
+        let value = |dx: i32, dy: i32, dz: i32| {
+            self.value([
+                x.saturating_add(dx),
+                y.saturating_add(dy),
+                z.saturating_add(dz),
+            ])
+        };
+
+        let lerp = |t: f32, a: f32, b: f32| a + (b - a) * t;
+        let lerp_y = |dz| {
+            lerp(
+                t.y,
+                lerp(t.x, value(0, 0, dz), value(1, 0, dz)),
+                lerp(t.x, value(0, 1, dz), value(1, 1, dz)),
+            )
+        };
+
+        lerp(t.z, lerp_y(0), lerp_y(1))
+    }
+}
+
+/// Read an internal node, `child` is the start, count and size of the child level.
+fn read_node(
+    bytes: &Bytes,
+    level: &Level,
+    node: usize,
+    child: (usize, usize, usize),
+) -> Result<Node> {
+    let (child_start, child_count, child_size) = child;
+    let len = 1usize << (3 * level.log2_dim);
+
+    let data = bytes.node(node, level.size)?;
+
+    let mut table = Vec::with_capacity(len);
+    for i in 0..len {
+        let mask = data.u64(level.child_mask + (i >> 6) * 8)?;
+        let slot = level.table + i * 8;
+
+        if mask & (1 << (i & 63)) != 0 {
+            let offset = data.i64(slot)?;
+            let index = child_index(node, offset, child_start, child_count, child_size)?;
+            table.push(Slot::Child(index));
+        } else {
+            table.push(Slot::Value(data.f32(slot)?));
+        }
+    }
+
+    Ok(Node { table })
+}
+
+/// Index of a child node at byte `offset` from its parent at `parent`.
+fn child_index(
+    parent: usize,
+    offset: i64,
+    start: usize,
+    count: usize,
+    size: usize,
+) -> Result<usize> {
+    (parent as i64)
+        .checked_add(offset)
+        .and_then(|position| usize::try_from(position).ok())
+        .and_then(|position| position.checked_sub(start))
+        .filter(|position| position % size == 0 && position / size < count)
+        .map(|position| position / size)
+        .ok_or(Error::InvalidNanoVdb("invalid child node offset"))
+}
+
+/// Index of the child containing the voxel within a node of `level`.
+fn offset(level: &Level, [x, y, z]: [i32; 3]) -> usize {
+    let mask = (1 << level.total) - 1;
+    let child = level.total - level.log2_dim;
+
+    let axis = |value: i32| ((value & mask) >> child) as usize;
+    axis(x) << (2 * level.log2_dim) | axis(y) << level.log2_dim | axis(z)
+}
+
+fn check_version(version: u32) -> Result<()> {
+    let major = version >> 21;
+    let minor = (version >> 10) & 0x7ff;
+
+    if major != 32 || minor < 3 {
+        return Err(Error::InvalidNanoVdb("unsupported version"));
+    }
+
+    Ok(())
+}
+
+fn checked_add(a: usize, b: usize) -> Result<usize> {
+    a.checked_add(b)
+        .ok_or(Error::InvalidNanoVdb("unexpected end of file"))
+}
+
+fn unzip(data: &[u8], size: usize) -> Result<Vec<u8>> {
+    let bytes = Bytes(data);
+    let len = bytes.u64(0)? as usize;
+    let compressed = bytes.slice(8, checked_add(8, len)?)?;
+
+    // Don't trust the size for allocations, and don't decompress more than needed to check it.
+    let mut grid = Vec::with_capacity(size.min(compressed.len()));
+    flate2::read::ZlibDecoder::new(compressed)
+        .take((size as u64).saturating_add(1))
+        .read_to_end(&mut grid)?;
+
+    if grid.len() != size {
+        return Err(Error::InvalidNanoVdb("unexpected size of compressed grid"));
+    }
+
+    Ok(grid)
+}
+
+/// Little endian values at byte offsets.
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Bytes<'a> {
+    fn slice(&self, start: usize, end: usize) -> Result<&'a [u8]> {
+        self.0
+            .get(start..end)
+            .ok_or(Error::InvalidNanoVdb("unexpected end of file"))
+    }
+
+    /// Node of `size` bytes at `offset`.
+    fn node(&self, offset: usize, size: usize) -> Result<Bytes<'a>> {
+        self.slice(offset, checked_add(offset, size)?).map(Bytes)
+    }
+
+    fn array<const N: usize>(&self, offset: usize) -> Result<[u8; N]> {
+        let slice = self.slice(offset, checked_add(offset, N)?)?;
+        let mut array = [0; N];
+        array.copy_from_slice(slice);
+        Ok(array)
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16> {
+        self.array(offset).map(u16::from_le_bytes)
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+        self.array(offset).map(u32::from_le_bytes)
+    }
+
+    fn i32(&self, offset: usize) -> Result<i32> {
+        self.array(offset).map(i32::from_le_bytes)
+    }
+
+    fn u64(&self, offset: usize) -> Result<u64> {
+        self.array(offset).map(u64::from_le_bytes)
+    }
+
+    fn i64(&self, offset: usize) -> Result<i64> {
+        self.array(offset).map(i64::from_le_bytes)
+    }
+
+    fn f32(&self, offset: usize) -> Result<f32> {
+        self.array(offset).map(f32::from_le_bytes)
+    }
+
+    fn f64(&self, offset: usize) -> Result<f64> {
+        self.array(offset).map(f64::from_le_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    const VERSION: u32 = 32 << 21 | 6 << 10;
+
+    fn put<const N: usize>(data: &mut [u8], offset: usize, bytes: [u8; N]) {
+        data[offset..offset + N].copy_from_slice(&bytes);
+    }
+
+    /// Grid with a single leaf at the origin, the voxel values are equal to x.
+    fn grid() -> Vec<u8> {
+        let tree = GRID_SIZE;
+        let root = tree + 64;
+        let upper = root + ROOT_SIZE + ROOT_TILE_SIZE;
+        let lower = upper + UPPER.size;
+        let leaf = lower + LOWER.size;
+
+        let mut data = vec![0; leaf + LEAF_SIZE];
+
+        put(&mut data, 0, MAGIC_GRID.to_le_bytes());
+        put(&mut data, 16, VERSION.to_le_bytes());
+        put(&mut data, 40, *b"density\0");
+        // Voxels are 2 units wide, the grid is moved by 1 along x.
+        for i in 0..3 {
+            put(&mut data, 296 + 88 + i * 32, 2.0f64.to_le_bytes());
+            put(&mut data, 296 + 160 + i * 32, 0.5f64.to_le_bytes());
+        }
+        put(&mut data, 296 + 232, 1.0f64.to_le_bytes());
+        put(&mut data, 560 + 24, 17.0f64.to_le_bytes());
+        put(&mut data, 636, GRID_TYPE_FLOAT.to_le_bytes());
+
+        for (level, node) in [leaf, lower, upper, root].into_iter().enumerate() {
+            put(
+                &mut data,
+                tree + level * 8,
+                ((node - tree) as u64).to_le_bytes(),
+            );
+        }
+        for level in 0..3 {
+            put(&mut data, tree + 32 + level * 4, 1u32.to_le_bytes());
+        }
+
+        for axis in 0..3 {
+            put(&mut data, root + 12 + axis * 4, 7i32.to_le_bytes());
+        }
+        put(&mut data, root + 24, 1u32.to_le_bytes());
+        put(&mut data, root + 36, 7.0f32.to_le_bytes());
+        put(
+            &mut data,
+            root + ROOT_SIZE + 8,
+            ((upper - root) as i64).to_le_bytes(),
+        );
+
+        put(&mut data, upper + UPPER.child_mask, 1u64.to_le_bytes());
+        put(
+            &mut data,
+            upper + UPPER.table,
+            ((lower - upper) as i64).to_le_bytes(),
+        );
+        // Constant tile next to the lower node.
+        put(&mut data, upper + UPPER.table + 8, 5.0f32.to_le_bytes());
+
+        put(&mut data, lower + LOWER.child_mask, 1u64.to_le_bytes());
+        put(
+            &mut data,
+            lower + LOWER.table,
+            ((leaf - lower) as i64).to_le_bytes(),
+        );
+
+        for voxel in 0..LEAF_VOXELS {
+            let x = (voxel >> 6) as f32;
+            put(&mut data, leaf + LEAF_VALUES + voxel * 4, x.to_le_bytes());
+        }
+
+        data
+    }
+
+    fn file(codec: u16, grid: &[u8], stored: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(MAGIC_FILE.to_le_bytes());
+        data.extend(VERSION.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend(codec.to_le_bytes());
+
+        let mut meta = [0; FILE_META_SIZE];
+        put(&mut meta, 0, (grid.len() as u64).to_le_bytes());
+        put(&mut meta, 8, (stored.len() as u64).to_le_bytes());
+        put(&mut meta, 32, GRID_TYPE_FLOAT.to_le_bytes());
+        put(&mut meta, 136, 8u32.to_le_bytes());
+        data.extend(meta);
+        data.extend(b"density\0");
+
+        data.extend(stored);
+        data
+    }
+
+    #[test]
+    fn read_grid() -> Result<()> {
+        let grid = grid();
+        let vdb = NanoVdb::from_reader(file(CODEC_NONE, &grid, &grid).as_slice())?;
+
+        let grid = vdb.grid("density").unwrap();
+        assert_eq!(grid.index_bounds, [[0, 0, 0], [7, 7, 7]]);
+        assert_eq!(grid.world_bounds, [[0.0; 3], [17.0, 0.0, 0.0]]);
+        assert_eq!(grid.maximum, 7.0);
+        assert_eq!(
+            grid.index_to_world
+                .transform_point3(Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(3.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            grid.world_to_index
+                .transform_point3(Vec3::new(3.0, 0.0, 0.0)),
+            Vec3::new(1.0, 0.0, 0.0)
+        );
+
+        assert_eq!(grid.value([3, 1, 2]), 3.0);
+        assert_eq!(grid.value([0, 0, 200]), 5.0);
+        assert_eq!(grid.value([5000, 0, 0]), 0.0);
+        assert_eq!(grid.value([-1, 0, 0]), 0.0);
+
+        // Index (2.75, 1.5, 1.5) is three quarters between voxels 2 and 3.
+        assert_eq!(grid.density([6.5, 3.0, 3.0]), 2.75);
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_zip() -> Result<()> {
+        let grid = grid();
+
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&grid)?;
+        let compressed = encoder.finish()?;
+
+        let mut stored = (compressed.len() as u64).to_le_bytes().to_vec();
+        stored.extend(compressed);
+
+        let vdb = NanoVdb::from_reader(file(CODEC_ZIP, &grid, &stored).as_slice())?;
+        assert_eq!(vdb.grids.len(), 1);
+        assert_eq!(vdb.grids[0].value([7, 0, 0]), 7.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid() {
+        let grid = grid();
+
+        let err = NanoVdb::from_reader(file(2, &grid, &grid).as_slice()).unwrap_err();
+        assert!(matches!(err, Error::InvalidNanoVdb("unsupported codec")));
+
+        let data = file(CODEC_NONE, &grid, &grid);
+        let err = NanoVdb::from_reader(&data[..data.len() - 1]).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidNanoVdb("unexpected end of file")
+        ));
+
+        // Child offset pointing outside of the lower nodes.
+        let mut broken = grid.clone();
+        let upper = GRID_SIZE + 64 + ROOT_SIZE + ROOT_TILE_SIZE;
+        put(&mut broken, upper + UPPER.table, 8i64.to_le_bytes());
+        let err = NanoVdb::from_reader(file(CODEC_NONE, &broken, &broken).as_slice()).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidNanoVdb("invalid child node offset")
+        ));
+
+        // Node offsets close to the end of the address space.
+        for upper in [u64::MAX - 1000, u64::MAX - 1000 - GRID_SIZE as u64] {
+            let mut broken = grid.clone();
+            put(&mut broken, GRID_SIZE + 16, upper.to_le_bytes());
+            put(&mut broken, GRID_SIZE + 64 + 24, 0u32.to_le_bytes());
+            let err =
+                NanoVdb::from_reader(file(CODEC_NONE, &broken, &broken).as_slice()).unwrap_err();
+            assert!(matches!(
+                err,
+                Error::InvalidNanoVdb("unexpected end of file")
+            ));
+        }
+
+        // Compressed data larger than the grid size.
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&grid).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut stored = (compressed.len() as u64).to_le_bytes().to_vec();
+        stored.extend(compressed);
+        let err =
+            NanoVdb::from_reader(file(CODEC_ZIP, &grid[..16], &stored).as_slice()).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidNanoVdb("unexpected size of compressed grid")
+        ));
+
+        let err = NanoVdb::from_reader(&b"VDB"[..]).unwrap_err();
+        assert!(matches!(err, Error::InvalidNanoVdb(_)));
+    }
+}
@@ -1,14 +1,8 @@
 //! Parameter management.
 
-use std::{
-    collections::HashMap,
-    num::{ParseFloatError, ParseIntError},
-    path::Iter,
-    result,
-    str::{FromStr, ParseBoolError},
-};
+use std::{collections::HashMap, fmt, io, result, str::FromStr};
 
-use crate::{Error, Result};
+use crate::{color::ColorSpace, token::Span, Error, Result, Version};
 
 /// Parameter type.
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -55,16 +49,64 @@ impl FromStr for ParamType {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+impl ParamType {
+    /// Parse a type name, including pbrt-v3 synonyms.
+    pub(crate) fn from_v3(s: &str) -> Result<Self> {
+        match s {
+            "color" => Ok(ParamType::Rgb),
+            "point" => Ok(ParamType::Point3),
+            "vector" => Ok(ParamType::Vector3),
+            _ => ParamType::from_str(s),
+        }
+    }
+
+    /// Returns the type name as used in scene files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ParamType::Boolean => "bool",
+            ParamType::Integer => "integer",
+            ParamType::Float => "float",
+            ParamType::Point2 => "point2",
+            ParamType::Vector2 => "vector2",
+            ParamType::Point3 => "point3",
+            ParamType::Vector3 => "vector3",
+            ParamType::Normal3 => "normal3",
+            ParamType::Spectrum => "spectrum",
+            ParamType::Rgb => "rgb",
+            ParamType::Blackbody => "blackbody",
+            ParamType::String => "string",
+            ParamType::Texture => "texture",
+            ParamType::Normal => "normal",
+        }
+    }
+}
+
+impl fmt::Display for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Spectrum {
     //  "rgb L" [ r g b ]
-    Rgb([f32; 3]),
+    /// RGB value along with the color space that was active when it was declared.
+    Rgb([f32; 3], ColorSpace),
     // "blackbody L" 3000
     Blackbody(i32),
+    // "float eta" 1.5
+    /// Same value at all wavelengths.
+    Constant(f32),
+    // "spectrum eta" "metal-Cu-eta"
+    /// Named spectrum (such as "metal-Cu-eta") or a path to a file with wavelength-value pairs.
+    Named(String),
+    // "spectrum L" [ 300 .3  400 .6  500 .8 ]
+    /// Piecewise-linear spectrum given as (wavelength, value) pairs.
+    Sampled(Vec<(f32, f32)>),
 }
 
 /// Represents a single parsed parameter.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub struct Param<'a> {
     /// Parameter name.
     pub name: &'a str,
@@ -72,19 +114,72 @@ pub struct Param<'a> {
     pub ty: ParamType,
     /// One or more values.
     pub value: &'a str,
+    /// Position of the parameter within the source file.
+    span: Option<Span>,
+}
+
+/// Parameters are compared by value, the position is not taken into account.
+impl<'a> PartialEq for Param<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.ty == other.ty && self.value == other.value
+    }
 }
 
 impl<'a> Param<'a> {
     pub fn new(type_and_name: &'a str, value: &'a str) -> Result<Self> {
+        Self::with_version(type_and_name, value, Version::V4)
+    }
+
+    /// Create a parameter, type names are parsed according to the file format `version`.
+    pub(crate) fn with_version(
+        type_and_name: &'a str,
+        value: &'a str,
+        version: Version,
+    ) -> Result<Self> {
         // Param name is "type name"
         let mut split = type_and_name.split_whitespace();
 
         let ty_name = split.next().ok_or(Error::InvalidParamName)?;
-        let ty = ParamType::from_str(ty_name)?;
+        let ty = match version {
+            Version::V3 => ParamType::from_v3(ty_name)?,
+            Version::V4 => ParamType::from_str(ty_name)?,
+        };
 
         let name = split.next().ok_or(Error::InvalidParamName)?;
 
-        Ok(Self { name, ty, value })
+        // pbrt-v3 allows quoted booleans: "bool twosided" "true"
+        let value = match (version, ty) {
+            (Version::V3, ParamType::Boolean) => value.trim_matches('"'),
+            _ => value,
+        };
+
+        Ok(Self {
+            name,
+            ty,
+            value,
+            span: None,
+        })
+    }
+
+    /// Set parameter's position within the source file.
+    pub(crate) fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    /// Returns parameter's position within the source file, if known.
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    /// Annotate an error with the parameter name and position.
+    pub(crate) fn error(&self, err: impl Into<Error>) -> Error {
+        let err = err.into().with_param(self.name);
+
+        match self.span {
+            Some(span) => err.at(span),
+            None => err,
+        }
     }
 
     pub fn items<T: FromStr>(
@@ -96,83 +191,150 @@ impl<'a> Param<'a> {
     pub fn rgb(&self) -> Result<[f32; 3]> {
         let mut iter = self.items::<f32>();
 
-        let r = iter.next().ok_or(Error::MissingRequiredParameter)??;
-        let g = iter.next().ok_or(Error::MissingRequiredParameter)??;
-        let b = iter.next().ok_or(Error::MissingRequiredParameter)??;
+        let mut next = || match iter.next() {
+            Some(value) => value.map_err(|err| self.error(err)),
+            None => Err(self.error(Error::MissingRequiredParameter)),
+        };
 
-        Ok([r, g, b])
+        Ok([next()?, next()?, next()?])
     }
 
     pub fn single<T: FromStr>(&self) -> result::Result<T, <T as FromStr>::Err> {
         T::from_str(self.value)
     }
 
+    /// Returns string values without quotes.
+    pub fn strings(&self) -> impl Iterator<Item = &'a str> {
+        self.value.split('"').skip(1).step_by(2)
+    }
+
     pub fn vec<T: FromStr>(&self) -> result::Result<Vec<T>, <T as FromStr>::Err> {
         self.items()
             .collect::<result::Result<Vec<T>, <T as FromStr>::Err>>()
     }
 
-    pub fn spectrum(&self) -> Result<Spectrum> {
+    /// Parse spectrum value, RGB values are assumed to be in `color_space`.
+    pub fn spectrum(&self, color_space: ColorSpace) -> Result<Spectrum> {
         let res = match self.ty {
-            ParamType::Rgb => Spectrum::Rgb(self.rgb()?),
-            ParamType::Blackbody => Spectrum::Blackbody(self.single()?),
-            _ => return Err(Error::InvalidObjectType),
+            ParamType::Rgb => Spectrum::Rgb(self.rgb()?, color_space),
+            ParamType::Blackbody => {
+                Spectrum::Blackbody(self.single().map_err(|err| self.error(err))?)
+            }
+            ParamType::Float => Spectrum::Constant(self.single().map_err(|err| self.error(err))?),
+            ParamType::Spectrum => match self.strings().next() {
+                Some(name) => Spectrum::Named(name.to_string()),
+                None => {
+                    let values = self.vec::<f32>().map_err(|err| self.error(err))?;
+                    if values.is_empty() || values.len() % 2 != 0 {
+                        return Err(self.error(Error::ParseSlice));
+                    }
+
+                    let pairs = values.chunks_exact(2).map(|p| (p[0], p[1])).collect();
+                    Spectrum::Sampled(pairs)
+                }
+            },
+            _ => return Err(self.error(Error::InvalidObjectType)),
         };
 
         Ok(res)
     }
 }
 
+/// Writes the parameter as `"type name" value`, multiple values are enclosed in brackets.
+///
+/// Fails for infinite and NaN numbers, pbrt can't read them back.
+impl fmt::Display for Param<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let numeric = !matches!(
+            self.ty,
+            ParamType::Boolean | ParamType::String | ParamType::Texture
+        );
+        if numeric
+            && self
+                .items::<f32>()
+                .flatten()
+                .any(|value| !value.is_finite())
+        {
+            return Err(fmt::Error);
+        }
+
+        write!(f, "\"{} {}\" ", self.ty, self.name)?;
+
+        if self.value.contains(char::is_whitespace) || self.value.is_empty() {
+            write!(f, "[ {} ]", self.value)
+        } else {
+            f.write_str(self.value)
+        }
+    }
+}
+
 /// Parameters collection.
-#[derive(Default, Debug, PartialEq, Clone)]
-pub struct ParamList<'a>(HashMap<&'a str, Param<'a>>);
+#[derive(Default, Debug, Clone)]
+pub struct ParamList<'a> {
+    params: HashMap<&'a str, Param<'a>>,
+    /// Parameter names in the order they were added.
+    order: Vec<&'a str>,
+    /// Color space that was active when the parameters were declared.
+    color_space: ColorSpace,
+}
 
 impl<'a> ParamList<'a> {
     /// Add a new parameter to the list.
     pub fn add(&mut self, param: Param<'a>) -> Result<()> {
-        if self.0.insert(param.name, param).is_some() {
+        let name = param.name;
+        if self.params.insert(name, param).is_some() {
             return Err(Error::DuplicatedParamName);
         }
 
+        self.order.push(name);
         Ok(())
     }
 
     /// Get parameter by name.
     pub fn get(&self, name: &str) -> Option<&Param<'a>> {
-        self.0.get(name)
+        self.params.get(name)
+    }
+
+    /// Iterate over parameters in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = &Param<'a>> {
+        self.order.iter().map(|name| &self.params[name])
     }
 
     /// Return the number of parameters.
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.params.len()
     }
 
     /// Returns `true` when the list is empty.
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.params.is_empty()
     }
 
-    fn vec<T: FromStr>(&self, name: &str) -> result::Result<Option<Vec<T>>, <T as FromStr>::Err> {
-        let res = match self.get(name).map(|param| param.vec()) {
-            Some(v) => Some(v?),
-            None => None,
-        };
-
-        Ok(res)
+    fn vec<T: FromStr>(&self, name: &str) -> Result<Option<Vec<T>>>
+    where
+        Error: From<<T as FromStr>::Err>,
+    {
+        self.get(name)
+            .map(|param| param.vec().map_err(|err| param.error(err)))
+            .transpose()
     }
 
-    pub fn floats(&self, name: &str) -> result::Result<Option<Vec<f32>>, ParseFloatError> {
+    pub fn floats(&self, name: &str) -> Result<Option<Vec<f32>>> {
         self.vec(name)
     }
 
-    pub fn integers(&self, name: &str) -> result::Result<Option<Vec<i32>>, ParseIntError> {
+    pub fn integers(&self, name: &str) -> Result<Option<Vec<i32>>> {
         self.vec(name)
     }
 
-    fn single<T: FromStr>(&self, name: &str, default: T) -> result::Result<T, <T as FromStr>::Err> {
-        self.get(name)
-            .map(|p| p.single::<T>())
-            .unwrap_or(Ok(default))
+    fn single<T: FromStr>(&self, name: &str, default: T) -> Result<T>
+    where
+        Error: From<<T as FromStr>::Err>,
+    {
+        match self.get(name) {
+            Some(param) => param.single::<T>().map_err(|err| param.error(err)),
+            None => Ok(default),
+        }
     }
 
     /// Get a float value by name.
@@ -182,31 +344,131 @@ impl<'a> ParamList<'a> {
     ///
     /// If there is a value and it's not possible to parse it into float,
     /// an error will be returned.
-    pub fn float(&self, name: &str, default: f32) -> result::Result<f32, ParseFloatError> {
+    pub fn float(&self, name: &str, default: f32) -> Result<f32> {
         self.single(name, default)
     }
 
-    pub fn integer(&self, name: &str, default: i32) -> result::Result<i32, ParseIntError> {
+    pub fn integer(&self, name: &str, default: i32) -> Result<i32> {
         self.single(name, default)
     }
 
-    pub fn boolean(&self, name: &str, default: bool) -> result::Result<bool, ParseBoolError> {
+    pub fn boolean(&self, name: &str, default: bool) -> Result<bool> {
         self.single(name, default)
     }
 
-    pub fn string(&self, name: &str) -> Option<&str> {
-        self.get(name).map(|v| v.value)
+    /// Get a string value by name, without quotes.
+    pub fn string(&self, name: &str) -> Option<&'a str> {
+        self.get(name).and_then(|param| param.strings().next())
+    }
+
+    /// Get all string values by name, without quotes.
+    pub fn strings(&self, name: &str) -> Option<Vec<&'a str>> {
+        self.get(name).map(|param| param.strings().collect())
     }
 
     pub fn extend(&mut self, other: &ParamList<'a>) {
-        for (k, v) in &other.0 {
-            self.0.insert(k, v.clone());
+        for param in other.iter() {
+            if self.params.insert(param.name, param.clone()).is_none() {
+                self.order.push(param.name);
+            }
         }
     }
 
     pub fn get_data(&self) -> &HashMap<&'a str, Param<'a>> {
-        &self.0
+        &self.params
+    }
+
+    /// Returns the color space RGB values are specified in.
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    /// Set the color space RGB values are specified in.
+    pub fn set_color_space(&mut self, color_space: ColorSpace) {
+        self.color_space = color_space;
     }
+
+    /// Write parameters in scene file syntax.
+    pub fn write_to<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_display(writer, self)
+    }
+
+    /// Get a spectrum value by name.
+    pub fn spectrum(&self, name: &str) -> Result<Option<Spectrum>> {
+        self.get(name)
+            .map(|param| param.spectrum(self.color_space))
+            .transpose()
+    }
+}
+
+/// Lists are equal when they have the same parameters, regardless of the order.
+impl<'a> PartialEq for ParamList<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.params == other.params && self.color_space == other.color_space
+    }
+}
+
+/// Writes each parameter on a separate indented line, in the order they were added.
+///
+/// Nothing is written for an empty list, so it can directly follow a directive.
+impl fmt::Display for ParamList<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for param in self.iter() {
+            write!(f, "\n    {param}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats a float, infinite and NaN values are rejected with an error.
+pub(crate) struct Float(pub f32);
+
+impl fmt::Display for Float {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.0.is_finite() {
+            return Err(fmt::Error);
+        }
+
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Write `value` in scene file syntax.
+///
+/// Formatting only fails on values pbrt can't represent, such errors are
+/// reported as `InvalidData`.
+pub(crate) fn write_display<W: io::Write>(
+    writer: &mut W,
+    value: &impl fmt::Display,
+) -> io::Result<()> {
+    struct Adapter<'w, W> {
+        writer: &'w mut W,
+        error: Option<io::Error>,
+    }
+
+    impl<W: io::Write> fmt::Write for Adapter<'_, W> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.writer.write_all(s.as_bytes()).map_err(|err| {
+                self.error = Some(err);
+                fmt::Error
+            })
+        }
+    }
+
+    let mut adapter = Adapter {
+        writer,
+        error: None,
+    };
+
+    fmt::write(&mut adapter, format_args!("{value}")).map_err(|_| {
+        adapter.error.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "infinite and NaN values can't be written",
+            )
+        })
+    })
 }
 
 #[cfg(test)]
@@ -250,7 +512,7 @@ mod tests {
     fn parse_blackbody() -> Result<()> {
         let param = Param::new("blackbody I", "5500")?;
 
-        let i = param.spectrum().unwrap();
+        let i = param.spectrum(ColorSpace::Srgb).unwrap();
 
         assert!(matches!(i, Spectrum::Blackbody(5500)));
         Ok(())
@@ -259,9 +521,77 @@ mod tests {
     #[test]
     fn parse_rgb() -> Result<()> {
         let param = Param::new("rgb L", "7 0 7")?;
-        let i = param.spectrum().unwrap();
+        let i = param.spectrum(ColorSpace::Rec2020).unwrap();
+
+        assert!(matches!(i, Spectrum::Rgb(_, ColorSpace::Rec2020)));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_spectrum() -> Result<()> {
+        let param = Param::new("spectrum eta", "\"metal-Cu-eta\"")?;
+        let eta = param.spectrum(ColorSpace::Srgb)?;
+        assert_eq!(eta, Spectrum::Named("metal-Cu-eta".to_string()));
+
+        let param = Param::new("spectrum L", "300 .3 400 .6")?;
+        let l = param.spectrum(ColorSpace::Srgb)?;
+        assert_eq!(l, Spectrum::Sampled(vec![(300.0, 0.3), (400.0, 0.6)]));
+
+        let param = Param::new("spectrum L", "300 .3 400")?;
+        assert!(param.spectrum(ColorSpace::Srgb).is_err());
+
+        let param = Param::new("float eta", "1.5")?;
+        let eta = param.spectrum(ColorSpace::Srgb)?;
+        assert_eq!(eta, Spectrum::Constant(1.5));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_strings() -> Result<()> {
+        let mut list = ParamList::default();
+        list.add(Param::new("string filename", "\"foo.exr\"")?)?;
+        list.add(Param::new("string materials", "\"a\" \"b\"")?)?;
+
+        assert_eq!(list.string("filename"), Some("foo.exr"));
+        assert_eq!(list.strings("materials"), Some(vec!["a", "b"]));
+        assert_eq!(list.string("missing"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_params() -> Result<()> {
+        let mut list = ParamList::default();
+        list.add(Param::new("string filename", "\"foo.exr\"")?)?;
+        list.add(Param::new("float fov", "45")?)?;
+        list.add(Param::new("rgb L", "1 0.5 0")?)?;
+
+        assert_eq!(
+            list.to_string(),
+            concat!(
+                "\n    \"string filename\" \"foo.exr\"",
+                "\n    \"float fov\" 45",
+                "\n    \"rgb L\" [ 1 0.5 0 ]",
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_non_finite() -> Result<()> {
+        let mut list = ParamList::default();
+        list.add(Param::new("string name", "\"inf\"")?)?;
+        list.add(Param::new("float fov", "inf")?)?;
+
+        let mut output = Vec::new();
+        let err = list.write_to(&mut output).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let param = Param::new("point3 P", "0 NaN 1")?;
+        assert!(fmt::write(&mut String::new(), format_args!("{param}")).is_err());
 
-        assert!(matches!(i, Spectrum::Rgb(_)));
         Ok(())
     }
 }
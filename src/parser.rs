@@ -1,10 +1,12 @@
 //! Directives parser.
 
+use std::{fmt, io};
+
 use crate::{
-    param::{Param, ParamList},
-    token::{Directive, Token},
+    param::{write_display, Float, Param, ParamList},
+    token::{Directive, Span, Token},
     tokenizer::Tokenizer,
-    Error, Result,
+    Error, Location, Result,
 };
 
 /// Parsed directive.
@@ -80,6 +82,7 @@ pub enum Element<'a> {
         start: f32,
         end: f32,
     },
+    /// `ActiveTransform StartTime|EndTime|All`.
     ActiveTransform {
         ty: &'a str,
     },
@@ -142,16 +145,175 @@ pub enum Element<'a> {
         interior: &'a str,
         exterior: &'a str,
     },
+    /// pbrt-v3 end of the scene description.
+    WorldEnd,
+    /// pbrt-v3 transform block, replaced by attribute blocks in pbrt-v4.
+    TransformBegin,
+    TransformEnd,
+}
+
+impl<'a> Element<'a> {
+    /// Returns the parameter list of the element, if it has one.
+    pub fn params(&self) -> Option<&ParamList<'a>> {
+        match self {
+            Element::Film { params, .. }
+            | Element::Camera { params, .. }
+            | Element::Sampler { params, .. }
+            | Element::Integrator { params, .. }
+            | Element::Accelerator { params, .. }
+            | Element::PixelFilter { params, .. }
+            | Element::Attribute { params, .. }
+            | Element::LightSource { params, .. }
+            | Element::AreaLightSource { params, .. }
+            | Element::Material { params, .. }
+            | Element::MakeNamedMaterial { params, .. }
+            | Element::Texture { params, .. }
+            | Element::Shape { params, .. }
+            | Element::MakeNamedMedium { params, .. } => Some(params),
+            _ => None,
+        }
+    }
+
+    /// Write element in scene file syntax.
+    ///
+    /// Fails with `InvalidData` if the element contains infinite or NaN values.
+    pub fn write_to<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_display(writer, self)
+    }
+}
+
+/// Writes the element as a single directive, parameters go to separate lines.
+impl fmt::Display for Element<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Element::Include(path) => write!(f, "Include \"{path}\""),
+            Element::Import(path) => write!(f, "Import \"{path}\""),
+            Element::Option(param) => write!(f, "Option {param}"),
+            Element::Film { ty, params } => write!(f, "Film \"{ty}\"{params}"),
+            Element::ColorSpace { ty } => write!(f, "ColorSpace \"{ty}\""),
+            Element::Camera { ty, params } => write!(f, "Camera \"{ty}\"{params}"),
+            Element::Sampler { ty, params } => write!(f, "Sampler \"{ty}\"{params}"),
+            Element::Integrator { ty, params } => write!(f, "Integrator \"{ty}\"{params}"),
+            Element::Accelerator { ty, params } => write!(f, "Accelerator \"{ty}\"{params}"),
+            Element::CoordinateSystem { name } => write!(f, "CoordinateSystem \"{name}\""),
+            Element::CoordSysTransform { name } => write!(f, "CoordSysTransform \"{name}\""),
+            Element::PixelFilter { ty, params } => write!(f, "PixelFilter \"{ty}\"{params}"),
+            Element::Identity => f.write_str("Identity"),
+            Element::Translate { v } => write!(f, "Translate {}", Vector(v)),
+            Element::Scale { v } => write!(f, "Scale {}", Vector(v)),
+            Element::Rotate { angle, v } => {
+                write!(f, "Rotate {} {}", Float(*angle), Vector(v))
+            }
+            Element::LookAt { eye, look_at, up } => {
+                f.write_str("LookAt")?;
+                for v in [eye, look_at, up] {
+                    write!(f, " {}", Vector(v))?;
+                }
+                Ok(())
+            }
+            Element::Transform { m } => write!(f, "Transform {}", Matrix(m)),
+            Element::ConcatTransform { m } => write!(f, "ConcatTransform {}", Matrix(m)),
+            Element::TransformTimes { start, end } => {
+                write!(f, "TransformTimes {} {}", Float(*start), Float(*end))
+            }
+            Element::ActiveTransform { ty } => write!(f, "ActiveTransform {ty}"),
+            Element::ReverseOrientation => f.write_str("ReverseOrientation"),
+            Element::WorldBegin => f.write_str("WorldBegin"),
+            Element::AttributeBegin => f.write_str("AttributeBegin"),
+            Element::AttributeEnd => f.write_str("AttributeEnd"),
+            Element::Attribute { target, params } => write!(f, "Attribute \"{target}\"{params}"),
+            Element::LightSource { ty, params } => write!(f, "LightSource \"{ty}\"{params}"),
+            Element::AreaLightSource { ty, params } => {
+                write!(f, "AreaLightSource \"{ty}\"{params}")
+            }
+            Element::Material { ty, params } => write!(f, "Material \"{ty}\"{params}"),
+            Element::MakeNamedMaterial { name, params } => {
+                write!(f, "MakeNamedMaterial \"{name}\"{params}")
+            }
+            Element::NamedMaterial { name } => write!(f, "NamedMaterial \"{name}\""),
+            Element::Texture {
+                name,
+                ty,
+                class,
+                params,
+            } => write!(f, "Texture \"{name}\" \"{ty}\" \"{class}\"{params}"),
+            Element::Shape { name, params } => write!(f, "Shape \"{name}\"{params}"),
+            Element::ObjectBegin { name } => write!(f, "ObjectBegin \"{name}\""),
+            Element::ObjectEnd => f.write_str("ObjectEnd"),
+            Element::ObjectInstance { name } => write!(f, "ObjectInstance \"{name}\""),
+            Element::MakeNamedMedium { name, params } => {
+                write!(f, "MakeNamedMedium \"{name}\"{params}")
+            }
+            Element::MediumInterface { interior, exterior } => {
+                write!(f, "MediumInterface \"{interior}\" \"{exterior}\"")
+            }
+            Element::WorldEnd => f.write_str("WorldEnd"),
+            Element::TransformBegin => f.write_str("TransformBegin"),
+            Element::TransformEnd => f.write_str("TransformEnd"),
+        }
+    }
+}
+
+/// Formats a 4x4 matrix as a bracketed list of 16 values.
+pub(crate) struct Matrix<'a>(pub &'a [f32; 16]);
+
+impl fmt::Display for Matrix<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[")?;
+        for value in self.0 {
+            write!(f, " {}", Float(*value))?;
+        }
+        f.write_str(" ]")
+    }
+}
+
+/// Formats three space separated values.
+struct Vector<'a>(&'a [f32; 3]);
+
+impl fmt::Display for Vector<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [x, y, z] = *self.0;
+        write!(f, "{} {} {}", Float(x), Float(y), Float(z))
+    }
+}
+
+/// Scene file format version.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    /// pbrt-v3 format, accepts `WorldEnd`, `TransformBegin`/`TransformEnd`
+    /// and `color`, `point` and `vector` parameter types.
+    V3,
+    #[default]
+    V4,
 }
 
 pub struct Parser<'a> {
+    str: &'a str,
     tokenizer: Tokenizer<'a>,
+    version: Version,
+    /// Last parsed directive and its position, used to annotate errors.
+    directive: Option<(&'a str, Span)>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(str: &'a str) -> Self {
+        Self::with_version(str, Version::V4)
+    }
+
+    /// Create a parser for the given file format version.
+    pub fn with_version(str: &'a str, version: Version) -> Self {
         let tokenizer = Tokenizer::new(str);
-        Self { tokenizer }
+        Self {
+            str,
+            tokenizer,
+            version,
+            directive: None,
+        }
+    }
+
+    /// Returns the file format version being parsed.
+    pub fn version(&self) -> Version {
+        self.version
     }
 
     /// Parse next element.
@@ -160,8 +322,74 @@ impl<'a> Parser<'a> {
             return Err(Error::EndOfFile);
         };
 
+        self.directive = Some((next_token.value(), next_token.span()));
+
         // Check if token is directive
-        let directive = next_token.directive().ok_or(Error::UnknownDirective)?;
+        let Some(directive) = next_token.directive() else {
+            return Err(self.annotate(Error::UnknownDirective));
+        };
+
+        self.parse_directive(directive)
+            .map_err(|err| self.annotate(err))
+    }
+
+    /// Returns the position of the last parsed directive.
+    pub fn directive_span(&self) -> Option<Span> {
+        self.directive.map(|(_, span)| span)
+    }
+
+    /// Returns the byte offset right after the last parsed element.
+    pub fn offset(&self) -> usize {
+        self.tokenizer.offset()
+    }
+
+    /// Returns the location of the last parsed directive.
+    pub(crate) fn location(&self) -> Option<Location> {
+        let (name, span) = self.directive?;
+
+        Some(Location {
+            span: Some(span),
+            directive: Some(name.to_string()),
+            snippet: Some(self.snippet(span)),
+            ..Location::default()
+        })
+    }
+
+    /// Returns the source line the span starts at.
+    fn snippet(&self, span: Span) -> String {
+        let start = self.str[..span.start.min(self.str.len())]
+            .rfind('\n')
+            .map_or(0, |pos| pos + 1);
+
+        let line = self.str[start..].lines().next().unwrap_or_default();
+        line.trim_end().to_string()
+    }
+
+    /// Fill in missing location details of an error that happened while
+    /// parsing or loading the last directive.
+    ///
+    /// Errors without a position get the position of the directive.
+    pub fn annotate(&self, err: Error) -> Error {
+        if matches!(err, Error::EndOfFile) {
+            return err;
+        }
+
+        let Some(directive) = self.location() else {
+            return err;
+        };
+
+        let mut err = err.with_location(&directive);
+
+        let location = err.location_mut();
+        if let (Some(span), None) = (location.span, &location.snippet) {
+            location.snippet = Some(self.snippet(span));
+        }
+
+        err
+    }
+
+    fn parse_directive(&mut self, directive: Directive) -> Result<Element<'a>> {
+        let v3 = self.version == Version::V3;
 
         let element = match directive {
             Directive::Include => Element::Include(self.read_str()?),
@@ -247,9 +475,13 @@ impl<'a> Parser<'a> {
                 start: self.read_float()?,
                 end: self.read_float()?,
             },
-            Directive::ActiveTransform => Element::ActiveTransform {
-                ty: self.read_str()?,
-            },
+            // pbrt expects `StartTime`, `EndTime` or `All` without quotes.
+            Directive::ActiveTransform => {
+                let token = self.read_token()?;
+                Element::ActiveTransform {
+                    ty: token.unquote().unwrap_or(token.value()),
+                }
+            }
             Directive::ReverseOrientation => Element::ReverseOrientation,
             Directive::WorldBegin => Element::WorldBegin,
             Directive::AttributeBegin => Element::AttributeBegin,
@@ -302,6 +534,12 @@ impl<'a> Parser<'a> {
                 interior: self.read_str()?,
                 exterior: self.read_str()?,
             },
+            Directive::WorldEnd if v3 => Element::WorldEnd,
+            Directive::TransformBegin if v3 => Element::TransformBegin,
+            Directive::TransformEnd if v3 => Element::TransformEnd,
+            Directive::WorldEnd | Directive::TransformBegin | Directive::TransformEnd => {
+                return Err(Error::UnknownDirective)
+            }
         };
 
         Ok(element)
@@ -317,18 +555,18 @@ impl<'a> Parser<'a> {
 
         // Not a brace
         if !is_open && !is_close {
-            return Err(Error::UnexpectedToken);
+            return Err(Error::UnexpectedToken.at(token.span()));
         }
 
         Ok(())
     }
 
-    /// Read next token or return [Error::UnexpectedEnd].
+    /// Read next token or return [Error::NoToken].
     fn read_token(&mut self) -> Result<Token<'a>> {
         match self.tokenizer.next() {
             Some(token) => {
                 if !token.is_valid() {
-                    return Err(Error::InvalidToken);
+                    return Err(Error::InvalidToken.at(token.span()));
                 }
 
                 Ok(token)
@@ -340,7 +578,9 @@ impl<'a> Parser<'a> {
     /// Read token as `f32`.
     fn read_float(&mut self) -> Result<f32> {
         let token = self.read_token()?;
-        let parsed = token.parse::<f32>()?;
+        let parsed = token
+            .parse::<f32>()
+            .map_err(|err| Error::from(err).at(token.span()))?;
         Ok(parsed)
     }
 
@@ -365,7 +605,9 @@ impl<'a> Parser<'a> {
     /// Read a quoted string.
     fn read_str(&mut self) -> Result<&'a str> {
         let token = self.read_token()?;
-        token.unquote().ok_or(Error::InvalidString)
+        token
+            .unquote()
+            .ok_or_else(|| Error::InvalidString.at(token.span()))
     }
 
     /// Parse a single option
@@ -375,41 +617,44 @@ impl<'a> Parser<'a> {
     /// - "float scale" [10]
     /// - "float iso" 150
     fn read_param(&mut self) -> Result<Param<'a>> {
-        let type_and_name = self.read_str()?;
-
-        let mut start = self.tokenizer.offset();
-        let end;
+        let name_token = self.read_token()?;
+        let type_and_name = name_token
+            .unquote()
+            .ok_or_else(|| Error::InvalidString.at(name_token.span()))?;
 
         // Either [ or a single value.
         let value = self.read_token()?;
 
-        if value.is_open_brace() {
-            // Skip brace offset
-            start = self.tokenizer.offset();
+        let span = if value.is_open_brace() {
+            let mut span = value.span();
+            span.start = span.end;
 
             // Read array of values
             loop {
                 let value = self.read_token()?;
 
                 if value.is_close_brace() {
-                    end = self.tokenizer.offset() - 1;
+                    span.end = value.span().start;
                     break;
                 }
 
                 // Got directive without closing bracket token.
                 if value.is_directive() {
-                    return Err(Error::UnexpectedToken);
+                    return Err(Error::UnexpectedToken.at(value.span()));
                 }
             }
+
+            span
         } else {
             // Single value
-            end = start + value.token_size() + 1;
-        }
+            value.span()
+        };
 
-        let token = self.tokenizer.token(start, end);
-        let param = Param::new(type_and_name, token.value())?;
+        let value = self.str[span.start..span.end].trim();
+        let param = Param::with_version(type_and_name, value, self.version)
+            .map_err(|err| err.with_param(type_and_name).at(name_token.span()))?;
 
-        Ok(param)
+        Ok(param.with_span(name_token.span()))
     }
 
     #[inline]
@@ -421,7 +666,10 @@ impl<'a> Parser<'a> {
                 // Each parameter starts with a quoted string
                 Some(token) if token.is_quote() => {
                     let param = self.read_param()?;
-                    list.add(param)?;
+                    let (name, span) = (param.name, token.span());
+
+                    list.add(param)
+                        .map_err(|err| err.with_param(name).at(span))?;
                 }
                 // Other token, break loop
                 Some(_) => break,
@@ -493,6 +741,25 @@ Rotate 1 0 0 1
         assert!(matches!(element, Element::LookAt { .. }));
     }
 
+    #[test]
+    fn parse_active_transform() {
+        let mut parser = Parser::new(
+            "
+ActiveTransform StartTime
+ActiveTransform \"All\"
+        ",
+        );
+
+        assert!(matches!(
+            parser.parse_next().unwrap(),
+            Element::ActiveTransform { ty: "StartTime" }
+        ));
+        assert!(matches!(
+            parser.parse_next().unwrap(),
+            Element::ActiveTransform { ty: "All" }
+        ));
+    }
+
     #[test]
     fn parse_option() {
         let mut parser = Parser::new(
@@ -579,4 +846,121 @@ LookAt 0 5.5 24 0 11 -10 0 1 0
 
         assert!(matches!(next, Element::ConcatTransform { .. }));
     }
+
+    #[test]
+    fn error_location() {
+        let mut parser = Parser::new(
+            "
+Film \"rgb\"
+    \"floaty iso\" 150
+        ",
+        );
+
+        let err = parser.parse_next().unwrap_err();
+        assert!(matches!(err.kind(), Error::InvalidParamType));
+
+        let location = err.location().unwrap();
+        let span = location.span.unwrap();
+
+        assert_eq!((span.line, span.column), (3, 5));
+        assert_eq!(location.directive.as_deref(), Some("Film"));
+        assert_eq!(location.param.as_deref(), Some("floaty iso"));
+        assert_eq!(location.snippet.as_deref(), Some("    \"floaty iso\" 150"));
+    }
+
+    #[test]
+    fn error_unknown_directive() {
+        let mut parser = Parser::new("WorldBegin\n  Foo");
+
+        parser.parse_next().unwrap();
+        let err = parser.parse_next().unwrap_err();
+
+        assert!(matches!(err.kind(), Error::UnknownDirective));
+
+        let location = err.location().unwrap();
+        let span = location.span.unwrap();
+
+        assert_eq!((span.line, span.column), (2, 3));
+        assert_eq!(location.directive.as_deref(), Some("Foo"));
+    }
+
+    #[test]
+    fn write_elements() {
+        let source = "
+Option \"bool disablepixeljitter\" true
+LookAt 0 5.5 24 0 11 -10 0 1 0
+Camera \"perspective\" \"float fov\" [ 30 ]
+Film \"rgb\" \"string filename\" \"out.exr\" \"integer xresolution\" 400
+Transform [ 1 0 0 0 0 1 0 0 0 0 1 0 0 0 0 1 ]
+ActiveTransform EndTime
+WorldBegin
+AttributeBegin
+    Texture \"checks\" \"spectrum\" \"checkerboard\" \"rgb tex1\" [ 1 0 0 ]
+    Shape \"trianglemesh\" \"point3 P\" [ 0 0 0
+        1 0 0 1 1 0 ] \"integer indices\" [ 0 1 2 ]
+    MediumInterface \"\" \"fog\"
+AttributeEnd
+";
+
+        let mut parser = Parser::new(source);
+        let mut output = Vec::new();
+        while let Ok(element) = parser.parse_next() {
+            element.write_to(&mut output).unwrap();
+            output.push(b'\n');
+        }
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Camera \"perspective\"\n    \"float fov\" 30\n"));
+        assert!(
+            output.contains("\"string filename\" \"out.exr\"\n    \"integer xresolution\" 400\n")
+        );
+
+        // Written elements parse back to the same elements.
+        let mut expected = Parser::new(source);
+        let mut parser = Parser::new(&output);
+        loop {
+            match (expected.parse_next(), parser.parse_next()) {
+                (Err(Error::EndOfFile), Err(Error::EndOfFile)) => break,
+                (a, b) => assert_eq!(a.unwrap(), b.unwrap()),
+            }
+        }
+    }
+
+    #[test]
+    fn write_non_finite() {
+        let element = Element::Translate {
+            v: [0.0, f32::INFINITY, 0.0],
+        };
+
+        let err = element.write_to(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let element = Element::Transform { m: [f32::NAN; 16] };
+        assert!(element.write_to(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn parse_v3() {
+        let source = "TransformBegin
+Material \"matte\" \"color Kd\" [ 1 0 0 ] \"bool twosided\" \"true\"
+TransformEnd
+WorldEnd
+";
+
+        let err = Parser::new(source).parse_next().unwrap_err();
+        assert!(matches!(err.kind(), Error::UnknownDirective));
+
+        let mut parser = Parser::with_version(source, Version::V3);
+        assert_eq!(parser.version(), Version::V3);
+        assert_eq!(parser.parse_next().unwrap(), Element::TransformBegin);
+
+        let Element::Material { params, .. } = parser.parse_next().unwrap() else {
+            panic!("expected material");
+        };
+        assert_eq!(params.get("Kd").unwrap().ty, ParamType::Rgb);
+        assert!(params.boolean("twosided", false).unwrap());
+
+        assert_eq!(parser.parse_next().unwrap(), Element::TransformEnd);
+        assert_eq!(parser.parse_next().unwrap(), Element::WorldEnd);
+    }
 }
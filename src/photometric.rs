@@ -0,0 +1,447 @@
+//! Photometric normalization of light sources.
+//!
+//! pbrt-v4 normalizes every emission spectrum to have a luminance of 1 nit and then
+//! multiplies it by the light's scale. When `power` or `illuminance` is given, the scale is
+//! further adjusted so that the light emits the requested amount, independently of the
+//! emission spectrum.
+//!
+//! The functions in this module return that final scale. It multiplies:
+//! - RGB values directly, since the color space illuminant is normalized instead of
+//!   the RGB spectrum (pbrt deliberately ignores the RGB value itself when matching `power`).
+//! - Any other spectrum divided by its luminance (e.g. a "blackbody" spectrum with a
+//!   luminance of 1 nit).
+//!
+//! Images are not read by the crate, lights with textured emission need the decoded
+//! image to be passed in.
+
+use std::f32::consts::PI;
+
+use glam::{Mat4, Vec3};
+
+use crate::{
+    color::ColorSpace,
+    types::{AreaLight, Light, Shape},
+    Error, Result,
+};
+
+/// Decoded linear RGB image referenced by a light's "filename" parameter.
+#[derive(Debug, Clone, Copy)]
+pub struct Image<'a> {
+    pub width: usize,
+    pub height: usize,
+    /// Pixels in scanline order, starting at the top left corner.
+    pub pixels: &'a [[f32; 3]],
+    /// Color space of the pixel values.
+    pub color_space: ColorSpace,
+}
+
+impl Image<'_> {
+    fn check(&self) -> Result<()> {
+        if self.width * self.height != self.pixels.len() {
+            return Err(Error::ParseSlice);
+        }
+
+        Ok(())
+    }
+
+    fn luminance(&self, rgb: [f32; 3]) -> f32 {
+        let y = self.color_space.rgb_to_xyz().row(1);
+        y.dot(Vec3::from(rgb).max(Vec3::ZERO))
+    }
+
+    /// Average luminance of the image.
+    fn average_luminance(&self) -> f32 {
+        let sum = self
+            .pixels
+            .iter()
+            .map(|rgb| self.luminance(*rgb))
+            .sum::<f32>();
+
+        sum / self.pixels.len().max(1) as f32
+    }
+}
+
+/// Final scale of the light's emission.
+///
+/// `image` is the decoded "filename" image and is required when the light has one
+/// and its brightness is given by `power` or `illuminance`.
+pub fn light_scale(light: &Light, image: Option<&Image>) -> Result<f32> {
+    let scale = match light {
+        Light::Distant {
+            scale, illuminance, ..
+        } => match illuminance {
+            Some(illuminance) => scale * illuminance,
+            None => *scale,
+        },
+        Light::GonioPhotometric {
+            scale,
+            power,
+            filename,
+            ..
+        } => match power {
+            Some(power) => {
+                // Goniometric images are converted to a single channel by averaging RGB.
+                let average = match filename {
+                    Some(_) => {
+                        let image = required(image)?;
+                        let sum = image
+                            .pixels
+                            .iter()
+                            .map(|[r, g, b]| (r + g + b) / 3.0)
+                            .sum::<f32>();
+
+                        sum / image.pixels.len().max(1) as f32
+                    }
+                    None => 1.0,
+                };
+
+                normalize(*scale, *power, 4.0 * PI * average)
+            }
+            None => *scale,
+        },
+        Light::Infinite {
+            filename,
+            scale,
+            illuminance,
+            ..
+        } => match (illuminance, filename) {
+            (Some(illuminance), Some(_)) => {
+                let image = required(image)?;
+                normalize(*scale, *illuminance, hemisphere_illuminance(image))
+            }
+            // Illuminance of the uniform radiance L is L * Pi.
+            (Some(illuminance), None) => scale * illuminance / PI,
+            (None, _) => *scale,
+        },
+        Light::Point { scale, power, .. } => match power {
+            Some(power) => normalize(*scale, *power, 4.0 * PI),
+            None => *scale,
+        },
+        Light::Projection {
+            scale, power, fov, ..
+        } => match power {
+            Some(power) => {
+                let image = required(image)?;
+                normalize(*scale, *power, projected_intensity(image, *fov))
+            }
+            None => *scale,
+        },
+        Light::Spot {
+            scale,
+            power,
+            cone_angle,
+            cone_delta,
+            ..
+        } => match power {
+            Some(power) => {
+                let cos_end = cone_angle.to_radians().cos();
+                let cos_start = (cone_angle - cone_delta).to_radians().cos();
+
+                // Intensity falls off smoothly between the two cones, which is
+                // approximated by the half of the solid angle between them.
+                let k_e = 2.0 * PI * ((1.0 - cos_start) + (cos_start - cos_end) / 2.0);
+                normalize(*scale, *power, k_e)
+            }
+            None => *scale,
+        },
+    };
+
+    Ok(scale)
+}
+
+/// Final scale of the emission of a single shape with an area light.
+///
+/// pbrt creates a separate area light for each shape, including each triangle of
+/// a mesh, so `power` is emitted by every one of them. `area` is the shape's area,
+/// as returned by [shape_areas].
+pub fn area_light_scale(light: &AreaLight, area: f32, image: Option<&Image>) -> Result<f32> {
+    let AreaLight::Diffuse {
+        filename,
+        two_sided,
+        scale,
+        power,
+        ..
+    } = light;
+
+    let Some(power) = power else {
+        return Ok(*scale);
+    };
+
+    let mut k_e = match filename {
+        Some(_) => required(image)?.average_luminance(),
+        None => 1.0,
+    };
+
+    if *two_sided {
+        k_e *= 2.0;
+    }
+
+    Ok(normalize(*scale, *power, k_e * area * PI))
+}
+
+/// Areas of the individual shapes pbrt creates for `shape`, as used by [area_light_scale].
+///
+/// Quadrics are measured in object space like pbrt does, triangle meshes are transformed
+/// by `transform` and have an entry per triangle.
+/// Returns `None` for "plymesh" shapes, use [triangle_areas] once the mesh is loaded.
+pub fn shape_areas(shape: &Shape, transform: &Mat4) -> Option<Result<Vec<f32>>> {
+    let area = match shape {
+        Shape::Cylinder {
+            radius,
+            zmin,
+            zmax,
+            phimax,
+            ..
+        } => (zmax - zmin) * radius * phi_max(*phimax),
+        Shape::Disk {
+            radius,
+            innerradius,
+            phimax,
+            ..
+        } => phi_max(*phimax) * 0.5 * (radius * radius - innerradius * innerradius),
+        Shape::Sphere {
+            radius,
+            zmin,
+            zmax,
+            phimax,
+            ..
+        } => {
+            let z0 = zmin.min(*zmax).clamp(-radius, *radius);
+            let z1 = zmin.max(*zmax).clamp(-radius, *radius);
+            phi_max(*phimax) * radius * (z1 - z0)
+        }
+        Shape::TriangleMesh {
+            indices, positions, ..
+        } => return Some(triangle_areas(indices, positions, transform)),
+        Shape::PlyMesh { .. } => return None,
+    };
+
+    Some(Ok(vec![area]))
+}
+
+/// Areas of the triangles of a mesh transformed by `transform`.
+pub fn triangle_areas(indices: &[i32], positions: &[f32], transform: &Mat4) -> Result<Vec<f32>> {
+    let vertex = |index: i32| {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| positions.get(index * 3..index * 3 + 3))
+            .map(|p| transform.transform_point3(Vec3::from_slice(p)))
+            .ok_or_else(|| Error::ParseSlice.with_param("indices"))
+    };
+
+    indices
+        .chunks_exact(3)
+        .map(|triangle| {
+            let p0 = vertex(triangle[0])?;
+            let p1 = vertex(triangle[1])?;
+            let p2 = vertex(triangle[2])?;
+
+            Ok(0.5 * (p1 - p0).cross(p2 - p0).length())
+        })
+        .collect()
+}
+
+fn required<'a, 'b>(image: Option<&'a Image<'b>>) -> Result<&'a Image<'b>> {
+    let image = image.ok_or_else(|| Error::MissingRequiredParameter.with_param("filename"))?;
+    image.check()?;
+    Ok(image)
+}
+
+/// Adjust `scale` so that emission of `k_e` units per unit of scale becomes `target`.
+fn normalize(scale: f32, target: f32, k_e: f32) -> f32 {
+    // Lights that emit nothing can't be brightened.
+    if k_e > 0.0 {
+        scale * target / k_e
+    } else {
+        scale
+    }
+}
+
+fn phi_max(degrees: f32) -> f32 {
+    degrees.clamp(0.0, 360.0).to_radians()
+}
+
+/// Illuminance at an upward facing point from an environment map with the equal-area
+/// octahedral parameterization.
+fn hemisphere_illuminance(image: &Image) -> f32 {
+    let mut k_e = 0.0;
+
+    for y in 0..image.height {
+        let v = (y as f32 + 0.5) / image.height as f32;
+        for x in 0..image.width {
+            let u = (x as f32 + 0.5) / image.width as f32;
+
+            let cos_theta = equal_area_cos_theta(u, v);
+            if cos_theta <= 0.0 {
+                continue;
+            }
+
+            k_e += image.luminance(image.pixels[y * image.width + x]) * cos_theta;
+        }
+    }
+
+    // Every pixel covers the same solid angle.
+    k_e * 4.0 * PI / image.pixels.len().max(1) as f32
+}
+
+/// Z component of the direction of the equal-area square to sphere mapping.
+fn equal_area_cos_theta(u: f32, v: f32) -> f32 {
+    let u = 2.0 * u - 1.0;
+    let v = 2.0 * v - 1.0;
+
+    let signed_distance = 1.0 - (u.abs() + v.abs());
+    let r = 1.0 - signed_distance.abs();
+
+    (1.0 - r * r).copysign(signed_distance)
+}
+
+/// Intensity of the projection light integrated over the image, per unit of scale.
+fn projected_intensity(image: &Image, fov: f32) -> f32 {
+    let aspect = image.width as f32 / image.height.max(1) as f32;
+    let (sx, sy) = if aspect > 1.0 {
+        (aspect, 1.0)
+    } else {
+        (1.0, 1.0 / aspect)
+    };
+
+    // Screen space is scaled by the field of view on the plane at z = 1.
+    let tan = (fov.to_radians() / 2.0).tan();
+
+    let mut k_e = 0.0;
+    for y in 0..image.height {
+        let py = sy * (1.0 - 2.0 * (y as f32 + 0.5) / image.height as f32) * tan;
+        for x in 0..image.width {
+            let px = sx * (2.0 * (x as f32 + 0.5) / image.width as f32 - 1.0) * tan;
+
+            // Solid angle of a pixel is proportional to cos^3 of its direction.
+            let cos_theta = 1.0 / (px * px + py * py + 1.0).sqrt();
+            k_e += image.luminance(image.pixels[y * image.width + x]) * cos_theta.powi(3);
+        }
+    }
+
+    let pixel_area = (2.0 * sx * tan) * (2.0 * sy * tan) / image.pixels.len().max(1) as f32;
+    k_e * pixel_area
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scene;
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() <= 1e-3 * b.abs().max(1.0), "{} != {}", a, b);
+    }
+
+    fn lights(source: &str) -> Vec<f32> {
+        let scene = Scene::load(source, None).unwrap();
+        scene
+            .lights
+            .iter()
+            .map(|light| light_scale(&light.params, None).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn normalize_lights() {
+        let scales = lights(
+            r#"
+WorldBegin
+LightSource "point" "float power" 100 "float scale" 2
+LightSource "point" "float scale" 3
+LightSource "spot" "float power" 10 "float coneangle" 90 "float conedelta" 0
+LightSource "distant" "float illuminance" 5
+LightSource "infinite" "float illuminance" 5
+LightSource "goniometric" "float power" 10
+"#,
+        );
+
+        assert_near(scales[0], 2.0 * 100.0 / (4.0 * PI));
+        assert_near(scales[1], 3.0);
+        // A spot light with 90 degrees cone covers the hemisphere.
+        assert_near(scales[2], 10.0 / (2.0 * PI));
+        assert_near(scales[3], 5.0);
+        assert_near(scales[4], 5.0 / PI);
+        assert_near(scales[5], 10.0 / (4.0 * PI));
+    }
+
+    #[test]
+    fn normalize_images() {
+        let pixels = vec![[1.0; 3]; 64 * 64];
+        let image = Image {
+            width: 64,
+            height: 64,
+            pixels: &pixels,
+            color_space: ColorSpace::Srgb,
+        };
+
+        let light = Light::Infinite {
+            filename: Some("sky.exr".into()),
+            spectrum: None,
+            scale: 1.0,
+            portal: None,
+            illuminance: Some(PI),
+        };
+
+        // Uniform white environment map matches the uniform light.
+        let err = light_scale(&light, None).unwrap_err();
+        assert!(matches!(err.kind(), Error::MissingRequiredParameter));
+        assert_near(light_scale(&light, Some(&image)).unwrap(), 1.0);
+
+        let light = Light::GonioPhotometric {
+            spectrum: None,
+            scale: 1.0,
+            power: Some(4.0 * PI),
+            filename: Some("gonio.exr".into()),
+        };
+        assert_near(light_scale(&light, Some(&image)).unwrap(), 1.0);
+
+        // Projection of a white image with 90 degrees field of view covers a cube face,
+        // which is a sixth of the sphere.
+        let light = Light::Projection {
+            scale: 1.0,
+            power: Some(4.0 * PI / 6.0),
+            fov: 90.0,
+            filename: "slide.exr".into(),
+        };
+        assert_near(light_scale(&light, Some(&image)).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn normalize_area_lights() {
+        let scene = Scene::load(
+            r#"
+WorldBegin
+AreaLightSource "diffuse" "float power" 10 "bool twosided" true
+Scale 2 2 2
+Shape "trianglemesh" "point3 P" [ 0 0 0 1 0 0 0 1 0 1 1 0 ] "integer indices" [ 0 1 2 1 3 2 ]
+Shape "sphere" "float radius" 2 "float zmin" 0
+Shape "disk" "float radius" 2 "float innerradius" 1 "float phimax" 180
+Shape "cylinder" "float zmin" 0
+"#,
+            None,
+        )
+        .unwrap();
+
+        let areas = scene
+            .shapes
+            .iter()
+            .map(|shape| shape_areas(&shape.params, &shape.transform).unwrap())
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        // Triangles are transformed, quadrics are measured in object space.
+        assert_eq!(areas[0], vec![2.0, 2.0]);
+        assert_near(areas[1][0], 8.0 * PI);
+        assert_near(areas[2][0], 1.5 * PI);
+        assert_near(areas[3][0], 2.0 * PI);
+
+        let light = &scene.area_lights[0];
+        assert_near(
+            area_light_scale(light, 2.0, None).unwrap(),
+            10.0 / (2.0 * 2.0 * PI),
+        );
+
+        let err = triangle_areas(&[0, 1, 5], &[0.0; 9], &Mat4::IDENTITY).unwrap_err();
+        assert!(matches!(err.kind(), Error::ParseSlice));
+    }
+}
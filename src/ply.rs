@@ -0,0 +1,869 @@
+//! PLY mesh loader.
+//!
+//! Supports ASCII and binary (both little and big endian) files with triangle and quad faces,
+//! just like pbrt's "plymesh" shape.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufWriter, Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use crate::{file, types::Shape, Error, Result, Scene};
+
+/// Mesh data read from a PLY file.
+///
+/// Buffers have the same layout as [crate::types::Shape::TriangleMesh].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PlyMesh {
+    /// Vertex indices, three per triangle.
+    pub indices: Vec<i32>,
+    /// Vertex indices of bilinear patches, four per quad.
+    ///
+    /// Vertices are ordered as pbrt's "bilinearmesh" expects them: (p00, p10, p01, p11).
+    pub quad_indices: Vec<i32>,
+    /// Vertex positions, three floats per vertex.
+    pub positions: Vec<f32>,
+    /// Per-vertex normals, empty if the file doesn't provide them.
+    pub normals: Vec<f32>,
+    /// Per-vertex texture coordinates, two floats per vertex.
+    pub uvs: Vec<f32>,
+    /// Per-face integer indices, empty if the file doesn't provide them.
+    ///
+    /// Triangles come first, in the same order as `indices`, followed by quads
+    /// in the same order as `quad_indices`.
+    pub face_indices: Vec<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScalarType {
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::Uint8 => 1,
+            ScalarType::Int16 | ScalarType::Uint16 => 2,
+            ScalarType::Int32 | ScalarType::Uint32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8,
+        }
+    }
+}
+
+impl FromStr for ScalarType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let ty = match s {
+            "char" | "int8" => ScalarType::Int8,
+            "uchar" | "uint8" => ScalarType::Uint8,
+            "short" | "int16" => ScalarType::Int16,
+            "ushort" | "uint16" => ScalarType::Uint16,
+            "int" | "int32" => ScalarType::Int32,
+            "uint" | "uint32" => ScalarType::Uint32,
+            "float" | "float32" => ScalarType::Float32,
+            "double" | "float64" => ScalarType::Float64,
+            _ => return Err(Error::InvalidPly("unknown property type")),
+        };
+
+        Ok(ty)
+    }
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    ty: ScalarType,
+    /// Type of the list length, `None` for scalar properties.
+    count: Option<ScalarType>,
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Parse PLY header up to and including the "end_header" line.
+fn read_header(reader: &mut impl BufRead) -> Result<(Format, Vec<Element>)> {
+    let mut line = String::new();
+
+    let mut next_line = |line: &mut String| -> Result<()> {
+        line.clear();
+        if reader.read_line(line)? == 0 {
+            return Err(Error::InvalidPly("unexpected end of header"));
+        }
+        Ok(())
+    };
+
+    next_line(&mut line)?;
+    if line.trim_end() != "ply" {
+        return Err(Error::InvalidPly("missing magic number"));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+
+    loop {
+        next_line(&mut line)?;
+
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("format") => {
+                format = Some(match words.next() {
+                    Some("ascii") => Format::Ascii,
+                    Some("binary_little_endian") => Format::BinaryLittleEndian,
+                    Some("binary_big_endian") => Format::BinaryBigEndian,
+                    _ => return Err(Error::InvalidPly("unknown format")),
+                });
+            }
+            Some("element") => {
+                let (Some(name), Some(count)) = (words.next(), words.next()) else {
+                    return Err(Error::InvalidPly("invalid element"));
+                };
+
+                elements.push(Element {
+                    name: name.to_string(),
+                    count: count.parse()?,
+                    properties: Vec::new(),
+                });
+            }
+            Some("property") => {
+                let element = elements
+                    .last_mut()
+                    .ok_or(Error::InvalidPly("property outside of element"))?;
+
+                let property = match words.next() {
+                    Some("list") => {
+                        let (Some(count), Some(ty), Some(name)) =
+                            (words.next(), words.next(), words.next())
+                        else {
+                            return Err(Error::InvalidPly("invalid list property"));
+                        };
+
+                        Property {
+                            name: name.to_string(),
+                            ty: ty.parse()?,
+                            count: Some(count.parse()?),
+                        }
+                    }
+                    Some(ty) => Property {
+                        name: words
+                            .next()
+                            .ok_or(Error::InvalidPly("invalid property"))?
+                            .to_string(),
+                        ty: ty.parse()?,
+                        count: None,
+                    },
+                    None => return Err(Error::InvalidPly("invalid property")),
+                };
+
+                element.properties.push(property);
+            }
+            Some("end_header") => break,
+            // Comments, obj_info and blank lines.
+            _ => {}
+        }
+    }
+
+    let format = format.ok_or(Error::InvalidPly("missing format"))?;
+
+    Ok((format, elements))
+}
+
+/// Reads scalar values from the PLY body.
+trait Values {
+    fn next(&mut self, ty: ScalarType) -> Result<f64>;
+}
+
+struct AsciiValues<'a>(std::str::SplitAsciiWhitespace<'a>);
+
+impl Values for AsciiValues<'_> {
+    fn next(&mut self, _ty: ScalarType) -> Result<f64> {
+        let token = self
+            .0
+            .next()
+            .ok_or(Error::InvalidPly("unexpected end of file"))?;
+
+        Ok(token.parse()?)
+    }
+}
+
+struct BinaryValues<R> {
+    reader: R,
+    big_endian: bool,
+}
+
+impl<R: Read> Values for BinaryValues<R> {
+    fn next(&mut self, ty: ScalarType) -> Result<f64> {
+        let mut buf = [0_u8; 8];
+        let buf = &mut buf[..ty.size()];
+
+        self.reader
+            .read_exact(buf)
+            .map_err(|_| Error::InvalidPly("unexpected end of file"))?;
+
+        if self.big_endian {
+            buf.reverse();
+        }
+
+        let value = match ty {
+            ScalarType::Int8 => i8::from_le_bytes([buf[0]]) as f64,
+            ScalarType::Uint8 => buf[0] as f64,
+            ScalarType::Int16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+            ScalarType::Uint16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+            ScalarType::Int32 => i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            ScalarType::Uint32 => u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            ScalarType::Float32 => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            ScalarType::Float64 => f64::from_le_bytes([
+                buf[0], buf[1], buf[2], buf[3], buf[4], buf[5], buf[6], buf[7],
+            ]),
+        };
+
+        Ok(value)
+    }
+}
+
+/// Index of the first property with one of the given names.
+fn find(element: &Element, names: &[&str]) -> Option<usize> {
+    element
+        .properties
+        .iter()
+        .position(|p| names.contains(&p.name.as_str()))
+}
+
+fn read_body(elements: &[Element], values: &mut dyn Values) -> Result<PlyMesh> {
+    let mut mesh = PlyMesh::default();
+    // Appended to the triangle face indices once all faces are read.
+    let mut quad_face_indices = Vec::new();
+
+    let mut row = Vec::new();
+    let mut face = Vec::new();
+
+    for element in elements {
+        let x = find(element, &["x"]);
+        let y = find(element, &["y"]);
+        let z = find(element, &["z"]);
+        let nx = find(element, &["nx"]);
+        let ny = find(element, &["ny"]);
+        let nz = find(element, &["nz"]);
+        let u = find(element, &["u", "s", "texture_u", "texture_s"]);
+        let v = find(element, &["v", "t", "texture_v", "texture_t"]);
+        let vertex_indices = find(element, &["vertex_indices", "vertex_index"]);
+        let face_indices = find(element, &["face_indices"]);
+
+        for _ in 0..element.count {
+            row.clear();
+            face.clear();
+
+            for (i, property) in element.properties.iter().enumerate() {
+                match property.count {
+                    Some(count_ty) => {
+                        let count = values.next(count_ty)? as usize;
+                        for _ in 0..count {
+                            let value = values.next(property.ty)?;
+                            if Some(i) == vertex_indices {
+                                face.push(value as i32);
+                            }
+                        }
+                        row.push(0.0);
+                    }
+                    None => row.push(values.next(property.ty)?),
+                }
+            }
+
+            match element.name.as_str() {
+                "vertex" => {
+                    let (Some(x), Some(y), Some(z)) = (x, y, z) else {
+                        return Err(Error::InvalidPly("vertex position is missing"));
+                    };
+                    mesh.positions
+                        .extend([row[x] as f32, row[y] as f32, row[z] as f32]);
+
+                    if let (Some(nx), Some(ny), Some(nz)) = (nx, ny, nz) {
+                        mesh.normals
+                            .extend([row[nx] as f32, row[ny] as f32, row[nz] as f32]);
+                    }
+
+                    if let (Some(u), Some(v)) = (u, v) {
+                        mesh.uvs.extend([row[u] as f32, row[v] as f32]);
+                    }
+                }
+                "face" => {
+                    let face_index = face_indices.map(|index| row[index] as i32);
+
+                    match face.len() {
+                        3 => {
+                            mesh.indices.extend_from_slice(&face);
+                            mesh.face_indices.extend(face_index);
+                        }
+                        // Bilinear patches expect vertices in (p00, p10, p01, p11) order.
+                        4 => {
+                            mesh.quad_indices
+                                .extend([face[0], face[1], face[3], face[2]]);
+                            quad_face_indices.extend(face_index);
+                        }
+                        _ => {
+                            return Err(Error::InvalidPly("only triangles and quads are supported"))
+                        }
+                    }
+                }
+                // Other elements are skipped.
+                _ => {}
+            }
+        }
+    }
+
+    mesh.face_indices.append(&mut quad_face_indices);
+
+    let vertex_count = mesh.positions.len() / 3;
+    if mesh
+        .indices
+        .iter()
+        .chain(&mesh.quad_indices)
+        .any(|&index| index < 0 || index as usize >= vertex_count)
+    {
+        return Err(Error::InvalidPly("vertex index is out of bounds"));
+    }
+
+    Ok(mesh)
+}
+
+impl PlyMesh {
+    /// Read a PLY mesh from a file.
+    ///
+    /// Files with ".gz" extension are decompressed if the "gzip" feature is enabled.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<PlyMesh> {
+        let path = path.as_ref();
+
+        file::open(path)
+            .and_then(PlyMesh::from_reader)
+            .map_err(|err| err.with_path(path))
+    }
+
+    /// Read a PLY mesh from a buffered reader.
+    pub fn from_reader(mut reader: impl BufRead) -> Result<PlyMesh> {
+        let (format, elements) = read_header(&mut reader)?;
+
+        match format {
+            Format::Ascii => {
+                let mut data = String::new();
+                reader.read_to_string(&mut data)?;
+
+                read_body(&elements, &mut AsciiValues(data.split_ascii_whitespace()))
+            }
+            Format::BinaryLittleEndian | Format::BinaryBigEndian => {
+                let mut values = BinaryValues {
+                    reader,
+                    big_endian: format == Format::BinaryBigEndian,
+                };
+
+                read_body(&elements, &mut values)
+            }
+        }
+    }
+}
+
+impl PlyMesh {
+    /// Write the mesh as a binary little endian PLY file.
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+
+        self.write_file(path.as_ref(), &options)
+    }
+
+    fn write_file(&self, path: &Path, options: &OpenOptions) -> Result<()> {
+        let write = || -> Result<()> {
+            let mut writer = BufWriter::new(options.open(path)?);
+            self.write(&mut writer)?;
+            writer.flush()?;
+            Ok(())
+        };
+
+        write().map_err(|err| err.with_path(path))
+    }
+
+    /// Write the mesh in binary little endian PLY format.
+    pub fn write(&self, mut writer: impl Write) -> Result<()> {
+        let vertex_count = self.positions.len() / 3;
+        let face_count = self.indices.len() / 3 + self.quad_indices.len() / 4;
+
+        if self.positions.len() % 3 != 0
+            || self.indices.len() % 3 != 0
+            || self.quad_indices.len() % 4 != 0
+        {
+            return Err(Error::InvalidPly("incomplete vertex or face"));
+        }
+
+        let has_normals = !self.normals.is_empty();
+        let has_uvs = !self.uvs.is_empty();
+        let has_face_indices = !self.face_indices.is_empty();
+
+        if (has_normals && self.normals.len() != self.positions.len())
+            || (has_uvs && self.uvs.len() != vertex_count * 2)
+            || (has_face_indices && self.face_indices.len() != face_count)
+        {
+            return Err(Error::InvalidPly("mismatched buffer sizes"));
+        }
+
+        if self
+            .indices
+            .iter()
+            .chain(&self.quad_indices)
+            .any(|&index| index < 0 || index as usize >= vertex_count)
+        {
+            return Err(Error::InvalidPly("vertex index is out of bounds"));
+        }
+
+        writeln!(writer, "ply")?;
+        writeln!(writer, "format binary_little_endian 1.0")?;
+        writeln!(writer, "element vertex {vertex_count}")?;
+        for name in ["x", "y", "z"] {
+            writeln!(writer, "property float {name}")?;
+        }
+        if has_normals {
+            for name in ["nx", "ny", "nz"] {
+                writeln!(writer, "property float {name}")?;
+            }
+        }
+        if has_uvs {
+            for name in ["u", "v"] {
+                writeln!(writer, "property float {name}")?;
+            }
+        }
+        writeln!(writer, "element face {face_count}")?;
+        writeln!(writer, "property list uchar int vertex_indices")?;
+        if has_face_indices {
+            writeln!(writer, "property int face_indices")?;
+        }
+        writeln!(writer, "end_header")?;
+
+        for i in 0..vertex_count {
+            let normals = match has_normals {
+                true => &self.normals[i * 3..i * 3 + 3],
+                false => &[],
+            };
+            let uvs = match has_uvs {
+                true => &self.uvs[i * 2..i * 2 + 2],
+                false => &[],
+            };
+
+            for value in self.positions[i * 3..i * 3 + 3]
+                .iter()
+                .chain(normals)
+                .chain(uvs)
+            {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+
+        // Face indices list triangles first, followed by quads.
+        let mut face_indices = self.face_indices.iter();
+        let mut write_face = |face: &[i32]| -> io::Result<()> {
+            writer.write_all(&[face.len() as u8])?;
+            for index in face {
+                writer.write_all(&index.to_le_bytes())?;
+            }
+
+            match face_indices.next() {
+                Some(face_index) => writer.write_all(&face_index.to_le_bytes()),
+                None => Ok(()),
+            }
+        };
+
+        for triangle in self.indices.chunks_exact(3) {
+            write_face(triangle)?;
+        }
+        // Quads are stored in bilinear patch order, restore the winding order of the face.
+        for q in self.quad_indices.chunks_exact(4) {
+            write_face(&[q[0], q[1], q[3], q[2]])?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Scene {
+    /// Move large inline triangle meshes out into PLY files, like `pbrt --toply`.
+    ///
+    /// Every "trianglemesh" shape with at least `min_vertices` vertices is written to
+    /// `directory/geometry/mesh_NNNN.ply` and replaced by a "plymesh" shape that refers
+    /// to it relative to `directory`. Meshes with shading tangents are left as is,
+    /// since PLY files can't store them.
+    ///
+    /// Existing files are never overwritten, names that are already taken are skipped.
+    /// If any mesh fails to be written, the written files are removed and the scene
+    /// is left unchanged.
+    ///
+    /// Returns the number of written files.
+    pub fn convert_to_ply<P: AsRef<Path>>(
+        &mut self,
+        directory: P,
+        min_vertices: usize,
+    ) -> Result<usize> {
+        let directory = directory.as_ref();
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+
+        // Shapes are only replaced once all files are written.
+        let mut written = Vec::new();
+        let mut number = 0;
+
+        for (index, entity) in self.shapes.iter_mut().enumerate() {
+            let Shape::TriangleMesh {
+                indices,
+                positions,
+                normals,
+                tangents,
+                uvs,
+                ..
+            } = &mut entity.params
+            else {
+                continue;
+            };
+
+            if positions.len() / 3 < min_vertices || !tangents.is_empty() {
+                continue;
+            }
+
+            let mesh = PlyMesh {
+                indices: std::mem::take(indices),
+                positions: std::mem::take(positions),
+                normals: std::mem::take(normals),
+                uvs: std::mem::take(uvs),
+                ..PlyMesh::default()
+            };
+
+            let mut write = || -> Result<String> {
+                if written.is_empty() {
+                    fs::create_dir_all(directory.join("geometry"))?;
+                }
+
+                loop {
+                    number += 1;
+                    let filename = format!("geometry/mesh_{number:04}.ply");
+
+                    match mesh.write_file(&directory.join(&filename), &options) {
+                        Ok(()) => return Ok(filename),
+                        // Written by a previous conversion.
+                        Err(err) if is_already_exists(&err) => continue,
+                        Err(err) => {
+                            let _ = fs::remove_file(directory.join(&filename));
+                            return Err(err);
+                        }
+                    }
+                }
+            };
+            let result = write();
+
+            *indices = mesh.indices;
+            *positions = mesh.positions;
+            *normals = mesh.normals;
+            *uvs = mesh.uvs;
+
+            match result {
+                Ok(filename) => written.push((index, filename)),
+                Err(err) => {
+                    for (_, filename) in &written {
+                        let _ = fs::remove_file(directory.join(filename));
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        let count = written.len();
+
+        for (index, filename) in written {
+            let entity = &mut self.shapes[index];
+
+            if let Shape::TriangleMesh { alpha, .. } = entity.params {
+                entity.params = Shape::PlyMesh { alpha, filename };
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+fn is_already_exists(err: &Error) -> bool {
+    matches!(err.kind(), Error::Io(err) if err.kind() == io::ErrorKind::AlreadyExists)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASCII: &str = "ply
+format ascii 1.0
+comment made by hand
+element vertex 5
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property float s
+property float t
+element face 2
+property list uchar int vertex_indices
+property int face_indices
+end_header
+0 0 0 0 0 1 0 0
+1 0 0 0 0 1 1 0
+1 1 0 0 0 1 1 1
+0 1 0 0 0 1 0 1
+2 2 0 0 0 1 1 1
+3 0 1 2 7
+4 1 4 2 3 9
+";
+
+    #[test]
+    fn read_ascii() -> Result<()> {
+        let mesh = PlyMesh::from_reader(ASCII.as_bytes())?;
+
+        assert_eq!(mesh.positions.len(), 15);
+        assert_eq!(mesh.normals.len(), 15);
+        assert_eq!(mesh.uvs, vec![0., 0., 1., 0., 1., 1., 0., 1., 1., 1.]);
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        assert_eq!(mesh.quad_indices, vec![1, 4, 3, 2]);
+        assert_eq!(mesh.face_indices, vec![7, 9]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_mixed_faces() -> Result<()> {
+        let mixed = ASCII
+            .replace("element face 2", "element face 3")
+            .replace("4 1 4 2 3 9\n", "4 1 4 2 3 9\n3 0 2 3 8\n");
+        let mesh = PlyMesh::from_reader(mixed.as_bytes())?;
+
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.quad_indices, vec![1, 4, 3, 2]);
+        assert_eq!(mesh.face_indices, vec![7, 8, 9]);
+
+        Ok(())
+    }
+
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian {
+            "binary_big_endian"
+        } else {
+            "binary_little_endian"
+        };
+
+        let mut data = format!(
+            "ply\nformat {format} 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+             property float z\nproperty double texture_u\nproperty double texture_v\n\
+             element face 1\nproperty list uchar uint vertex_indices\nend_header\n"
+        )
+        .into_bytes();
+
+        let f32_bytes = |v: f32| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let f64_bytes = |v: f64| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+
+        for i in 0..3 {
+            for v in [i as f32, 1.0, 2.0] {
+                data.extend(f32_bytes(v));
+            }
+            for v in [0.5, i as f64] {
+                data.extend(f64_bytes(v));
+            }
+        }
+
+        data.push(3);
+        for i in [2_u32, 1, 0] {
+            data.extend(if big_endian {
+                i.to_be_bytes()
+            } else {
+                i.to_le_bytes()
+            });
+        }
+
+        data
+    }
+
+    #[test]
+    fn read_binary() -> Result<()> {
+        for big_endian in [false, true] {
+            let mesh = PlyMesh::from_reader(binary(big_endian).as_slice())?;
+
+            assert_eq!(mesh.positions, vec![0., 1., 2., 1., 1., 2., 2., 1., 2.]);
+            assert_eq!(mesh.uvs, vec![0.5, 0., 0.5, 1., 0.5, 2.]);
+            assert!(mesh.normals.is_empty());
+            assert_eq!(mesh.indices, vec![2, 1, 0]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_invalid() {
+        let pentagon = ASCII.replace("4 1 4 2 3 9", "5 1 4 2 3 0 9");
+        assert!(PlyMesh::from_reader(pentagon.as_bytes()).is_err());
+
+        let out_of_bounds = ASCII.replace("3 0 1 2 7", "3 0 1 5 7");
+        assert!(PlyMesh::from_reader(out_of_bounds.as_bytes()).is_err());
+
+        let truncated = &ASCII[..ASCII.len() - 12];
+        assert!(PlyMesh::from_reader(truncated.as_bytes()).is_err());
+
+        assert!(PlyMesh::from_reader("obj\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn write_and_read() -> Result<()> {
+        let mesh = PlyMesh::from_reader(ASCII.as_bytes())?;
+
+        let mut data = Vec::new();
+        mesh.write(&mut data)?;
+
+        assert_eq!(PlyMesh::from_reader(data.as_slice())?, mesh);
+
+        let invalid = PlyMesh {
+            indices: vec![0, 1],
+            ..PlyMesh::default()
+        };
+        assert!(invalid.write(&mut Vec::new()).is_err());
+
+        let out_of_bounds = PlyMesh {
+            indices: vec![0, 1, 5],
+            ..mesh.clone()
+        };
+        assert!(out_of_bounds.write(&mut Vec::new()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_mixed_faces() -> Result<()> {
+        // Triangle, quad and triangle in file order.
+        let mixed = ASCII
+            .replace("element face 2", "element face 3")
+            .replace("4 1 4 2 3 9\n", "4 1 4 2 3 9\n3 0 2 3 8\n");
+        let mesh = PlyMesh::from_reader(mixed.as_bytes())?;
+
+        let mut data = Vec::new();
+        mesh.write(&mut data)?;
+
+        let written = PlyMesh::from_reader(data.as_slice())?;
+        assert_eq!(written, mesh);
+        assert_eq!(written.face_indices, vec![7, 8, 9]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn convert_to_ply() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pbrt-toply-")?;
+        let temp_path = temp_dir.path();
+
+        let data = r#"
+WorldBegin
+Shape "trianglemesh" "point3 P" [0 0 0 1 0 0 0 1 0 1 1 0] "integer indices" [0 1 2 2 1 3]
+    "float alpha" 0.5
+Shape "trianglemesh" "point3 P" [0 0 0 1 0 0 0 1 0] "integer indices" [0 1 2]
+Shape "trianglemesh" "point3 P" [0 0 0 1 0 0 0 1 0 1 1 0] "integer indices" [0 1 2 2 1 3]
+    "vector3 S" [1 0 0 1 0 0 1 0 0 1 0 0]
+Shape "sphere"
+        "#;
+
+        let mut scene = Scene::load(data, None)?;
+        assert_eq!(scene.convert_to_ply(temp_path, 4)?, 1);
+
+        let Shape::PlyMesh { alpha, filename } = &scene.shapes[0].params else {
+            panic!("Unexpected shape, want PlyMesh");
+        };
+        assert_eq!(*alpha, 0.5);
+        assert_eq!(filename, "geometry/mesh_0001.ply");
+
+        let mesh = PlyMesh::from_file(temp_path.join(filename))?;
+        assert_eq!(mesh.indices, vec![0, 1, 2, 2, 1, 3]);
+        assert_eq!(mesh.positions.len(), 12);
+
+        // Small meshes and meshes with tangents stay inline.
+        assert!(matches!(scene.shapes[1].params, Shape::TriangleMesh { .. }));
+        assert!(matches!(scene.shapes[2].params, Shape::TriangleMesh { .. }));
+
+        // Files of previous conversions are kept.
+        let mut other = Scene::load(data, None)?;
+        assert_eq!(other.convert_to_ply(temp_path, 3)?, 2);
+
+        let Shape::PlyMesh { filename, .. } = &other.shapes[0].params else {
+            panic!("Unexpected shape, want PlyMesh");
+        };
+        assert_eq!(filename, "geometry/mesh_0002.ply");
+        assert_eq!(
+            PlyMesh::from_file(temp_path.join("geometry/mesh_0001.ply"))?,
+            mesh
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn convert_to_ply_error() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pbrt-toply-")?;
+        let temp_path = temp_dir.path();
+
+        let data = r#"
+WorldBegin
+Shape "trianglemesh" "point3 P" [0 0 0 1 0 0 0 1 0]
+Shape "trianglemesh" "point3 P" [0 0 0 1 0 0 0 1 0]
+        "#;
+
+        let mut scene = Scene::load(data, None)?;
+
+        // The second mesh can't be written.
+        if let Shape::TriangleMesh { indices, .. } = &mut scene.shapes[1].params {
+            indices.truncate(2);
+        }
+
+        assert!(scene.convert_to_ply(temp_path, 0).is_err());
+        assert!(!temp_path.join("geometry/mesh_0001.ply").exists());
+
+        let Shape::TriangleMesh {
+            indices, positions, ..
+        } = &scene.shapes[0].params
+        else {
+            panic!("Unexpected shape, want TriangleMesh");
+        };
+        assert_eq!(indices, &[0, 1, 2]);
+        assert_eq!(positions.len(), 9);
+
+        Ok(())
+    }
+}
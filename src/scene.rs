@@ -1,18 +1,54 @@
 //! Scene loader
 
-use std::{collections::HashMap, env, fs, path::Path, slice, str, thread::current};
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    env, mem, panic,
+    path::{Path, PathBuf},
+    slice,
+    str::{self, FromStr},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
 
 use glam::{Mat4, Vec3};
 
 use crate::{
-    param::ParamList,
+    color::ColorSpace,
+    file,
+    param::{ParamList, ParamType},
     types::{
-        Accelerator, AreaLight, Camera, Film, Integrator, Light, Material, Medium, Options,
-        PixelFilter, Sampler, Shape, Texture,
+        Accelerator, AreaLight, Camera, Film, Integrator, Light, Material, Medium, NamedTextures,
+        Options, PixelFilter, Sampler, Shape, Texture, TextureType,
     },
-    Element, Error, Parser, Result,
+    Element, Error, IncludeSite, Location, Parser, Result,
 };
 
+/// Transformation matrices at the start and the end of the shutter interval.
+type TransformSet = [Mat4; 2];
+
+/// Transformations modified by subsequent transformation directives, set with `ActiveTransform`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum ActiveTransform {
+    StartTime,
+    EndTime,
+    #[default]
+    All,
+}
+
+impl FromStr for ActiveTransform {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "StartTime" => Ok(ActiveTransform::StartTime),
+            "EndTime" => Ok(ActiveTransform::EndTime),
+            "All" => Ok(ActiveTransform::All),
+            _ => Err(Error::UnknownActiveTransform),
+        }
+    }
+}
+
 /// A number of directives modify the current graphics state.
 /// Examples include the transformation directives (Transformations),
 /// and the directive that sets the current material.
@@ -22,10 +58,16 @@ struct State<'a> {
     /// directive, is part of the graphics state.
     reverse_orientation: bool,
 
-    transform_matrix: Mat4,
+    /// Current transformation matrix (CTM) at the start and the end time.
+    ctm: TransformSet,
+    active_transform: ActiveTransform,
+
+    /// Color space RGB values are interpreted in, set with the `ColorSpace` directive.
+    color_space: ColorSpace,
 
-    current_inside_medium: Option<&'a str>,
-    current_outside_medium: Option<&'a str>,
+    /// Indices of the media in `Scene::mediums` set with `MediumInterface`, `None` is a vacuum.
+    current_inside_medium: Option<usize>,
+    current_outside_medium: Option<usize>,
 
     material_index: Option<usize>,
     area_light_index: Option<usize>,
@@ -41,10 +83,44 @@ struct State<'a> {
     texture_params: ParamList<'a>,
 }
 
+impl State<'_> {
+    /// Apply `f` to each of the transformations selected by `ActiveTransform`.
+    fn for_active_transforms(&mut self, mut f: impl FnMut(&mut Mat4)) {
+        let [start, end] = &mut self.ctm;
+
+        match self.active_transform {
+            ActiveTransform::StartTime => f(start),
+            ActiveTransform::EndTime => f(end),
+            ActiveTransform::All => {
+                f(start);
+                f(end);
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct CameraEntity {
     pub params: Camera,
+    /// Camera from world transformation at the start time.
+    pub transform: Mat4,
+    /// Camera from world transformation at the end time.
+    pub transform_end: Mat4,
+    /// Medium the camera is in, the exterior medium when the camera was declared.
+    pub medium_index: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct LightEntity {
+    pub params: Light,
+    /// Light to world transformation in effect when the light was declared.
+    ///
+    /// Transformation at the start time.
     pub transform: Mat4,
+    /// Transformation at the end time, differs from `transform` for animated lights.
+    pub transform_end: Mat4,
+    /// Medium rays leaving the light travel through, the exterior medium when the light was declared.
+    pub medium_index: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -52,10 +128,26 @@ pub struct ShapeEntity {
     pub params: Shape,
     /// If shape is a part of [Object], transform matrix defines the transformation from
     /// object space to the instance's coordinate space.
+    ///
+    /// Transformation at the start time.
     pub transform: Mat4,
+    /// Transformation at the end time, differs from `transform` for animated shapes.
+    pub transform_end: Mat4,
     pub reverse_orientation: bool,
     pub material_index: Option<usize>,
     pub area_light_index: Option<usize>,
+    /// Medium inside the shape.
+    pub inside_medium_index: Option<usize>,
+    /// Medium outside the shape.
+    pub outside_medium_index: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct MediumEntity {
+    pub name: String,
+    pub params: Medium,
+    /// Medium to world transformation in effect when the medium was declared.
+    pub transform: Mat4,
 }
 
 #[derive(Debug, Clone)]
@@ -68,15 +160,20 @@ pub struct Object {
 
 #[derive(Debug)]
 pub struct Instance {
+    /// Instance to world transformation at the start time.
     pub instance_to_world: Mat4,
+    /// Instance to world transformation at the end time.
+    pub instance_to_world_end: Mat4,
     pub object_index: usize,
     pub area_light_index: Option<usize>,
     pub reverse_orientation: bool,
 }
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct Scene {
+    /// Time the start transformations apply to, set with `TransformTimes`.
     pub start_time: f32,
+    /// Time the end transformations apply to, set with `TransformTimes`.
     pub end_time: f32,
     pub options: Options,
     pub camera: Option<CameraEntity>,
@@ -87,12 +184,40 @@ pub struct Scene {
     pub sampler: Option<Sampler>,
     pub textures: Vec<Texture>,
     pub materials: Vec<Material>,
-    pub lights: Vec<Light>,
+    pub lights: Vec<LightEntity>,
     pub area_lights: Vec<AreaLight>,
-    pub mediums: Vec<Medium>,
+    pub mediums: Vec<MediumEntity>,
     pub shapes: Vec<ShapeEntity>,
     pub objects: Vec<Object>,
     pub instances: Vec<Instance>,
+    /// Directory relative file names in the scene are resolved against.
+    pub working_directory: Option<PathBuf>,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene {
+            // pbrt's default shutter interval.
+            start_time: 0.0,
+            end_time: 1.0,
+            options: Options::default(),
+            camera: None,
+            film: None,
+            integrator: None,
+            accelerator: None,
+            pixel_filter: None,
+            sampler: None,
+            textures: Vec::new(),
+            materials: Vec::new(),
+            lights: Vec::new(),
+            area_lights: Vec::new(),
+            mediums: Vec::new(),
+            shapes: Vec::new(),
+            objects: Vec::new(),
+            instances: Vec::new(),
+            working_directory: None,
+        }
+    }
 }
 
 impl Scene {
@@ -102,8 +227,28 @@ impl Scene {
 
         let working_directory = path.parent();
 
-        let data = fs::read_to_string(path)?;
-        Self::load(&data, working_directory)
+        // Scene files may be compressed using gzip.
+        let data = file::read_to_string(path).map_err(|err| err.with_path(path))?;
+
+        let mut loader = Loader::new(&data, working_directory, Some(path));
+        loader.run()?;
+
+        Ok(loader.scene)
+    }
+
+    /// Resolve a file name referenced by the scene (such as a PLY mesh or an image)
+    /// relative to the scene's working directory.
+    pub fn resolve_path(&self, filename: &str) -> PathBuf {
+        match &self.working_directory {
+            Some(directory) => directory.join(filename),
+            None => PathBuf::from(filename),
+        }
+    }
+
+    /// Read the mesh of a "plymesh" shape, `filename` is resolved relative to the working directory.
+    #[cfg(feature = "ply")]
+    pub fn load_ply(&self, filename: &str) -> Result<crate::ply::PlyMesh> {
+        crate::ply::PlyMesh::from_file(self.resolve_path(filename))
     }
 
     /// Load a PBRT v4 scene from a string slice.
@@ -111,471 +256,2060 @@ impl Scene {
     /// # Arguments
     /// - `data` is a string buffer with the file data.
     /// - `working_directory` is a file's directory path which required for includes
-    /// with relative paths to work.
+    ///   with relative paths to work.
     pub fn load(data: &str, working_directory: Option<&Path>) -> Result<Scene> {
-        let mut scene = Scene::default();
+        let mut loader = Loader::new(data, working_directory, None);
+        loader.run()?;
+
+        Ok(loader.scene)
+    }
+}
+
+/// A file being parsed.
+struct Source<'a> {
+    parser: Parser<'a>,
+    /// Path to the file, `None` when loading from a string.
+    path: Option<PathBuf>,
+    /// Graphics state to restore once parsing of an imported file is done.
+    import: Option<Import<'a>>,
+}
+
+/// Graphics state saved by an `Import` directive.
+struct Import<'a> {
+    state: State<'a>,
+    /// Size of the attributes stack at the moment of import.
+    depth: usize,
+    named_coord_systems: HashMap<String, TransformSet>,
+}
+
+impl<'a> Source<'a> {
+    /// Annotate an error with the current position within the file.
+    fn annotate(&self, err: Error) -> Error {
+        let err = self.parser.annotate(err);
+
+        match &self.path {
+            Some(path) => err.with_path(path),
+            None => err,
+        }
+    }
+}
+
+/// Number of entities of each kind.
+///
+/// Imported files are loaded into separate partial scenes, whose indices
+/// start from the counts of the importing scene and are relocated on merge.
+#[derive(Debug, Default, Clone, Copy)]
+struct Counts {
+    textures: usize,
+    materials: usize,
+    area_lights: usize,
+    mediums: usize,
+    shapes: usize,
+    objects: usize,
+    unresolved: usize,
+}
+
+/// Kinds of named entities.
+#[derive(Debug, Clone, Copy)]
+enum Namespace {
+    Texture(TextureType),
+    Material,
+    Medium,
+    Object,
+}
+
+/// A name referenced by an imported file before it was defined.
+///
+/// Imports of the same batch are loaded in parallel, so the name might be defined
+/// by one of the preceding imports. Such references get placeholder indices, which
+/// are replaced once the partial scenes are merged in source order.
+struct Unresolved {
+    namespace: Namespace,
+    name: String,
+    /// Location of the referencing directive.
+    location: Location,
+}
 
-        let mut parsers = Vec::new();
-        parsers.push(Parser::new(data));
+/// Returns the placeholder index of the unresolved name `number`.
+///
+/// Placeholders count down from `usize::MAX`, far away from any real index.
+fn placeholder(number: usize) -> usize {
+    usize::MAX - number
+}
+
+/// Returns the unresolved name number if `index` is a placeholder.
+fn placeholder_number(index: usize) -> Option<usize> {
+    (index > usize::MAX / 2).then(|| usize::MAX - index)
+}
 
-        let mut current_state = State::default();
-        let mut states_stack = Vec::new();
-        let mut is_world_block = false;
+/// Entities without an explicit "seed" parameter.
+#[derive(Debug, Default, Clone, Copy)]
+struct DefaultSeeds {
+    sampler: bool,
+    integrator: bool,
+}
 
-        let mut named_coord_systems: HashMap<String, Mat4> = HashMap::default();
+/// An `Import` waiting to be loaded.
+struct PendingImport {
+    path: PathBuf,
+    /// Location of the `Import` directive.
+    location: Location,
+}
 
-        // Texture name to index.
-        let mut named_textures: HashMap<String, usize> = HashMap::default();
-        let mut named_materials: HashMap<String, usize> = HashMap::default();
-        let mut named_mediums: HashMap<String, usize> = HashMap::default();
-        let mut named_objects: HashMap<String, usize> = HashMap::default();
+/// Scene loading state.
+struct Loader<'a> {
+    scene: Scene,
+    /// Indices of the entities in `scene` start from these values.
+    base: Counts,
+
+    working_directory: Option<PathBuf>,
+
+    /// Stack of files being parsed, the last one is the current file.
+    sources: Vec<Source<'a>>,
+    /// Files that import the current one, directly or through other imports.
+    importers: Vec<PathBuf>,
+
+    current_state: State<'a>,
+    states_stack: Vec<State<'a>>,
+    is_world_block: bool,
+    /// Sampler and integrator seeds that default to `Option "integer seed"`,
+    /// which may come later, so they are set at `WorldBegin`.
+    default_seeds: DefaultSeeds,
+
+    named_coord_systems: HashMap<String, TransformSet>,
+
+    named_textures: NamedTextures,
+    named_materials: HashMap<String, usize>,
+    named_mediums: HashMap<String, usize>,
+    named_objects: HashMap<String, usize>,
+
+    /// Consecutive `Import` directives, loaded in parallel.
+    pending_imports: Vec<PendingImport>,
+    /// Whether this loader reads an imported file into a partial scene.
+    is_import: bool,
+    /// Names referenced before they were defined, only used by imports.
+    unresolved: Vec<Unresolved>,
+
+    // Because data from included files might end up in cached parameters,
+    // we should keep the file data around until scene loading is done.
+    // This field must be declared last, so it's dropped after everything that references it.
+    includes: Vec<String>,
+}
 
-        // Because data from included files might end up in cached parameters,
-        // we should keep the file data around until scene loading is done.
-        let mut includes = Vec::new();
+impl<'a> Loader<'a> {
+    fn new(data: &'a str, working_directory: Option<&Path>, path: Option<&Path>) -> Self {
+        Loader {
+            scene: Scene {
+                working_directory: working_directory.map(Path::to_path_buf),
+                ..Scene::default()
+            },
+            base: Counts::default(),
+            working_directory: working_directory.map(Path::to_path_buf),
+            sources: vec![Source {
+                parser: Parser::new(data),
+                path: path.map(Path::to_path_buf),
+                import: None,
+            }],
+            importers: Vec::new(),
+            current_state: State::default(),
+            states_stack: Vec::new(),
+            is_world_block: false,
+            default_seeds: DefaultSeeds::default(),
+            named_coord_systems: HashMap::default(),
+            named_textures: NamedTextures::default(),
+            named_materials: HashMap::default(),
+            named_mediums: HashMap::default(),
+            named_objects: HashMap::default(),
+            pending_imports: Vec::new(),
+            is_import: false,
+            unresolved: Vec::new(),
+            includes: Vec::new(),
+        }
+    }
 
-        while let Some(parser) = parsers.last_mut() {
+    fn run(&mut self) -> Result<()> {
+        while let Some(source) = self.sources.last_mut() {
             // Fetch next element.
-            let element = match parser.parse_next() {
+            let result = source.parser.parse_next();
+
+            // Element might push a new file to the stack, so remember the current one.
+            let index = self.sources.len() - 1;
+
+            let element = match result {
                 Ok(element) => element,
-                Err(err) if matches!(err, Error::EndOfFile) => {
+                Err(Error::EndOfFile) => {
+                    let result = self.flush_imports().and_then(|_| self.finish_import(index));
+
+                    if let Err(err) = result {
+                        return Err(self.annotate(index, err));
+                    }
+
                     // Remove parser from the stack.
-                    parsers.pop();
+                    self.sources.pop();
                     continue;
                 }
-                Err(err) => return Err(err),
+                Err(err) => return Err(self.annotate(index, err)),
             };
 
-            match element {
-                Element::AttributeBegin => {
-                    states_stack.push(current_state.clone());
-                }
-                Element::AttributeEnd => match states_stack.pop() {
-                    Some(state) => current_state = state,
-                    None => return Err(Error::TooManyEndAttributes),
-                },
-                Element::Attribute { target, params } => match target {
-                    "shape" => current_state.shape_params.extend(&params),
-                    "light" => current_state.light_params.extend(&params),
-                    "material" => current_state.material_params.extend(&params),
-                    "medium" => current_state.medium_params.extend(&params),
-                    "texture" => current_state.texture_params.extend(&params),
-                    _ => unimplemented!(),
-                },
-                Element::ReverseOrientation => {
-                    current_state.reverse_orientation = !current_state.reverse_orientation;
-                }
-                Element::Translate { v } => {
-                    current_state.transform_matrix *= Mat4::from_translation(Vec3::from(v))
-                }
-                Element::Identity => {
-                    current_state.transform_matrix = Mat4::IDENTITY;
-                }
-                // Transform resets the CTM to the specified matrix.
-                Element::Transform { m } => {
-                    current_state.transform_matrix = Mat4::from_cols_array(&m);
-                }
-                // An arbitrary transformation to multiply the CTM with can be specified using ConcatTransform
-                Element::ConcatTransform { m } => {
-                    current_state.transform_matrix *= Mat4::from_cols_array(&m);
-                }
-                Element::Scale { v } => {
-                    current_state.transform_matrix *= Mat4::from_scale(Vec3::from(v));
-                }
-                Element::Rotate { angle, v } => {
-                    current_state.transform_matrix *= Mat4::from_axis_angle(Vec3::from(v), angle);
-                }
-                Element::LookAt { eye, look_at, up } => {
-                    current_state.transform_matrix *=
-                        Mat4::look_at_lh(Vec3::from(eye), Vec3::from(look_at), Vec3::from(up));
-                }
-                // A name can be associated with the CTM using the CoordinateSystem directive.
-                Element::CoordinateSystem { name } => {
-                    named_coord_systems.insert(name.to_string(), current_state.transform_matrix);
-                }
-                // The CTM can later be reset to the recorded transformation using CoordSysTransform.
-                Element::CoordSysTransform { name } => {
-                    match named_coord_systems.get(name).copied() {
-                        Some(mat) => current_state.transform_matrix = mat,
-                        None => {
-                            // TODO: Material not found, return error.
-                            unimplemented!()
-                        }
-                    }
-                }
-                // The Camera directive specifies the camera used for viewing the scene.
-                Element::Camera { ty, params } => {
-                    let camera_from_world = current_state.transform_matrix;
-                    // TODO: Support transformStartTime and transformEndTime
-                    let world_from_camera = camera_from_world.inverse();
+            // Consecutive imports are loaded in parallel, anything else
+            // has to wait until they are merged to preserve source order.
+            let result = match element {
+                Element::Import(..) => self.process(element),
+                element => self.flush_imports().and_then(|_| self.process(element)),
+            };
 
-                    // pbrt automatically records the camera transformation matrix in the "camera" named coordinate system.
-                    // This can be useful for placing light sources with respect to the camera, for example.
+            if let Err(err) = result {
+                return Err(self.annotate(index, err));
+            }
+        }
 
-                    // TODO: Fix key
-                    named_coord_systems.insert("camera".to_string(), world_from_camera);
+        if !self.states_stack.is_empty() {
+            return Err(Error::MissingEndAttribute);
+        }
+        if !self.is_world_block {
+            return Err(Error::MissingWorldBegin);
+        }
 
-                    let camera = Camera::new(ty, params)?;
+        Ok(())
+    }
 
-                    let entity = CameraEntity {
-                        params: camera,
-                        // transform: world_from_camera,
-                        transform: camera_from_world,
-                    };
+    /// If the file at `index` was imported, restore the importer's graphics state.
+    fn finish_import(&mut self, index: usize) -> Result<()> {
+        let Some(import) = self.sources[index].import.take() else {
+            return Ok(());
+        };
 
-                    scene.camera = Some(entity);
-                }
-                Element::Film { ty, params } => {
-                    debug_assert!(scene.film.is_none());
-                    let film = Film::new(ty, params)?;
-                    scene.film = Some(film);
-                }
-                Element::Integrator { ty, params } => {
-                    debug_assert!(scene.integrator.is_none());
-                    let integrator = Integrator::new(ty, params)?;
-                    scene.integrator = Some(integrator);
-                }
-                Element::Accelerator { ty, params } => {
-                    debug_assert!(scene.accelerator.is_none());
-                    let accelerator = Accelerator::new(ty, params)?;
-                    scene.accelerator = Some(accelerator);
-                }
-                Element::PixelFilter { ty, params } => {
-                    let pixel_filter = PixelFilter::new(ty, params)?;
-                    debug_assert!(scene.pixel_filter.is_none());
-                    scene.pixel_filter = Some(pixel_filter);
-                }
-                Element::ColorSpace { .. } => {
-                    todo!("Support color space");
-                }
-                Element::Sampler { ty, params } => {
-                    let sampler = Sampler::new(ty, params)?;
-                    debug_assert!(scene.sampler.is_none());
-                    scene.sampler = Some(sampler);
-                }
-                // pbrt supports animated transformations by allowing two transformation
-                // matrices to be specified at different times.
-                Element::TransformTimes { start, end } => {
-                    // TransformTimes directive must be outside of the world definition block,
-                    if is_world_block {
-                        return Err(Error::WorldAlreadyStarted);
-                    }
+        // Imported files must close all attribute blocks they open.
+        if self.states_stack.len() != import.depth {
+            return Err(Error::MissingEndAttribute);
+        }
 
-                    scene.start_time = start;
-                    scene.end_time = end;
-                }
-                // ActiveTransform directive indicates whether subsequent directives that modify the CTM should
-                // apply to the transformation at the starting time, the transformation at the ending time, or both.
-                Element::ActiveTransform { .. } => {
-                    todo!("Support animated transformations")
-                }
-                // Include behaves similarly to the #include directive in C++: parsing of the current file is suspended,
-                // the specified file is parsed in its entirety, and only then does parsing of the current file resume.
-                // Its effect is equivalent to direct text substitution of the included file.
-                Element::Include(path) => {
-                    // If the filename given to a Include or Import statement is not an absolute path,
-                    // its path is interpreted as being relative to the directory of the initial file being parsed as
-                    // specified with pbrt's command-line arguments.
-                    let path = Path::new(path);
-
-                    let full_path;
-
-                    let path = if path.is_absolute() {
-                        path
-                    } else {
-                        full_path = match working_directory {
-                            Some(directory) => directory.join(path),
-                            // Use current working directory if not provided
-                            None => env::current_dir()?.join(path),
-                        };
-
-                        full_path.as_path()
-                    };
-
-                    let data = fs::read_to_string(path)?;
-
-                    // Included files may be compressed using gzip.
-                    // If a scene file name has a ".gz" suffix, then pbrt will automatically decompress it as it is read from disk.
-                    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
-                        if ext.ends_with(".gz") {
-                            todo!("Gzip compression");
-                        }
-                    }
+        // Shapes defined by the imported file still belong to the active object.
+        let shape_count = self.current_state.shape_count;
 
-                    // In Rust, String is heap allocated type, so it's safe to keep a pointer to
-                    // the raw data and move the String object (like push it to the vector).
-                    let raw = data.as_bytes();
-                    let raw_len = raw.len();
-                    let raw_ptr = raw.as_ptr();
+        self.current_state = import.state;
+        self.current_state.shape_count = shape_count;
+        self.named_coord_systems = import.named_coord_systems;
 
-                    includes.push(data);
+        Ok(())
+    }
 
-                    // TODO: is there a better way?
-                    let parser = Parser::new(unsafe {
-                        let byte_slice = slice::from_raw_parts(raw_ptr, raw_len);
-                        str::from_utf8_unchecked(byte_slice)
-                    });
-                    parsers.push(parser);
-                }
-                Element::Import(..) => {
-                    todo!("Support imports")
-                }
-                Element::WorldBegin => {
-                    is_world_block = true;
-                    current_state.transform_matrix = Mat4::IDENTITY;
-                }
-                Element::Option(param) => {
-                    scene.options.apply(param)?;
-                }
-                Element::Texture {
-                    name,
-                    ty,
-                    class,
-                    mut params,
-                } => {
-                    params.extend(&current_state.texture_params);
-                    let texture = Texture::new(name, ty, class, params)?;
+    /// Pop graphics state from the attributes stack.
+    ///
+    /// Imported files are not allowed to pop states pushed by the importer.
+    fn pop_state(&mut self) -> Option<State<'a>> {
+        let floor = self
+            .sources
+            .iter()
+            .rev()
+            .find_map(|source| source.import.as_ref().map(|import| import.depth))
+            .unwrap_or(0);
+
+        if self.states_stack.len() > floor {
+            self.states_stack.pop()
+        } else {
+            None
+        }
+    }
 
-                    let index = scene.textures.len();
-                    scene.textures.push(texture);
+    /// Resolve a path given to an `Include` or `Import` directive.
+    fn resolve_path(&self, path: &str) -> Result<PathBuf> {
+        // If the filename given to a Include or Import statement is not an absolute path,
+        // its path is interpreted as being relative to the directory of the initial file being parsed as
+        // specified with pbrt's command-line arguments.
+        let path = Path::new(path);
+
+        let path = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            match &self.working_directory {
+                Some(directory) => directory.join(path),
+                // Use current working directory if not provided
+                None => env::current_dir()?.join(path),
+            }
+        };
 
-                    named_textures.insert(name.to_string(), index);
-                }
-                // The Material directive specifies the current material, which then applies for all subsequent
-                // shape definitions until the end of the current attribute scope or until a new material is defined.
-                Element::Material { ty, mut params } => {
-                    params.extend(&current_state.material_params);
-                    let material = Material::new(ty, params, &named_textures)?;
+        Ok(path)
+    }
 
-                    let index = scene.materials.len();
-                    scene.materials.push(material);
+    /// Paths of the files that lead to the current one, starting with the top level file.
+    fn file_chain(&self) -> impl Iterator<Item = &PathBuf> {
+        self.importers.iter().chain(
+            self.sources
+                .iter()
+                .filter_map(|source| source.path.as_ref()),
+        )
+    }
 
-                    current_state.material_index = Some(index);
-                }
-                Element::MakeNamedMaterial { name, mut params } => {
-                    params.extend(&current_state.material_params);
-                    let material = Material::new(name, params, &named_textures)?;
+    /// Read a file and make it the current one.
+    fn push_file(&mut self, path: PathBuf, import: Option<Import<'a>>) -> Result<()> {
+        // A file that includes itself would be read over and over.
+        if self.file_chain().any(|parent| *parent == path) {
+            return Err(Error::RecursiveInclude);
+        }
 
-                    let index = scene.materials.len();
-                    scene.materials.push(material);
+        // Included files may be compressed using gzip.
+        // If a scene file name has a ".gz" suffix, then pbrt will automatically decompress it as it is read from disk.
+        let data = file::read_to_string(&path)?;
 
-                    named_materials.insert(name.to_string(), index);
-                }
-                Element::NamedMaterial { name } => {
-                    // TODO: handle material not found case.
-                    if !named_materials.contains_key(name) {
-                        return Err(Error::NotFound);
-                    }
-                    current_state.material_index = named_materials.get(name).copied();
-                }
-                Element::LightSource { ty, params } => {
-                    // When a light source is created, the current exterior medium is used for rays leaving the light
-                    // when bidirectional light transport algorithms are used.
-                    //
-                    // The user is responsible for specifying media in a way such that rays reaching lights are in the same medium
-                    // as rays leaving those lights.
+        // In Rust, String is heap allocated type, so it's safe to keep a pointer to
+        // the raw data and move the String object (like push it to the vector).
+        let raw = data.as_bytes();
+        let raw_len = raw.len();
+        let raw_ptr = raw.as_ptr();
 
-                    // TODO: Handle current_outside_medium
+        self.includes.push(data);
 
-                    let light = Light::new(ty, params)?;
-                    scene.lights.push(light);
-                }
-                // After an AreaLightSource directive, all subsequent shapes emit light
-                // from their surfaces according to the distribution defined by the given
-                // area light implementation.
-                Element::AreaLightSource { ty, mut params } => {
-                    params.extend(&current_state.light_params);
-                    let area_light = AreaLight::new(ty, params)?;
-
-                    let index = scene.area_lights.len();
-                    scene.area_lights.push(area_light);
-
-                    // The current area light is saved and restored inside attribute blocks;
-                    // typically area light definitions are inside an AttributeBegin/AttributeEnd
-                    // pair in order to control the shapes that they are applied to.
-                    current_state.area_light_index = Some(index);
-                }
-                Element::Shape {
-                    name: ty,
-                    mut params,
-                } => {
-                    params.extend(&current_state.shape_params);
-                    let shape = Shape::new(ty, params)?;
-
-                    // When a shape is created, the current interior medium is assumed to be the medium inside the shape,
-                    // and the current exterior medium is assumed to be the medium outside the shape.
-                    // TODO: handle mediums
-
-                    let entity = ShapeEntity {
-                        params: shape,
-                        transform: current_state.transform_matrix,
-                        reverse_orientation: current_state.reverse_orientation,
-                        material_index: current_state.material_index,
-                        area_light_index: current_state.area_light_index,
-                    };
-
-                    scene.shapes.push(entity);
-
-                    // If inside of ObjectBegin/ObjectEnd, count the number of shapes.
-                    if current_state.active_object.is_some() {
-                        current_state.shape_count += 1;
-                    }
-                }
-                Element::ObjectBegin { name } => {
-                    if current_state.active_object.is_some() {
-                        // Nested objects are not allowed
-                        return Err(Error::NestedObjects);
-                    }
+        // TODO: is there a better way?
+        let parser = Parser::new(unsafe {
+            let byte_slice = slice::from_raw_parts(raw_ptr, raw_len);
+            str::from_utf8_unchecked(byte_slice)
+        });
 
-                    states_stack.push(current_state.clone());
+        self.sources.push(Source {
+            parser,
+            path: Some(path),
+            import,
+        });
 
-                    let object = Object {
-                        name: name.to_string(),
-                        shape_start: None,
-                        shape_count: 0,
-                        object_to_instance: current_state.transform_matrix,
-                    };
+        Ok(())
+    }
 
-                    let index = scene.objects.len();
-                    scene.objects.push(object);
+    /// Returns the chain of includes that led to the file at `index`.
+    fn include_chain(&self, index: usize) -> Vec<IncludeSite> {
+        // Each parent file is suspended right at its Include directive.
+        self.sources[..index]
+            .iter()
+            .rev()
+            .filter_map(|source| {
+                let span = source.parser.directive_span()?;
+
+                Some(IncludeSite {
+                    path: source.path.clone(),
+                    line: span.line,
+                })
+            })
+            .collect()
+    }
 
-                    current_state.active_object = Some(index);
-                    named_objects.insert(name.to_string(), index);
-                }
-                Element::ObjectEnd => {
-                    let object_index = current_state
-                        .active_object
-                        .take()
-                        .ok_or(Error::ElementNotAllowed)?;
+    /// Annotate an error with the position within the file at `index` and
+    /// the chain of includes that led to it.
+    fn annotate(&self, index: usize, err: Error) -> Error {
+        let mut err = self.sources[index].annotate(err);
 
-                    let object = &mut scene.objects[object_index];
+        let location = err.location_mut();
 
-                    object.shape_count = current_state.shape_count;
+        if location.included_from.is_empty() {
+            location.included_from = self.include_chain(index);
+        }
 
-                    if object.shape_count > 0 {
-                        object.shape_start = Some(scene.shapes.len() - object.shape_count)
-                    }
+        err
+    }
 
-                    current_state.shape_count = 0;
-                    current_state.active_object = None;
+    /// Returns the number of entities, including the ones of the importing scene.
+    fn counts(&self) -> Counts {
+        Counts {
+            textures: self.base.textures + self.scene.textures.len(),
+            materials: self.base.materials + self.scene.materials.len(),
+            area_lights: self.base.area_lights + self.scene.area_lights.len(),
+            mediums: self.base.mediums + self.scene.mediums.len(),
+            shapes: self.base.shapes + self.scene.shapes.len(),
+            objects: self.base.objects + self.scene.objects.len(),
+            unresolved: self.base.unresolved + self.unresolved.len(),
+        }
+    }
 
-                    match states_stack.pop() {
-                        Some(state) => current_state = state,
-                        None => return Err(Error::ElementNotAllowed),
-                    }
-                }
-                Element::ObjectInstance { name } => {
-                    let Some(object_index) = named_objects.get(name).copied() else {
-                        return Err(Error::NotFound)
-                    };
-
-                    let instance = Instance {
-                        // The current transformation matrix defines the world from instance space transformation.
-                        instance_to_world: current_state.transform_matrix,
-                        object_index,
-                        area_light_index: current_state.area_light_index,
-                        reverse_orientation: current_state.reverse_orientation,
-                    };
-
-                    scene.instances.push(instance);
-                }
-                // MakeNamedMedium associates a user-specified name with medium scattering characteristics.
-                Element::MakeNamedMedium { name, mut params } => {
-                    params.extend(&current_state.medium_params);
-                    let medium = Medium::new(params)?;
+    /// Returns the location of the directive being loaded.
+    fn location(&self) -> Location {
+        let index = self.sources.len() - 1;
 
-                    let index = scene.mediums.len();
-                    scene.mediums.push(medium);
+        let mut location = self.sources[index].parser.location().unwrap_or_default();
+        location.path = self.sources[index].path.clone();
+        location.included_from = self.include_chain(index);
 
-                    named_mediums.insert(name.to_string(), index);
-                }
-                // MediumInterface directive can be used to specify the current "interior" and "exterior" media.
-                // A vacuum—no participating media—is represented by empty string "".
-                Element::MediumInterface { interior, exterior } => {
-                    current_state.current_inside_medium = Some(interior);
-                    current_state.current_outside_medium = Some(exterior);
+        location
+    }
+
+    fn names(&self, namespace: Namespace) -> &HashMap<String, usize> {
+        match namespace {
+            Namespace::Texture(ty) => self.named_textures.names(ty),
+            Namespace::Material => &self.named_materials,
+            Namespace::Medium => &self.named_mediums,
+            Namespace::Object => &self.named_objects,
+        }
+    }
+
+    fn names_mut(&mut self, namespace: Namespace) -> &mut HashMap<String, usize> {
+        match namespace {
+            Namespace::Texture(ty) => self.named_textures.names_mut(ty),
+            Namespace::Material => &mut self.named_materials,
+            Namespace::Medium => &mut self.named_mediums,
+            Namespace::Object => &mut self.named_objects,
+        }
+    }
+
+    /// Returns the index of a named entity.
+    ///
+    /// Within imported files, names that are not defined yet get placeholder indices.
+    fn find_name(&mut self, namespace: Namespace, name: &str) -> Result<usize> {
+        match self.names(namespace).get(name) {
+            Some(index) if !self.is_inherited_placeholder(*index) => Ok(*index),
+            _ if self.is_import => Ok(self.defer(namespace, name, self.location())),
+            _ => Err(Error::NotFound),
+        }
+    }
+
+    /// Give placeholder indices to textures referenced by `params` that are not defined yet.
+    ///
+    /// The expected texture type is not known here, so both namespaces get a placeholder.
+    fn defer_textures(&mut self, params: &ParamList) {
+        if !self.is_import {
+            return;
+        }
+
+        for param in params.iter().filter(|param| param.ty == ParamType::Texture) {
+            let Some(name) = param.strings().next() else {
+                continue;
+            };
+
+            for ty in [TextureType::Float, TextureType::Spectrum] {
+                let namespace = Namespace::Texture(ty);
+
+                match self.names(namespace).get(name) {
+                    Some(index) if !self.is_inherited_placeholder(*index) => {}
+                    _ => {
+                        self.defer(namespace, name, self.location());
+                    }
                 }
             }
         }
+    }
+
+    /// Returns true if `index` is a placeholder of the importing file.
+    ///
+    /// The name might be defined by a preceding import of the same batch by now,
+    /// so it has to be resolved again.
+    fn is_inherited_placeholder(&self, index: usize) -> bool {
+        placeholder_number(index).map_or(false, |number| number < self.base.unresolved)
+    }
+
+    /// Record an unresolved name, returns its placeholder index.
+    fn defer(&mut self, namespace: Namespace, name: &str, location: Location) -> usize {
+        let index = placeholder(self.counts().unresolved);
 
-        debug_assert!(states_stack.is_empty());
-        debug_assert!(is_world_block);
+        self.unresolved.push(Unresolved {
+            namespace,
+            name: name.to_string(),
+            location,
+        });
+        self.names_mut(namespace).insert(name.to_string(), index);
 
-        Ok(scene)
+        index
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Resolve a name an imported file referenced before it was defined.
+    fn resolve(&mut self, unresolved: &Unresolved, import: &Location) -> Result<usize> {
+        let Unresolved {
+            namespace, name, ..
+        } = unresolved;
 
-    use tempdir::TempDir;
+        if let Some(index) = self.names(*namespace).get(name) {
+            return Ok(*index);
+        }
 
-    #[test]
-    fn test_includes() -> Result<()> {
-        let temp_dir = TempDir::new("pbrt-includes-")?;
-        let temp_path = temp_dir.path();
+        if self.is_import {
+            // Might be defined by an import preceding the importing file.
+            let mut location = unresolved.location.clone();
+            Self::import_location(&mut location, import);
 
-        fs::write(temp_path.join("1.pbrt"), "Shape \"sphere\"")?;
-        fs::write(temp_path.join("2.pbrt"), "Include \"1.pbrt\" ")?;
-        fs::write(temp_path.join("3.pbrt"), "Include \"2.pbrt\" ")?;
-        fs::write(temp_path.join("4.pbrt"), "Include \"3.pbrt\" ")?;
+            return Ok(self.defer(*namespace, name, location));
+        }
 
-        fs::write(
-            temp_path.join("main.pbrt"),
-            r#"
-WorldBegin
+        let err = match namespace {
+            Namespace::Texture(ty) => self.named_textures.find(name, *ty).err(),
+            _ => None,
+        }
+        .unwrap_or(Error::NotFound);
 
-Include "4.pbrt" # Include file with nexted includes
-Include "1.pbrt" # Include shap directly
+        Err(err.with_location(&unresolved.location))
+    }
 
-        "#,
-        )?;
+    /// Queue an `Import` directive of the current file for loading.
+    fn queue_import(&mut self, path: &str) -> Result<()> {
+        let path = self.resolve_path(path)?;
+        let location = self.location();
 
-        let scene = Scene::from_file(temp_path.join("main.pbrt"))?;
+        self.pending_imports.push(PendingImport { path, location });
 
-        debug_assert_eq!(scene.shapes.len(), 2);
+        Ok(())
+    }
+
+    /// Load queued imports in parallel and merge them in source order.
+    fn flush_imports(&mut self) -> Result<()> {
+        if self.pending_imports.is_empty() {
+            return Ok(());
+        }
+
+        let pending = mem::take(&mut self.pending_imports);
+        let base = self.counts();
+
+        let workers = thread::available_parallelism()
+            .map_or(1, |count| count.get())
+            .min(pending.len());
+
+        let next = AtomicUsize::new(0);
+
+        let mut results = thread::scope(|scope| {
+            let handles = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut results = Vec::new();
+
+                        loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(import) = pending.get(index) else {
+                                break;
+                            };
+
+                            results.push((index, self.load_import(&import.path, base)));
+                        }
+
+                        results
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|err| panic::resume_unwind(err))
+                })
+                .collect::<Vec<_>>()
+        });
+
+        results.sort_by_key(|(index, _)| *index);
+
+        for (import, (_, result)) in pending.iter().zip(results) {
+            result
+                .and_then(|partial| self.merge(partial, &import.location))
+                .map_err(|err| Self::import_error(err, &import.location))?;
+        }
 
         Ok(())
     }
 
-    #[test]
-    fn test_instancing() -> Result<()> {
-        let data = r#"
-WorldBegin
+    /// Load an imported file into a partial scene, starting with the current graphics state.
+    fn load_import(&self, path: &Path, base: Counts) -> Result<Loader<'a>> {
+        // Count shapes added to the active object (if any) by the imported file only.
+        let state = State {
+            shape_count: 0,
+            ..self.current_state.clone()
+        };
+
+        let mut loader = Loader {
+            scene: Scene::default(),
+            base,
+            working_directory: self.working_directory.clone(),
+            sources: Vec::new(),
+            importers: self.file_chain().cloned().collect(),
+            current_state: state.clone(),
+            states_stack: Vec::new(),
+            is_world_block: true,
+            default_seeds: DefaultSeeds::default(),
+            named_coord_systems: self.named_coord_systems.clone(),
+            named_textures: self.named_textures.clone(),
+            named_materials: self.named_materials.clone(),
+            named_mediums: self.named_mediums.clone(),
+            named_objects: self.named_objects.clone(),
+            pending_imports: Vec::new(),
+            is_import: true,
+            unresolved: Vec::new(),
+            includes: Vec::new(),
+        };
+
+        let import = Import {
+            state,
+            depth: 0,
+            named_coord_systems: HashMap::default(),
+        };
+
+        loader.push_file(path.to_path_buf(), Some(import))?;
+        loader.run()?;
+
+        Ok(loader)
+    }
 
-ObjectBegin "foo"
-Shape "sphere"
-Shape "sphere"
-ObjectEnd
+    /// Attach the location of an `Import` directive to an error from the imported file.
+    fn import_error(mut err: Error, import: &Location) -> Error {
+        // Failed to read the file, point to the directive itself.
+        if err.location().and_then(|location| location.span).is_none() {
+            return err.with_location(import);
+        }
 
-ObjectInstance "foo"
-Translate 1 0 0
-ObjectInstance "foo"
-        "#;
+        Self::import_location(err.location_mut(), import);
+        err
+    }
 
-        let scene = Scene::load(data, None)?;
+    /// Add an `Import` directive to the chain of includes of a location within the imported file.
+    fn import_location(location: &mut Location, import: &Location) {
+        if let Some(span) = import.span {
+            location.included_from.push(IncludeSite {
+                path: import.path.clone(),
+                line: span.line,
+            });
+        }
 
-        assert_eq!(scene.shapes.len(), 2);
+        location
+            .included_from
+            .extend(import.included_from.iter().cloned());
+    }
 
-        assert!(matches!(scene.shapes[0].params, Shape::Sphere { .. }));
-        assert!(matches!(scene.shapes[0].params, Shape::Sphere { .. }));
+    /// Append a partial scene loaded from an imported file.
+    fn merge(&mut self, partial: Loader<'a>, import: &Location) -> Result<()> {
+        let base = partial.base;
 
-        assert_eq!(scene.objects.len(), 1);
+        // Names referenced by the imported file are resolved first.
+        let mut resolved = Vec::with_capacity(partial.unresolved.len());
+        for unresolved in &partial.unresolved {
+            let result = self.resolve(unresolved, import);
 
-        let object = &scene.objects[0];
-        assert_eq!(&object.name, "foo");
-        assert_eq!(object.shape_start, Some(0));
-        assert_eq!(object.shape_count, 2);
+            // Textures get placeholders of both types, only the used one has to exist.
+            if result.is_err() && !matches!(unresolved.namespace, Namespace::Texture(_)) {
+                return result.map(|_| ());
+            }
 
-        assert_eq!(scene.instances.len(), 2);
+            resolved.push(result);
+        }
 
-        let inst1 = &scene.instances[0];
-        assert_eq!(inst1.object_index, 0);
+        let missing = Cell::new(None);
+        let counts = self.counts();
+
+        // Indices below the base refer to the entities of the importing scene.
+        let relocate = |index: usize, base: usize, count: usize| match placeholder_number(index) {
+            // Placeholders of the importing scene are resolved when it is merged.
+            Some(number) if number < partial.base.unresolved => index,
+            Some(number) => match &resolved[number - partial.base.unresolved] {
+                Ok(index) => *index,
+                Err(_) => {
+                    missing.set(missing.get().or(Some(number - partial.base.unresolved)));
+                    index
+                }
+            },
+            None if index >= base => index - base + count,
+            None => index,
+        };
+
+        let texture = |index| relocate(index, base.textures, counts.textures);
+        let material = |index| relocate(index, base.materials, counts.materials);
+        let area_light = |index| relocate(index, base.area_lights, counts.area_lights);
+        let medium = |index| relocate(index, base.mediums, counts.mediums);
+        let shape = |index| relocate(index, base.shapes, counts.shapes);
+        let object = |index| relocate(index, base.objects, counts.objects);
+
+        let scene = partial.scene;
+
+        self.scene
+            .textures
+            .extend(scene.textures.into_iter().map(|mut entity| {
+                for index in entity.textures_mut() {
+                    *index = texture(*index);
+                }
+                entity
+            }));
+        self.scene
+            .materials
+            .extend(scene.materials.into_iter().map(|mut material| {
+                for index in material.textures_mut() {
+                    *index = texture(*index);
+                }
+                material
+            }));
+        self.scene
+            .lights
+            .extend(scene.lights.into_iter().map(|mut entity| {
+                entity.medium_index = entity.medium_index.map(medium);
+                entity
+            }));
+        self.scene.area_lights.extend(scene.area_lights);
+        self.scene.mediums.extend(scene.mediums);
+
+        self.scene
+            .shapes
+            .extend(scene.shapes.into_iter().map(|mut entity| {
+                entity.material_index = entity.material_index.map(material);
+                entity.area_light_index = entity.area_light_index.map(area_light);
+                entity.inside_medium_index = entity.inside_medium_index.map(medium);
+                entity.outside_medium_index = entity.outside_medium_index.map(medium);
+                entity
+            }));
+
+        self.scene
+            .objects
+            .extend(scene.objects.into_iter().map(|mut entity| {
+                entity.shape_start = entity.shape_start.map(shape);
+                entity
+            }));
+
+        self.scene
+            .instances
+            .extend(scene.instances.into_iter().map(|mut instance| {
+                instance.object_index = object(instance.object_index);
+                instance.area_light_index = instance.area_light_index.map(area_light);
+                instance
+            }));
+
+        // Only names defined by the imported file are merged back.
+        let merge_names = |names: &mut HashMap<String, usize>,
+                           imported: HashMap<String, usize>,
+                           base: usize,
+                           relocate: &dyn Fn(usize) -> usize| {
+            for (name, index) in imported {
+                if index >= base && placeholder_number(index).is_none() {
+                    names.insert(name, relocate(index));
+                }
+            }
+        };
+
+        merge_names(
+            &mut self.named_textures.float,
+            partial.named_textures.float,
+            base.textures,
+            &texture,
+        );
+        merge_names(
+            &mut self.named_textures.spectrum,
+            partial.named_textures.spectrum,
+            base.textures,
+            &texture,
+        );
+        merge_names(
+            &mut self.named_materials,
+            partial.named_materials,
+            base.materials,
+            &material,
+        );
+        merge_names(
+            &mut self.named_mediums,
+            partial.named_mediums,
+            base.mediums,
+            &medium,
+        );
+        merge_names(
+            &mut self.named_objects,
+            partial.named_objects,
+            base.objects,
+            &object,
+        );
+
+        // Shapes defined by the imported file still belong to the active object.
+        self.current_state.shape_count += partial.current_state.shape_count;
+
+        match missing.get() {
+            Some(number) => resolved.swap_remove(number).map(|_| ()),
+            None => Ok(()),
+        }
+    }
 
-        let inst2 = &scene.instances[1];
-        assert_eq!(inst1.object_index, 0);
-        assert_ne!(inst1.instance_to_world, inst2.instance_to_world);
+    /// Apply a single element to the scene.
+    fn process(&mut self, element: Element<'a>) -> Result<()> {
+        match element {
+            Element::AttributeBegin => {
+                self.states_stack.push(self.current_state.clone());
+            }
+            Element::AttributeEnd => match self.pop_state() {
+                Some(state) => {
+                    // Shapes defined inside the attribute block still belong to the active object.
+                    let shape_count = self.current_state.shape_count;
+
+                    self.current_state = state;
+                    self.current_state.shape_count = shape_count;
+                }
+                None => return Err(Error::TooManyEndAttributes),
+            },
+            Element::Attribute { target, params } => match target {
+                "shape" => self.current_state.shape_params.extend(&params),
+                "light" => self.current_state.light_params.extend(&params),
+                "material" => self.current_state.material_params.extend(&params),
+                "medium" => self.current_state.medium_params.extend(&params),
+                "texture" => self.current_state.texture_params.extend(&params),
+                _ => return Err(Error::InvalidObjectType),
+            },
+            Element::ReverseOrientation => {
+                self.current_state.reverse_orientation = !self.current_state.reverse_orientation;
+            }
+            Element::Translate { v } => {
+                let translation = Mat4::from_translation(Vec3::from(v));
+                self.current_state
+                    .for_active_transforms(|m| *m *= translation);
+            }
+            Element::Identity => {
+                self.current_state
+                    .for_active_transforms(|m| *m = Mat4::IDENTITY);
+            }
+            // Transform resets the CTM to the specified matrix.
+            Element::Transform { m } => {
+                let transform = Mat4::from_cols_array(&m);
+                self.current_state.for_active_transforms(|m| *m = transform);
+            }
+            // An arbitrary transformation to multiply the CTM with can be specified using ConcatTransform
+            Element::ConcatTransform { m } => {
+                let transform = Mat4::from_cols_array(&m);
+                self.current_state
+                    .for_active_transforms(|m| *m *= transform);
+            }
+            Element::Scale { v } => {
+                let scale = Mat4::from_scale(Vec3::from(v));
+                self.current_state.for_active_transforms(|m| *m *= scale);
+            }
+            Element::Rotate { angle, v } => {
+                let rotation = Mat4::from_axis_angle(Vec3::from(v), angle);
+                self.current_state.for_active_transforms(|m| *m *= rotation);
+            }
+            Element::LookAt { eye, look_at, up } => {
+                let look_at =
+                    Mat4::look_at_lh(Vec3::from(eye), Vec3::from(look_at), Vec3::from(up));
+                self.current_state.for_active_transforms(|m| *m *= look_at);
+            }
+            // A name can be associated with the CTM using the CoordinateSystem directive.
+            Element::CoordinateSystem { name } => {
+                self.named_coord_systems
+                    .insert(name.to_string(), self.current_state.ctm);
+            }
+            // The CTM can later be reset to the recorded transformation using CoordSysTransform.
+            Element::CoordSysTransform { name } => {
+                match self.named_coord_systems.get(name).copied() {
+                    Some(ctm) => self.current_state.ctm = ctm,
+                    None => return Err(Error::InvalidMatrixName),
+                }
+            }
+            // The Camera directive specifies the camera used for viewing the scene.
+            Element::Camera { ty, params } => {
+                let camera_from_world = self.current_state.ctm;
+                let world_from_camera = camera_from_world.map(|m| m.inverse());
+
+                // pbrt automatically records the camera transformation matrix in the "camera" named coordinate system.
+                // This can be useful for placing light sources with respect to the camera, for example.
+
+                // TODO: Fix key
+                self.named_coord_systems
+                    .insert("camera".to_string(), world_from_camera);
+
+                let camera = Camera::new(ty, params)?;
+
+                let entity = CameraEntity {
+                    params: camera,
+                    // transform: world_from_camera,
+                    transform: camera_from_world[0],
+                    transform_end: camera_from_world[1],
+                    medium_index: self.current_state.current_outside_medium,
+                };
+
+                self.scene.camera = Some(entity);
+            }
+            // A repeated rendering option replaces the previous one, as in pbrt.
+            Element::Film { ty, params } => {
+                let film = Film::new(ty, params)?;
+                self.scene.film = Some(film);
+            }
+            Element::Integrator { ty, params } => {
+                self.default_seeds.integrator = params.get("seed").is_none();
+                let integrator = Integrator::new(ty, params, &self.scene.options)?;
+                self.scene.integrator = Some(integrator);
+            }
+            Element::Accelerator { ty, params } => {
+                let accelerator = Accelerator::new(ty, params)?;
+                self.scene.accelerator = Some(accelerator);
+            }
+            Element::PixelFilter { ty, params } => {
+                let pixel_filter = PixelFilter::new(ty, params)?;
+                self.scene.pixel_filter = Some(pixel_filter);
+            }
+            // The color space is part of the graphics state and applies to subsequent RGB values.
+            Element::ColorSpace { ty } => {
+                self.current_state.color_space = ty.parse()?;
+            }
+            Element::Sampler { ty, params } => {
+                self.default_seeds.sampler = params.get("seed").is_none();
+                let sampler = Sampler::new(ty, params, &self.scene.options)?;
+                self.scene.sampler = Some(sampler);
+            }
+            // pbrt supports animated transformations by allowing two transformation
+            // matrices to be specified at different times.
+            Element::TransformTimes { start, end } => {
+                // TransformTimes directive must be outside of the world definition block,
+                if self.is_world_block {
+                    return Err(Error::WorldAlreadyStarted);
+                }
+
+                self.scene.start_time = start;
+                self.scene.end_time = end;
+            }
+            // ActiveTransform directive indicates whether subsequent directives that modify the CTM should
+            // apply to the transformation at the starting time, the transformation at the ending time, or both.
+            Element::ActiveTransform { ty } => {
+                self.current_state.active_transform = ty.parse()?;
+            }
+            // Include behaves similarly to the #include directive in C++: parsing of the current file is suspended,
+            // the specified file is parsed in its entirety, and only then does parsing of the current file resume.
+            // Its effect is equivalent to direct text substitution of the included file.
+            Element::Include(path) => {
+                let path = self.resolve_path(path)?;
+                self.push_file(path, None)?;
+            }
+            // Import is similar to Include, but the imported file may only define named entities
+            // (materials, textures, media, objects) and shapes; changes to the graphics state
+            // do not propagate back to the importing file.
+            Element::Import(path) => {
+                // Import is only allowed inside the world definition block.
+                if !self.is_world_block {
+                    return Err(Error::ElementNotAllowed);
+                }
+
+                // Consecutive imports are loaded in parallel once a different directive is reached.
+                self.queue_import(path)?;
+            }
+            Element::WorldBegin => {
+                // Options apply regardless of their position before the world block.
+                let seed = self.scene.options.seed;
+
+                if let Some(sampler) = &mut self.scene.sampler {
+                    if self.default_seeds.sampler {
+                        *sampler.seed_mut() = seed;
+                    }
+                }
+                if let Some(integrator) = &mut self.scene.integrator {
+                    if self.default_seeds.integrator {
+                        if let Some(integrator_seed) = integrator.seed_mut() {
+                            *integrator_seed = seed;
+                        }
+                    }
+                }
+
+                self.is_world_block = true;
+                self.current_state.ctm = TransformSet::default();
+                self.current_state.active_transform = ActiveTransform::All;
+            }
+            // Options are scene-wide and can only be set before the world block.
+            Element::Option(param) => {
+                if self.is_world_block {
+                    return Err(Error::ElementNotAllowed);
+                }
+
+                self.scene.options.apply(param)?;
+            }
+            Element::Texture {
+                name,
+                ty,
+                class,
+                mut params,
+            } => {
+                params.extend(&self.current_state.texture_params);
+                params.set_color_space(self.current_state.color_space);
+                self.defer_textures(&params);
+                let texture = Texture::new(
+                    name,
+                    ty,
+                    class,
+                    params,
+                    &self.named_textures,
+                    self.current_state.ctm[0],
+                )?;
+
+                let index = self.counts().textures;
+                let ty = texture.ty;
+                self.scene.textures.push(texture);
+
+                self.named_textures
+                    .names_mut(ty)
+                    .insert(name.to_string(), index);
+            }
+            // The Material directive specifies the current material, which then applies for all subsequent
+            // shape definitions until the end of the current attribute scope or until a new material is defined.
+            Element::Material { ty, mut params } => {
+                params.extend(&self.current_state.material_params);
+                params.set_color_space(self.current_state.color_space);
+                self.defer_textures(&params);
+                let material = Material::new(None, ty, params, &self.named_textures)?;
+
+                let index = self.counts().materials;
+                self.scene.materials.push(material);
+
+                self.current_state.material_index = Some(index);
+            }
+            Element::MakeNamedMaterial { name, mut params } => {
+                params.extend(&self.current_state.material_params);
+                params.set_color_space(self.current_state.color_space);
+                let ty = params
+                    .string("type")
+                    .ok_or_else(|| Error::MissingRequiredParameter.with_param("type"))?;
+                self.defer_textures(&params);
+                let material = Material::new(Some(name), ty, params, &self.named_textures)?;
+
+                let index = self.counts().materials;
+                self.scene.materials.push(material);
+
+                self.named_materials.insert(name.to_string(), index);
+            }
+            Element::NamedMaterial { name } => {
+                let index = self.find_name(Namespace::Material, name)?;
+                self.current_state.material_index = Some(index);
+            }
+            Element::LightSource { ty, mut params } => {
+                // When a light source is created, the current exterior medium is used for rays leaving the light
+                // when bidirectional light transport algorithms are used.
+                //
+                // The user is responsible for specifying media in a way such that rays reaching lights are in the same medium
+                // as rays leaving those lights.
+                params.extend(&self.current_state.light_params);
+                params.set_color_space(self.current_state.color_space);
+                let light = LightEntity {
+                    params: Light::new(ty, params)?,
+                    transform: self.current_state.ctm[0],
+                    transform_end: self.current_state.ctm[1],
+                    medium_index: self.current_state.current_outside_medium,
+                };
+                self.scene.lights.push(light);
+            }
+            // After an AreaLightSource directive, all subsequent shapes emit light
+            // from their surfaces according to the distribution defined by the given
+            // area light implementation.
+            Element::AreaLightSource { ty, mut params } => {
+                params.extend(&self.current_state.light_params);
+                params.set_color_space(self.current_state.color_space);
+                let area_light = AreaLight::new(ty, params)?;
+
+                let index = self.counts().area_lights;
+                self.scene.area_lights.push(area_light);
+
+                // The current area light is saved and restored inside attribute blocks;
+                // typically area light definitions are inside an AttributeBegin/AttributeEnd
+                // pair in order to control the shapes that they are applied to.
+                self.current_state.area_light_index = Some(index);
+            }
+            Element::Shape {
+                name: ty,
+                mut params,
+            } => {
+                params.extend(&self.current_state.shape_params);
+                let shape = Shape::new(ty, params)?;
+
+                // When a shape is created, the current interior medium is assumed to be the medium inside the shape,
+                // and the current exterior medium is assumed to be the medium outside the shape.
+                let entity = ShapeEntity {
+                    params: shape,
+                    transform: self.current_state.ctm[0],
+                    transform_end: self.current_state.ctm[1],
+                    reverse_orientation: self.current_state.reverse_orientation,
+                    material_index: self.current_state.material_index,
+                    area_light_index: self.current_state.area_light_index,
+                    inside_medium_index: self.current_state.current_inside_medium,
+                    outside_medium_index: self.current_state.current_outside_medium,
+                };
+
+                self.scene.shapes.push(entity);
+
+                // If inside of ObjectBegin/ObjectEnd, count the number of shapes.
+                if self.current_state.active_object.is_some() {
+                    self.current_state.shape_count += 1;
+                }
+            }
+            Element::ObjectBegin { name } => {
+                if self.current_state.active_object.is_some() {
+                    // Nested objects are not allowed
+                    return Err(Error::NestedObjects);
+                }
+
+                self.states_stack.push(self.current_state.clone());
+
+                let object = Object {
+                    name: name.to_string(),
+                    shape_start: None,
+                    shape_count: 0,
+                    object_to_instance: self.current_state.ctm[0],
+                };
+
+                let index = self.counts().objects;
+                self.scene.objects.push(object);
+
+                self.current_state.active_object = Some(index);
+                self.named_objects.insert(name.to_string(), index);
+            }
+            Element::ObjectEnd => {
+                let object_index = self
+                    .current_state
+                    .active_object
+                    .take()
+                    .ok_or(Error::ElementNotAllowed)?;
+
+                let shape_end = self.counts().shapes;
+
+                // Objects started by an importing file can't be ended by the imported one.
+                let object = object_index
+                    .checked_sub(self.base.objects)
+                    .and_then(|index| self.scene.objects.get_mut(index))
+                    .ok_or(Error::ElementNotAllowed)?;
+
+                object.shape_count = self.current_state.shape_count;
+
+                if object.shape_count > 0 {
+                    object.shape_start = Some(shape_end - object.shape_count)
+                }
+
+                self.current_state.shape_count = 0;
+                self.current_state.active_object = None;
+
+                match self.pop_state() {
+                    Some(state) => self.current_state = state,
+                    None => return Err(Error::ElementNotAllowed),
+                }
+            }
+            Element::ObjectInstance { name } => {
+                let object_index = self.find_name(Namespace::Object, name)?;
+
+                let instance = Instance {
+                    // The current transformation matrix defines the world from instance space transformation.
+                    instance_to_world: self.current_state.ctm[0],
+                    instance_to_world_end: self.current_state.ctm[1],
+                    object_index,
+                    area_light_index: self.current_state.area_light_index,
+                    reverse_orientation: self.current_state.reverse_orientation,
+                };
+
+                self.scene.instances.push(instance);
+            }
+            // MakeNamedMedium associates a user-specified name with medium scattering characteristics.
+            Element::MakeNamedMedium { name, mut params } => {
+                params.extend(&self.current_state.medium_params);
+                params.set_color_space(self.current_state.color_space);
+                let ty = params
+                    .string("type")
+                    .ok_or_else(|| Error::MissingRequiredParameter.with_param("type"))?;
+
+                let medium = MediumEntity {
+                    name: name.to_string(),
+                    params: Medium::new(ty, params)?,
+                    transform: self.current_state.ctm[0],
+                };
+
+                let index = self.counts().mediums;
+                self.scene.mediums.push(medium);
+
+                self.named_mediums.insert(name.to_string(), index);
+            }
+            // MediumInterface directive can be used to specify the current "interior" and "exterior" media.
+            // A vacuum—no participating media—is represented by empty string "".
+            Element::MediumInterface { interior, exterior } => {
+                let mut medium = |name: &str| match name {
+                    "" => Ok(None),
+                    name => self.find_name(Namespace::Medium, name).map(Some),
+                };
+
+                let inside = medium(interior)?;
+                let outside = medium(exterior)?;
+
+                self.current_state.current_inside_medium = inside;
+                self.current_state.current_outside_medium = outside;
+            }
+            // pbrt-v3 files need to be upgraded first.
+            Element::WorldEnd | Element::TransformBegin | Element::TransformEnd => {
+                return Err(Error::UnknownDirective);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        param::Spectrum,
+        types::{CoordinateSystem, FilmType, MaterialType, SpectrumInput},
+    };
+
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_includes() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt-includes-")?;
+        let temp_path = temp_dir.path();
+
+        fs::write(temp_path.join("1.pbrt"), "Shape \"sphere\"")?;
+        fs::write(temp_path.join("2.pbrt"), "Include \"1.pbrt\" ")?;
+        fs::write(temp_path.join("3.pbrt"), "Include \"2.pbrt\" ")?;
+        fs::write(temp_path.join("4.pbrt"), "Include \"3.pbrt\" ")?;
+
+        fs::write(
+            temp_path.join("main.pbrt"),
+            r#"
+WorldBegin
+
+Include "4.pbrt" # Include file with nexted includes
+Include "1.pbrt" # Include shap directly
+
+        "#,
+        )?;
+
+        let scene = Scene::from_file(temp_path.join("main.pbrt"))?;
+
+        debug_assert_eq!(scene.shapes.len(), 2);
+
+        // Recursive includes and imports are reported instead of being read forever.
+        fs::write(temp_path.join("5.pbrt"), "Include \"6.pbrt\"")?;
+        fs::write(temp_path.join("6.pbrt"), "Include \"5.pbrt\"")?;
+        fs::write(temp_path.join("7.pbrt"), "Import \"8.pbrt\"")?;
+        fs::write(temp_path.join("8.pbrt"), "Import \"7.pbrt\"")?;
+
+        for data in ["Include \"5.pbrt\"", "WorldBegin\nImport \"7.pbrt\""] {
+            let err = Scene::load(data, Some(temp_path)).unwrap_err();
+            assert!(matches!(err.kind(), Error::RecursiveInclude));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_instancing_attribute_block() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+ObjectBegin "foo"
+Shape "sphere"
+AttributeBegin
+Translate 1 0 0
+Shape "sphere"
+AttributeEnd
+Shape "sphere"
+ObjectEnd
+
+ObjectInstance "foo"
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        assert_eq!(scene.shapes.len(), 3);
+
+        let object = &scene.objects[0];
+        assert_eq!(object.shape_start, Some(0));
+        assert_eq!(object.shape_count, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_imports() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt-imports-")?;
+        let temp_path = temp_dir.path();
+
+        fs::write(
+            temp_path.join("1.pbrt"),
+            r#"
+MakeNamedMaterial "red" "string type" "diffuse"
+Texture "checks" "spectrum" "checkerboard"
+ObjectBegin "ball"
+Shape "sphere"
+ObjectEnd
+
+# Graphics state changes must not leak to the importing file.
+Translate 1 2 3
+ReverseOrientation
+Material "conductor"
+Shape "sphere"
+            "#,
+        )?;
+        fs::write(temp_path.join("2.pbrt"), "Import \"1.pbrt\" ")?;
+
+        fs::write(
+            temp_path.join("main.pbrt"),
+            r#"
+WorldBegin
+
+Import "2.pbrt" # Import file with nested imports
+NamedMaterial "red"
+ObjectInstance "ball"
+Shape "disk"
+        "#,
+        )?;
+
+        let scene = Scene::from_file(temp_path.join("main.pbrt"))?;
+
+        assert_eq!(scene.textures.len(), 1);
+        assert_eq!(scene.materials.len(), 2);
+        assert_eq!(scene.objects.len(), 1);
+        assert_eq!(scene.instances.len(), 1);
+        assert_eq!(scene.shapes.len(), 3);
+
+        let sphere = &scene.shapes[1];
+        assert!(sphere.reverse_orientation);
+        assert_eq!(sphere.material_index, Some(1));
+        assert_ne!(sphere.transform, Mat4::IDENTITY);
+
+        let disk = &scene.shapes[2];
+        assert!(!disk.reverse_orientation);
+        assert_eq!(disk.material_index, Some(0));
+        assert_eq!(disk.transform, Mat4::IDENTITY);
+        assert_eq!(scene.instances[0].instance_to_world, Mat4::IDENTITY);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallel_imports() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt-imports-")?;
+        let temp_path = temp_dir.path();
+
+        let mut main =
+            String::from("WorldBegin\nMakeNamedMaterial \"base\" \"string type\" \"diffuse\"\n");
+
+        for i in 0..16 {
+            fs::write(
+                temp_path.join(format!("{i}.pbrt")),
+                format!(
+                    r#"
+MakeNamedMaterial "m{i}" "string type" "diffuse"
+NamedMaterial "{material}"
+Shape "sphere" "float radius" {i}
+ObjectBegin "o{i}"
+Shape "disk"
+ObjectEnd
+"#,
+                    // Refer to a material defined by the previous import.
+                    material = if i % 2 == 0 {
+                        "base".to_string()
+                    } else {
+                        format!("m{}", i - 1)
+                    },
+                ),
+            )?;
+
+            main += &format!("Import \"{i}.pbrt\"\n");
+        }
+
+        main += "ObjectInstance \"o15\"\nShape \"cylinder\"\n";
+
+        let scene = Scene::load(&main, Some(temp_path))?;
+
+        assert_eq!(scene.materials.len(), 17);
+        assert_eq!(scene.shapes.len(), 33);
+        assert_eq!(scene.objects.len(), 16);
+
+        for i in 0..16 {
+            let sphere = &scene.shapes[i * 2];
+            let Shape::Sphere { radius, .. } = sphere.params else {
+                panic!("Unexpected shape at {}, want Sphere", i * 2);
+            };
+            assert_eq!(radius, i as f32);
+
+            let material = if i % 2 == 0 { 0 } else { i };
+            assert_eq!(sphere.material_index, Some(material));
+
+            let object = &scene.objects[i];
+            assert_eq!(object.name, format!("o{i}"));
+            assert_eq!(object.shape_start, Some(i * 2 + 1));
+            assert_eq!(object.shape_count, 1);
+        }
+
+        assert_eq!(scene.instances[0].object_index, 15);
+        assert!(matches!(scene.shapes[32].params, Shape::Cylinder { .. }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_textures() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt-import-textures-")?;
+        let temp_path = temp_dir.path();
+
+        for i in 0..2 {
+            fs::write(
+                temp_path.join(format!("{i}.pbrt")),
+                format!(
+                    r#"
+Texture "bumps{i}" "float" "constant"
+MakeNamedMaterial "m{i}" "string type" "diffuse" "texture reflectance" "checks"
+    "texture displacement" "bumps{i}"
+"#
+                ),
+            )?;
+        }
+
+        let data = r#"
+WorldBegin
+Texture "checks" "spectrum" "checkerboard"
+Import "0.pbrt"
+Import "1.pbrt"
+        "#;
+
+        let scene = Scene::load(data, Some(temp_path))?;
+
+        assert_eq!(scene.textures.len(), 3);
+        assert_eq!(scene.materials.len(), 2);
+
+        for (i, material) in scene.materials.iter().enumerate() {
+            assert!(matches!(
+                material.ty,
+                MaterialType::Diffuse {
+                    reflectance: SpectrumInput::Texture(0)
+                }
+            ));
+            assert_eq!(material.displacement, Some(i + 1));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_forward_names() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt-import-names-")?;
+        let temp_path = temp_dir.path();
+
+        let files = [
+            (
+                "0.pbrt",
+                r#"
+MakeNamedMaterial "outer" "string type" "diffuse"
+MakeNamedMedium "fog" "string type" "homogeneous"
+Texture "checks" "spectrum" "checkerboard"
+"#,
+            ),
+            // Nested imports refer to names of a preceding import of both the outer and inner batch.
+            ("1.pbrt", "Import \"2.pbrt\"\nImport \"3.pbrt\"\n"),
+            (
+                "2.pbrt",
+                r#"
+MakeNamedMaterial "inner" "string type" "conductor"
+ObjectBegin "ball"
+Shape "sphere"
+ObjectEnd
+"#,
+            ),
+            (
+                "3.pbrt",
+                r#"
+MediumInterface "fog" ""
+NamedMaterial "outer"
+Shape "disk"
+NamedMaterial "inner"
+Shape "disk"
+ObjectInstance "ball"
+MakeNamedMaterial "checked" "string type" "diffuse" "texture reflectance" "checks"
+"#,
+            ),
+            ("missing.pbrt", "\nNamedMaterial \"missing\""),
+            ("float.pbrt", "Texture \"bumps\" \"float\" \"constant\""),
+            (
+                "spectrum.pbrt",
+                "MakeNamedMaterial \"m\" \"string type\" \"diffuse\" \"texture reflectance\" \"bumps\"",
+            ),
+        ];
+
+        for (name, data) in files {
+            fs::write(temp_path.join(name), data)?;
+        }
+
+        let scene = Scene::load(
+            "WorldBegin\nImport \"0.pbrt\"\nImport \"1.pbrt\"",
+            Some(temp_path),
+        )?;
+
+        assert_eq!(scene.materials.len(), 3);
+        assert_eq!(scene.shapes.len(), 3);
+
+        assert_eq!(scene.shapes[1].material_index, Some(0));
+        assert_eq!(scene.shapes[1].inside_medium_index, Some(0));
+        assert_eq!(scene.shapes[2].material_index, Some(1));
+        assert_eq!(scene.instances[0].object_index, 0);
+        assert!(matches!(
+            scene.materials[2].ty,
+            MaterialType::Diffuse {
+                reflectance: SpectrumInput::Texture(0)
+            }
+        ));
+
+        let load = |data: &str| Scene::load(data, Some(temp_path)).unwrap_err();
+
+        let err = load("WorldBegin\nImport \"0.pbrt\"\nImport \"missing.pbrt\"");
+        assert!(matches!(err.kind(), Error::NotFound));
+
+        let location = err.location().unwrap();
+        assert_eq!(location.path, Some(temp_path.join("missing.pbrt")));
+        assert_eq!(location.directive.as_deref(), Some("NamedMaterial"));
+        assert_eq!(location.span.map(|span| span.line), Some(2));
+        assert_eq!(
+            location.included_from,
+            vec![IncludeSite {
+                path: None,
+                line: 3
+            }]
+        );
+
+        let err = load("WorldBegin\nImport \"float.pbrt\"\nImport \"spectrum.pbrt\"");
+        assert!(matches!(err.kind(), Error::InvalidTextureType));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_errors() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt-imports-")?;
+        let temp_path = temp_dir.path();
+
+        fs::write(temp_path.join("begin.pbrt"), "AttributeBegin")?;
+        fs::write(temp_path.join("end.pbrt"), "AttributeEnd")?;
+
+        let load = |data: &str| Scene::load(data, Some(temp_path)).unwrap_err();
+
+        // Only allowed in the world block.
+        let err = load("Import \"begin.pbrt\"");
+        assert!(matches!(err.kind(), Error::ElementNotAllowed));
+
+        // Attribute blocks must be balanced within the imported file.
+        let err = load("WorldBegin Import \"begin.pbrt\"");
+        assert!(matches!(err.kind(), Error::MissingEndAttribute));
+
+        let err = load("WorldBegin AttributeBegin Import \"end.pbrt\" AttributeEnd");
+        assert!(matches!(err.kind(), Error::TooManyEndAttributes));
+
+        let location = err.location().unwrap();
+        assert_eq!(location.path, Some(temp_path.join("end.pbrt")));
+        assert_eq!(
+            location.included_from,
+            vec![IncludeSite {
+                path: None,
+                line: 1
+            }]
+        );
+
+        let err = load("WorldBegin\nImport \"missing.pbrt\"");
+        assert!(matches!(err.kind(), Error::Io(_)));
+
+        let location = err.location().unwrap();
+        assert_eq!(location.directive.as_deref(), Some("Import"));
+        assert_eq!(location.span.map(|span| span.line), Some(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_instancing() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+ObjectBegin "foo"
+Shape "sphere"
+Shape "sphere"
+ObjectEnd
+
+ObjectInstance "foo"
+Translate 1 0 0
+ObjectInstance "foo"
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        assert_eq!(scene.shapes.len(), 2);
+
+        assert!(matches!(scene.shapes[0].params, Shape::Sphere { .. }));
+        assert!(matches!(scene.shapes[0].params, Shape::Sphere { .. }));
+
+        assert_eq!(scene.objects.len(), 1);
+
+        let object = &scene.objects[0];
+        assert_eq!(&object.name, "foo");
+        assert_eq!(object.shape_start, Some(0));
+        assert_eq!(object.shape_count, 2);
+
+        assert_eq!(scene.instances.len(), 2);
+
+        let inst1 = &scene.instances[0];
+        assert_eq!(inst1.object_index, 0);
+
+        let inst2 = &scene.instances[1];
+        assert_eq!(inst1.object_index, 0);
+        assert_ne!(inst1.instance_to_world, inst2.instance_to_world);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_animated_transforms() -> Result<()> {
+        let data = r#"
+TransformTimes 0 0.5
+ActiveTransform EndTime
+Translate 0 0 -1
+ActiveTransform All
+Camera "perspective"
+
+WorldBegin
+
+AttributeBegin
+ActiveTransform StartTime
+Translate 1 0 0
+Shape "sphere"
+AttributeEnd
+
+Translate 0 1 0
+Shape "sphere"
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        assert_eq!((scene.start_time, scene.end_time), (0.0, 0.5));
+
+        let camera = scene.camera.as_ref().unwrap();
+        assert_eq!(camera.transform, Mat4::IDENTITY);
+        assert_eq!(
+            camera.transform_end,
+            Mat4::from_translation(Vec3::new(0.0, 0.0, -1.0))
+        );
+
+        let shape = &scene.shapes[0];
+        assert_eq!(
+            shape.transform,
+            Mat4::from_translation(Vec3::new(1.0, 0.0, 0.0))
+        );
+        assert_eq!(shape.transform_end, Mat4::IDENTITY);
+
+        // Active transform is restored by AttributeEnd.
+        let shape = &scene.shapes[1];
+        assert_eq!(shape.transform, shape.transform_end);
+
+        let err = Scene::load("ActiveTransform Foo", None).unwrap_err();
+        assert!(matches!(err.kind(), Error::UnknownActiveTransform));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "ply")]
+    #[test]
+    fn test_load_ply() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt-ply-")?;
+        let temp_path = temp_dir.path();
+
+        fs::create_dir(temp_path.join("geometry"))?;
+        fs::write(
+            temp_path.join("geometry/triangle.ply"),
+            "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+             property float z\nelement face 1\nproperty list uchar int vertex_indices\n\
+             end_header\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n",
+        )?;
+        fs::write(
+            temp_path.join("main.pbrt"),
+            "WorldBegin\nShape \"plymesh\" \"string filename\" \"geometry/triangle.ply\"\n",
+        )?;
+
+        let scene = Scene::from_file(temp_path.join("main.pbrt"))?;
+
+        let Shape::PlyMesh { filename, .. } = &scene.shapes[0].params else {
+            panic!("Unexpected shape, want PlyMesh");
+        };
+        assert_eq!(filename, "geometry/triangle.ply");
+
+        let mesh = scene.load_ply(filename)?;
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        assert_eq!(mesh.positions.len(), 9);
+
+        let err = scene.load_ply("geometry/missing.ply").unwrap_err();
+        assert_eq!(
+            err.location().and_then(|location| location.path.clone()),
+            Some(temp_path.join("geometry/missing.ply"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_color_space() -> Result<()> {
+        let data = r#"
+WorldBegin
+
+AttributeBegin
+ColorSpace "rec2020"
+LightSource "infinite" "rgb L" [1 1 1]
+Material "diffuse"
+AttributeEnd
+
+LightSource "infinite" "rgb L" [1 1 1]
+Material "diffuse"
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        assert!(matches!(
+            scene.lights[0].params,
+            Light::Infinite {
+                spectrum: Some(Spectrum::Rgb(_, ColorSpace::Rec2020)),
+                ..
+            }
+        ));
+        assert!(matches!(
+            scene.lights[1].params,
+            Light::Infinite {
+                spectrum: Some(Spectrum::Rgb(_, ColorSpace::Srgb)),
+                ..
+            }
+        ));
+
+        assert_eq!(scene.materials[0].color_space, ColorSpace::Rec2020);
+        assert_eq!(scene.materials[1].color_space, ColorSpace::Srgb);
+
+        let err = Scene::load("ColorSpace \"foo\"", None).unwrap_err();
+        assert!(matches!(err.kind(), Error::UnknownColorSpace));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_options() -> Result<()> {
+        let scene = Scene::load(
+            "Option \"bool disablepixeljitter\" true\nOption \"string rendercoordsys\" \"world\"\nWorldBegin",
+            None,
+        )?;
+        assert!(scene.options.disable_pixel_jitter);
+        assert_eq!(scene.options.render_coord_sys, CoordinateSystem::World);
+
+        let err = Scene::load("Option \"bool foo\" true", None).unwrap_err();
+        assert!(matches!(err.kind(), Error::UnknownOption));
+        assert_eq!(err.location().unwrap().param.as_deref(), Some("foo"));
+
+        let err = Scene::load("WorldBegin\nOption \"integer seed\" 1", None).unwrap_err();
+        assert!(matches!(err.kind(), Error::ElementNotAllowed));
+
+        // The seed option applies to a sampler and integrator specified before it.
+        let scene = Scene::load(
+            r#"
+Sampler "halton"
+Integrator "sppm"
+Option "integer seed" 7
+WorldBegin
+            "#,
+            None,
+        )?;
+        assert_eq!(scene.sampler.as_ref().map(Sampler::seed), Some(7));
+        assert!(matches!(
+            scene.integrator,
+            Some(Integrator::Sppm { seed: 7, .. })
+        ));
+
+        let scene = Scene::load(
+            "Sampler \"halton\" \"integer seed\" 2\nOption \"integer seed\" 7\nWorldBegin",
+            None,
+        )?;
+        assert_eq!(scene.sampler.as_ref().map(Sampler::seed), Some(2));
+
+        // Repeated rendering options replace the previous ones.
+        let scene = Scene::load(
+            "Sampler \"halton\"\nSampler \"sobol\" \"integer seed\" 3\nFilm \"rgb\"\nFilm \"gbuffer\"\nWorldBegin",
+            None,
+        )?;
+        assert!(matches!(
+            scene.sampler,
+            Some(Sampler::Sobol { seed: 3, .. })
+        ));
+        assert!(matches!(
+            scene.film,
+            Some(Film {
+                ty: FilmType::GBuffer { .. },
+                ..
+            })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_light_attributes() -> Result<()> {
+        let data = r#"
+WorldBegin
+AttributeBegin
+Attribute "light" "float scale" 2
+LightSource "point"
+AreaLightSource "diffuse"
+AttributeEnd
+LightSource "point"
+        "#;
+
+        let scene = Scene::load(data, None)?;
+
+        let scales = scene
+            .lights
+            .iter()
+            .map(|light| match light.params {
+                Light::Point { scale, .. } => scale,
+                _ => panic!("Unexpected light {:?}, want Point", light.params),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(scales, [2.0, 1.0]);
+
+        assert!(matches!(
+            scene.area_lights[0],
+            AreaLight::Diffuse { scale, .. } if scale == 2.0
+        ));
+
+        // Animated lights keep both transformations.
+        let data = r#"
+WorldBegin
+ActiveTransform EndTime
+Translate 0 1 0
+LightSource "point"
+        "#;
+
+        let scene = Scene::load(data, None)?;
+        assert_eq!(scene.lights[0].transform, Mat4::IDENTITY);
+        assert_eq!(
+            scene.lights[0].transform_end,
+            Mat4::from_translation(Vec3::new(0.0, 1.0, 0.0))
+        );
+
+        let err = Scene::load("WorldBegin\nAreaLightSource \"foo\"", None).unwrap_err();
+        assert!(matches!(err.kind(), Error::InvalidObjectType));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_media() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt-media-")?;
+        let temp_path = temp_dir.path();
+
+        fs::write(
+            temp_path.join("smoke.pbrt"),
+            r#"
+MakeNamedMedium "smoke" "string type" "cloud"
+MediumInterface "smoke" "fog"
+Shape "sphere"
+"#,
+        )?;
+
+        let data = r#"
+MakeNamedMedium "fog" "string type" "homogeneous"
+MediumInterface "" "fog"
+Camera "perspective"
+WorldBegin
+MakeNamedMedium "water" "string type" "homogeneous"
+Import "smoke.pbrt"
+AttributeBegin
+    Translate 1 0 0
+    MakeNamedMedium "ink" "string type" "homogeneous" "float g" 0.5
+    MediumInterface "ink" ""
+    Shape "disk"
+AttributeEnd
+LightSource "point"
+"#;
+
+        let scene = Scene::load(data, Some(temp_path))?;
+
+        let names = scene
+            .mediums
+            .iter()
+            .map(|medium| medium.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["fog", "water", "smoke", "ink"]);
+        assert_eq!(
+            scene.mediums[3].transform,
+            Mat4::from_translation(Vec3::new(1.0, 0.0, 0.0))
+        );
+
+        assert_eq!(scene.camera.as_ref().unwrap().medium_index, Some(0));
+        assert_eq!(scene.lights[0].medium_index, Some(0));
+
+        // Indices of media defined by imported files are relocated.
+        assert_eq!(scene.shapes[0].inside_medium_index, Some(2));
+        assert_eq!(scene.shapes[0].outside_medium_index, Some(0));
+        assert_eq!(scene.shapes[1].inside_medium_index, Some(3));
+        assert_eq!(scene.shapes[1].outside_medium_index, None);
+
+        let err = Scene::load("WorldBegin\nMediumInterface \"\" \"fog\"", None).unwrap_err();
+        assert!(matches!(err.kind(), Error::NotFound));
+
+        let err = Scene::load("MakeNamedMedium \"fog\" \"float g\" 0", None).unwrap_err();
+        assert!(matches!(err.kind(), Error::MissingRequiredParameter));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_scenes() {
+        let load = |data: &str| Scene::load(data, None).unwrap_err();
+
+        let cases = [
+            ("WorldBegin\nAttribute \"foo\" \"float x\" 1", Error::InvalidObjectType),
+            ("CoordSysTransform \"foo\"\nWorldBegin", Error::InvalidMatrixName),
+            ("Film \"foo\"\nWorldBegin", Error::InvalidObjectType),
+            ("Shape \"sphere\"", Error::MissingWorldBegin),
+            ("WorldBegin\nAttributeBegin", Error::MissingEndAttribute),
+            (
+                "WorldBegin\nShape \"trianglemesh\" \"integer indices\" [ 0 1 2 ]",
+                Error::MissingRequiredParameter,
+            ),
+            (
+                "WorldBegin\nShape \"trianglemesh\" \"point3 P\" [ 0 0 0 1 0 0 0 1 0 0 0 1 ]",
+                Error::MissingRequiredParameter,
+            ),
+            (
+                "WorldBegin\nShape \"trianglemesh\" \"point3 P\" [ 0 0 0 1 0 0 0 1 0 ] \"integer indices\" [ 0 1 3 ]",
+                Error::ParseSlice,
+            ),
+            (
+                "WorldBegin\nShape \"trianglemesh\" \"point3 P\" [ 0 0 0 1 0 0 0 1 0 ] \"normal N\" [ 0 0 1 ]",
+                Error::ParseSlice,
+            ),
+            (
+                "WorldBegin\nShape \"trianglemesh\" \"point3 P\" [ 0 0 0 1 0 0 0 1 0 ] \"point2 uv\" [ 0 0 1 0 ]",
+                Error::ParseSlice,
+            ),
+        ];
+
+        for (data, expected) in cases {
+            let err = load(data);
+            assert_eq!(
+                std::mem::discriminant(err.kind()),
+                std::mem::discriminant(&expected),
+                "{data}: {err}"
+            );
+        }
+
+        // A single triangle doesn't need indices.
+        let scene = Scene::load(
+            "WorldBegin\nShape \"trianglemesh\" \"point3 P\" [ 0 0 0 1 0 0 0 1 0 ]",
+            None,
+        )
+        .unwrap();
+        assert!(matches!(
+            &scene.shapes[0].params,
+            Shape::TriangleMesh { indices, .. } if indices == &[0, 1, 2]
+        ));
+    }
+
+    #[test]
+    fn test_error_location() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt-errors-")?;
+        let temp_path = temp_dir.path();
+
+        fs::write(
+            temp_path.join("main.pbrt"),
+            "WorldBegin\n\nShape \"sphere\"\n  \"float radius\" 1..5\n",
+        )?;
+
+        let err = Scene::from_file(temp_path.join("main.pbrt")).unwrap_err();
+        assert!(matches!(err.kind(), Error::ParseFloat(_)));
+
+        let location = err.location().unwrap();
+        assert_eq!(location.path, Some(temp_path.join("main.pbrt")));
+        assert_eq!(location.directive.as_deref(), Some("Shape"));
+        assert_eq!(location.param.as_deref(), Some("radius"));
+
+        let span = location.span.unwrap();
+        assert_eq!((span.line, span.column), (4, 3));
+
+        let message = err.to_string();
+        assert!(message.ends_with(
+            "main.pbrt:4:3 (Shape, parameter \"radius\")\n  |\n4 |   \"float radius\" 1..5\n  |   ^^^^^^^^^^^^^^"
+        ));
+
+        // The message already includes the underlying error, so it's not repeated as the source.
+        assert!(std::error::Error::source(&err).is_none());
+
+        let err = Scene::load("WorldBegin\nShape \"plymesh\"", None).unwrap_err();
+        assert!(matches!(err.kind(), Error::MissingRequiredParameter));
+        assert_eq!(err.location().unwrap().param.as_deref(), Some("filename"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_include_stack() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt-include-errors-")?;
+        let temp_path = temp_dir.path();
+
+        fs::create_dir(temp_path.join("geometry"))?;
+
+        fs::write(
+            temp_path.join("geometry/wheels.pbrt"),
+            "Shape \"sphere\"\nShape \"cube\"\n",
+        )?;
+        fs::write(
+            temp_path.join("geometry/car.pbrt"),
+            "\n\nInclude \"geometry/wheels.pbrt\"\n",
+        )?;
+        fs::write(
+            temp_path.join("main.pbrt"),
+            "WorldBegin\nInclude \"geometry/car.pbrt\"\n",
+        )?;
+
+        let err = Scene::from_file(temp_path.join("main.pbrt")).unwrap_err();
+        assert!(matches!(err.kind(), Error::InvalidObjectType));
+
+        let location = err.location().unwrap();
+        assert_eq!(location.path, Some(temp_path.join("geometry/wheels.pbrt")));
+        assert_eq!(location.span.map(|span| span.line), Some(2));
+
+        assert_eq!(
+            location.included_from,
+            vec![
+                IncludeSite {
+                    path: Some(temp_path.join("geometry/car.pbrt")),
+                    line: 3,
+                },
+                IncludeSite {
+                    path: Some(temp_path.join("main.pbrt")),
+                    line: 2,
+                },
+            ]
+        );
+
+        let car = temp_path.join("geometry/car.pbrt");
+        let message = err.to_string();
+        assert!(message.contains(&format!("included from {}:3", car.display())));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip_includes() -> Result<()> {
+        use std::io::Write;
+
+        use flate2::{write::GzEncoder, Compression};
+
+        let temp_dir = TempDir::new("pbrt-gzip-")?;
+        let temp_path = temp_dir.path();
+
+        let compress = |name: &str, data: &str| -> Result<()> {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data.as_bytes())?;
+            fs::write(temp_path.join(name), encoder.finish()?)?;
+            Ok(())
+        };
+
+        compress("geometry.pbrt.gz", "Shape \"sphere\" Shape \"disk\"")?;
+        compress("main.pbrt.gz", "WorldBegin\nInclude \"geometry.pbrt.gz\"")?;
+
+        let scene = Scene::from_file(temp_path.join("main.pbrt.gz"))?;
+        assert_eq!(scene.shapes.len(), 2);
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    #[test]
+    fn test_gzip_disabled() -> Result<()> {
+        let temp_dir = TempDir::new("pbrt-gzip-")?;
+        let temp_path = temp_dir.path();
+
+        fs::write(temp_path.join("geometry.pbrt.gz"), [0x1f, 0x8b])?;
+        fs::write(
+            temp_path.join("main.pbrt"),
+            "WorldBegin\nInclude \"geometry.pbrt.gz\"",
+        )?;
+
+        let err = Scene::from_file(temp_path.join("main.pbrt")).unwrap_err();
+        assert!(matches!(err.kind(), Error::CompressionNotSupported));
 
         Ok(())
     }
@@ -3,19 +3,38 @@ use std::str::FromStr;
 
 use crate::Error;
 
-#[derive(Debug, PartialEq, Eq)]
+/// Position of a token within the source string.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    /// Byte offset of the first character.
+    pub start: usize,
+    /// Byte offset right after the last character.
+    pub end: usize,
+    /// Line number, starting from 1.
+    pub line: usize,
+    /// Column number (in chars), starting from 1.
+    pub column: usize,
+}
+
+#[derive(Debug, Eq)]
 pub struct Token<'a> {
     str: &'a str,
+    span: Span,
 }
 
 impl<'a> Token<'a> {
+    #[cfg(test)]
     pub fn new(str: &'a str) -> Self {
-        Token { str }
+        Self::with_span(str, Span::default())
+    }
+
+    pub fn with_span(str: &'a str, span: Span) -> Self {
+        Token { str, span }
     }
 
-    /// Returns the number of chars in the token.
-    pub fn token_size(&self) -> usize {
-        self.str.len()
+    /// Returns token's position within the source string.
+    pub fn span(&self) -> Span {
+        self.span
     }
 
     /// Returns token's value.
@@ -95,6 +114,13 @@ impl<'a> Token<'a> {
     }
 }
 
+/// Tokens are compared by value, the position is not taken into account.
+impl<'a> PartialEq for Token<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.str == other.str
+    }
+}
+
 /// Type of pbrt directive if [Token] is directive.
 #[derive(Debug, PartialEq)]
 pub enum Directive {
@@ -145,6 +171,11 @@ pub enum Directive {
     Texture,
     MakeNamedMaterial,
     NamedMaterial,
+
+    // pbrt-v3 only directives.
+    WorldEnd,
+    TransformBegin,
+    TransformEnd,
 }
 
 impl fmt::Display for Directive {
@@ -196,6 +227,9 @@ impl FromStr for Directive {
             "MakeNamedMaterial" => Directive::MakeNamedMaterial,
             "NamedMaterial" => Directive::NamedMaterial,
             "PixelFilter" => Directive::PixelFilter,
+            "WorldEnd" => Directive::WorldEnd,
+            "TransformBegin" => Directive::TransformBegin,
+            "TransformEnd" => Directive::TransformEnd,
             _ => return Err(Error::UnknownDirective),
         };
 
@@ -278,6 +312,14 @@ mod tests {
             Directive::from_str("AttributeEnd").unwrap(),
             Directive::AttributeEnd
         );
+        assert_eq!(
+            Directive::from_str("TransformBegin").unwrap(),
+            Directive::TransformBegin
+        );
+        assert_eq!(
+            Directive::from_str("WorldEnd").unwrap(),
+            Directive::WorldEnd
+        );
     }
 
     #[test]
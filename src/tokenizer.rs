@@ -1,75 +1,93 @@
-use crate::token::Token;
+use crate::token::{Span, Token};
 
 /// Tokenizer splits a string into an iterator of tokens.
 pub(crate) struct Tokenizer<'a> {
     str: &'a str,
     offset: usize,
+    /// Current line number, starting from 1.
+    line: usize,
+    /// Current column number, starting from 1.
+    column: usize,
+    /// Whether whitespace and comments are returned as tokens.
+    trivia: bool,
 }
 
 impl<'a> Tokenizer<'a> {
     pub fn new(str: &'a str) -> Self {
-        Self { str, offset: 0 }
+        Self {
+            str,
+            offset: 0,
+            line: 1,
+            column: 1,
+            trivia: false,
+        }
     }
 
-    fn rewind_until(&mut self, chars: &[char]) -> usize {
-        let mut offset = 0;
-
-        loop {
-            // Peek next char
-            let Some(ch) = self.peek_char() else {
-                break;
-            };
+    /// Create a lossless tokenizer that also returns whitespace runs and comments,
+    /// so the tokens cover the whole string.
+    pub fn with_trivia(str: &'a str) -> Self {
+        Self {
+            trivia: true,
+            ..Self::new(str)
+        }
+    }
 
+    /// Step forward until one of `chars` is found (or the end of the string is reached).
+    fn rewind_until(&mut self, chars: &[char]) {
+        while let Some(ch) = self.peek_char() {
             if chars.contains(&ch) {
                 break;
             }
 
-            // Take next char
-            if let Some((pos, _)) = self.next_char() {
-                offset = pos;
-            }
+            self.next_char();
         }
-
-        offset
     }
 
-    fn peek_char(&mut self) -> Option<char> {
-        if self.offset > self.str.len() {
-            None
-        } else {
-            self.str[self.offset..].chars().next()
-        }
+    fn peek_char(&self) -> Option<char> {
+        self.str[self.offset..].chars().next()
     }
 
     /// Get current char and step forward.
     fn next_char(&mut self) -> Option<(usize, char)> {
-        match self.peek_char() {
-            Some(ch) => {
-                let offset = self.offset;
-                self.offset += 1;
-                Some((offset, ch))
-            }
-            None => None,
+        let ch = self.peek_char()?;
+        let offset = self.offset;
+
+        self.offset += ch.len_utf8();
+
+        if ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
         }
+
+        Some((offset, ch))
+    }
+
+    /// Byte offset of the next character to read.
+    pub fn offset(&self) -> usize {
+        self.offset
     }
 
     /// Get current token without moving forward.
     pub fn peek_token(&mut self) -> Option<Token<'a>> {
-        let offset = self.offset;
+        let (offset, line, column) = (self.offset, self.line, self.column);
         let token = self.next();
-        self.offset = offset;
+        (self.offset, self.line, self.column) = (offset, line, column);
 
         token
     }
 
-    pub fn token(&self, start: usize, end: usize) -> Token<'a> {
-        let token = Token::new(&self.str[start..end]);
-        token
-    }
+    /// Make a token from `start` offset up to the current position.
+    fn token(&self, start: usize, line: usize, column: usize) -> Token<'a> {
+        let span = Span {
+            start,
+            end: self.offset,
+            line,
+            column,
+        };
 
-    /// Return current offset within string.
-    pub fn offset(&self) -> usize {
-        self.offset
+        Token::with_span(&self.str[start..self.offset], span)
     }
 }
 
@@ -78,35 +96,43 @@ impl<'a> Iterator for Tokenizer<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let Some((start, ch)) = self.next_char() else {
-                return None;
-            };
+            let (line, column) = (self.line, self.column);
+            let (start, ch) = self.next_char()?;
 
             let token = match ch {
-                '[' | ']' => self.token(start, start + 1),
-                ' ' | '\n' | '\t' | '\r' => continue,
+                '[' | ']' => self.token(start, line, column),
+                ' ' | '\n' | '\t' | '\r' => {
+                    if !self.trivia {
+                        continue;
+                    }
+
+                    while matches!(self.peek_char(), Some(' ' | '\n' | '\t' | '\r')) {
+                        self.next_char();
+                    }
+
+                    self.token(start, line, column)
+                }
                 '"' => {
-                    let mut end = self.rewind_until(&['"']);
+                    self.rewind_until(&['"']);
 
                     // Consume remaining "
-                    if let Some((pos, _)) = self.next_char() {
-                        end = pos;
-                    }
+                    self.next_char();
 
-                    self.token(start, end + 1)
+                    self.token(start, line, column)
                 }
                 '#' => {
                     // Skip comment line
                     self.rewind_until(&['\r', '\n']);
-                    continue;
-                }
-                _ => {
-                    let mut end = self.rewind_until(&[' ', '\r', '\n', '\t', '"', '[', ']']);
-                    if end == 0 {
-                        end = start;
+
+                    if !self.trivia {
+                        continue;
                     }
 
-                    self.token(start, end + 1)
+                    self.token(start, line, column)
+                }
+                _ => {
+                    self.rewind_until(&[' ', '\r', '\n', '\t', '"', '[', ']']);
+                    self.token(start, line, column)
                 }
             };
 
@@ -254,4 +280,62 @@ Scale
 
         assert_eq!(t.next(), None);
     }
+
+    #[test]
+    fn token_spans() {
+        let mut t = Tokenizer::new("Scale -1\n  \"foo\" [ 2 ]");
+
+        let span = |t: &mut Tokenizer| {
+            let span = t.next().unwrap().span();
+            (span.start, span.end, span.line, span.column)
+        };
+
+        assert_eq!(span(&mut t), (0, 5, 1, 1));
+        assert_eq!(span(&mut t), (6, 8, 1, 7));
+        assert_eq!(span(&mut t), (11, 16, 2, 3));
+        assert_eq!(span(&mut t), (17, 18, 2, 9));
+        assert_eq!(span(&mut t), (19, 20, 2, 11));
+        assert_eq!(span(&mut t), (21, 22, 2, 13));
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn multibyte_chars() {
+        let mut t = Tokenizer::new("\"héllo\" wörld");
+
+        assert_eq!(t.next(), Some(Token::new("\"héllo\"")));
+
+        let token = t.next().unwrap();
+        assert_eq!(token, Token::new("wörld"));
+        assert_eq!(token.span().column, 9);
+
+        assert_eq!(t.next(), None);
+    }
+
+    #[test]
+    fn trivia() {
+        let str = "Shape \"sphere\"  # ball\n\t\"float radius\" [1]";
+        let tokens = Tokenizer::with_trivia(str)
+            .map(|token| token.span())
+            .map(|span| &str[span.start..span.end])
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            tokens,
+            [
+                "Shape",
+                " ",
+                "\"sphere\"",
+                "  ",
+                "# ball",
+                "\n\t",
+                "\"float radius\"",
+                " ",
+                "[",
+                "1",
+                "]"
+            ]
+        );
+        assert_eq!(tokens.concat(), str);
+    }
 }
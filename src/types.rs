@@ -1,8 +1,11 @@
 //! Data structures that can be deserialized from a parameter list.
 
-use std::{collections::HashMap, default, str::FromStr, string};
+use std::{collections::HashMap, str::FromStr};
+
+use glam::Mat4;
 
 use crate::{
+    color::ColorSpace,
     param::{Param, ParamList, ParamType, Spectrum},
     Error, Result,
 };
@@ -32,7 +35,7 @@ impl FromStr for CoordinateSystem {
     }
 }
 
-/// Scene-wide rendering options.
+/// Scene-wide rendering options, set with the `Option` directive before `WorldBegin`.
 #[derive(Debug)]
 pub struct Options {
     /// Forces all pixel samples to be through the center of the pixel area.
@@ -51,6 +54,14 @@ pub struct Options {
     pub mse_reference_out: Option<String>,
     /// Specifies the coordinate system to use for rendering computation.
     pub render_coord_sys: CoordinateSystem,
+    /// Seed for the random number generators used by the samplers.
+    pub seed: i32,
+    /// Replaces all materials with diffuse ones.
+    pub force_diffuse: bool,
+    /// Records per-pixel statistics, such as the number of rays traced, to images.
+    pub pixel_stats: bool,
+    /// Use the GPU-friendly wavefront integrator.
+    pub wavefront: bool,
 }
 
 impl Default for Options {
@@ -63,12 +74,66 @@ impl Default for Options {
             mse_reference_image: None,
             mse_reference_out: None,
             render_coord_sys: CoordinateSystem::CameraWorld,
+            seed: 0,
+            force_diffuse: false,
+            pixel_stats: false,
+            wavefront: false,
         }
     }
 }
 
 impl Options {
-    pub fn apply(&mut self, _option: Param) -> Result<()> {
+    /// Set an option from an `Option` directive parameter.
+    ///
+    /// Returns [Error::UnknownOption] if the name is not a pbrt-v4 option
+    /// and [Error::InvalidParamType] if the parameter type doesn't match the option.
+    pub fn apply(&mut self, option: Param) -> Result<()> {
+        let expect = |ty: ParamType| {
+            if option.ty == ty {
+                Ok(())
+            } else {
+                Err(option.error(Error::InvalidParamType))
+            }
+        };
+
+        let boolean = || -> Result<bool> {
+            expect(ParamType::Boolean)?;
+            option.single().map_err(|err| option.error(err))
+        };
+
+        let string = || -> Result<String> {
+            expect(ParamType::String)?;
+            option
+                .strings()
+                .next()
+                .map(str::to_string)
+                .ok_or_else(|| option.error(Error::InvalidOptionValue))
+        };
+
+        match option.name {
+            "disablepixeljitter" => self.disable_pixel_jitter = boolean()?,
+            "disabletexturefiltering" => self.disable_texture_filtering = boolean()?,
+            "disablewavelengthjitter" => self.disable_wavelength_jitter = boolean()?,
+            "displacementedgescale" => {
+                expect(ParamType::Float)?;
+                self.displacement_edge_scale = option.single().map_err(|err| option.error(err))?;
+            }
+            "msereferenceimage" => self.mse_reference_image = Some(string()?),
+            "msereferenceout" => self.mse_reference_out = Some(string()?),
+            "rendercoordsys" => {
+                self.render_coord_sys =
+                    CoordinateSystem::from_str(&string()?).map_err(|err| option.error(err))?;
+            }
+            "seed" => {
+                expect(ParamType::Integer)?;
+                self.seed = option.single().map_err(|err| option.error(err))?;
+            }
+            "forcediffuse" => self.force_diffuse = boolean()?,
+            "pixelstats" => self.pixel_stats = boolean()?,
+            "wavefront" => self.wavefront = boolean()?,
+            _ => return Err(option.error(Error::UnknownOption)),
+        }
+
         Ok(())
     }
 }
@@ -158,7 +223,7 @@ impl Film {
                 lambda_min: params.float("lambdamin", 360.0)?,
                 lambda_max: params.float("lambdamax", 830.0)?,
             },
-            _ => unimplemented!(),
+            _ => return Err(Error::InvalidObjectType),
         };
 
         let film = Film {
@@ -271,47 +336,179 @@ impl Camera {
 /// Many of these integrators are present only for pedagogical purposes or for use in debugging
 /// more complex integrators through computing images using much simpler integration algorithms.
 /// For rendering high quality images, one should almost always use one of `bdpt`, `mlt`, `sppm`, or `volpath`.
+///
+/// `max_depth` is the maximum length of a light-carrying path sampled by the integrator,
+/// `regularize` enables roughening of near-specular BSDFs to reduce variance.
 #[derive(Debug)]
 pub enum Integrator {
     /// Ambient occlusion (accessibility over the hemisphere).
-    AmbientOcclusion,
+    AmbientOcclusion {
+        /// Whether cosine-weighted sampling of directions is used.
+        cos_sample: bool,
+        /// Distance beyond which occluders are ignored.
+        max_distance: f32,
+    },
     /// Bidirectional path tracing.
-    Bdpt,
+    Bdpt {
+        max_depth: i32,
+        light_sampler: LightSampler,
+        regularize: bool,
+        /// Write an image with the contribution of each sampling strategy.
+        visualize_strategies: bool,
+        /// Write an image with the MIS weights of each sampling strategy.
+        visualize_weights: bool,
+    },
     /// Path tracing starting from the light sources.
-    LightPath,
+    LightPath { max_depth: i32 },
     /// Metropolis light transport using bidirectional path tracing.
-    Mlt,
+    Mlt {
+        max_depth: i32,
+        /// Number of samples used to compute the image brightness.
+        bootstrap_samples: i32,
+        /// Number of Markov chains run in parallel.
+        chains: i32,
+        /// Average number of mutations per pixel.
+        mutations_per_pixel: i32,
+        /// Probability of discarding the current path and starting from scratch.
+        large_step_probability: f32,
+        /// Standard deviation of small path perturbations.
+        sigma: f32,
+        regularize: bool,
+    },
     /// Path tracing.
-    Path { max_depth: i32 },
+    Path {
+        max_depth: i32,
+        light_sampler: LightSampler,
+        regularize: bool,
+    },
     /// Rendering using a simple random walk without any explicit light sampling.
-    RandomWalk,
+    RandomWalk { max_depth: i32 },
     /// Path tracing with very basic sampling algorithms.
-    SimplePath,
+    SimplePath {
+        max_depth: i32,
+        /// Whether light sources are sampled at each vertex.
+        sample_lights: bool,
+        /// Whether BSDFs are sampled to choose the next direction.
+        sample_bsdf: bool,
+    },
     /// Volumetric path tracing with very basic sampling algorithms.
-    SimpleVolPath,
+    SimpleVolPath { max_depth: i32 },
     /// Stochastic progressive photon mapping
-    Sppm,
+    Sppm {
+        max_depth: i32,
+        /// Number of photons traced per iteration, the number of pixels if negative.
+        photons_per_iteration: i32,
+        /// Initial photon search radius.
+        radius: f32,
+        /// Seed for the random number generator.
+        seed: i32,
+    },
     /// Volumetric path tracing.
     VolPath {
-        /// Maximum length of a light-carrying path sampled by the integrator.
         max_depth: i32,
+        light_sampler: LightSampler,
+        regularize: bool,
     },
 }
 
+/// Method used to choose which light to sample.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LightSampler {
+    /// All lights are sampled with equal probability.
+    Uniform,
+    /// Lights are sampled according to their emitted power.
+    Power,
+    /// Lights are sampled according to their estimated contribution at the shading point.
+    #[default]
+    Bvh,
+    /// All lights are considered at each point.
+    Exhaustive,
+}
+
+impl FromStr for LightSampler {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "uniform" => Ok(LightSampler::Uniform),
+            "power" => Ok(LightSampler::Power),
+            "bvh" => Ok(LightSampler::Bvh),
+            "exhaustive" => Ok(LightSampler::Exhaustive),
+            _ => Err(Error::InvalidString),
+        }
+    }
+}
+
 impl Integrator {
-    pub fn new(ty: &str, params: ParamList) -> Result<Integrator> {
+    /// Create an integrator, the `sppm` seed defaults to the one set with `Option "integer seed"`.
+    pub fn new(ty: &str, params: ParamList, options: &Options) -> Result<Integrator> {
+        let max_depth = params.integer("maxdepth", 5)?;
+        let regularize = params.boolean("regularize", false)?;
+
+        let light_sampler = |default: LightSampler| -> Result<LightSampler> {
+            params.string("lightsampler").map_or(Ok(default), |value| {
+                LightSampler::from_str(value).map_err(|err| err.with_param("lightsampler"))
+            })
+        };
+
         let integ = match ty {
-            "volpath" => Integrator::VolPath {
-                max_depth: params.integer("maxdepth", 5)?,
+            "ambientocclusion" => Integrator::AmbientOcclusion {
+                cos_sample: params.boolean("cossample", true)?,
+                max_distance: params.float("maxdistance", f32::INFINITY)?,
+            },
+            "bdpt" => Integrator::Bdpt {
+                max_depth,
+                light_sampler: light_sampler(LightSampler::Power)?,
+                regularize,
+                visualize_strategies: params.boolean("visualizestrategies", false)?,
+                visualize_weights: params.boolean("visualizeweights", false)?,
+            },
+            "lightpath" => Integrator::LightPath { max_depth },
+            "mlt" => Integrator::Mlt {
+                max_depth,
+                bootstrap_samples: params.integer("bootstrapsamples", 100000)?,
+                chains: params.integer("chains", 1000)?,
+                mutations_per_pixel: params.integer("mutationsperpixel", 100)?,
+                large_step_probability: params.float("largestepprobability", 0.3)?,
+                sigma: params.float("sigma", 0.01)?,
+                regularize,
             },
             "path" => Integrator::Path {
-                max_depth: params.integer("maxdepth", 5)?,
+                max_depth,
+                light_sampler: light_sampler(LightSampler::Bvh)?,
+                regularize,
+            },
+            "randomwalk" => Integrator::RandomWalk { max_depth },
+            "simplepath" => Integrator::SimplePath {
+                max_depth,
+                sample_lights: params.boolean("samplelights", true)?,
+                sample_bsdf: params.boolean("samplebsdf", true)?,
+            },
+            "simplevolpath" => Integrator::SimpleVolPath { max_depth },
+            "sppm" => Integrator::Sppm {
+                max_depth,
+                photons_per_iteration: params.integer("photonsperiteration", -1)?,
+                radius: params.float("radius", 1.0)?,
+                seed: params.integer("seed", options.seed)?,
             },
-            _ => unimplemented!(),
+            "volpath" => Integrator::VolPath {
+                max_depth,
+                light_sampler: light_sampler(LightSampler::Bvh)?,
+                regularize,
+            },
+            _ => return Err(Error::InvalidObjectType),
         };
 
         Ok(integ)
     }
+
+    /// Seed of integrators that use one.
+    pub(crate) fn seed_mut(&mut self) -> Option<&mut i32> {
+        match self {
+            Integrator::Sppm { seed, .. } => Some(seed),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -378,91 +575,529 @@ impl Accelerator {
     }
 }
 
-#[derive(Debug)]
+/// Filter used to reconstruct pixel values from image samples.
+///
+/// All filters have a radius in x and y, beyond which they are zero.
+#[derive(Debug, Clone, PartialEq)]
 pub enum PixelFilter {
-    Triangle {
-        float_xradius: f32,
-        float_yradius: f32,
+    /// Equally weights all samples within the radius.
+    Box { xradius: f32, yradius: f32 },
+    /// Gaussian shifted down so that it goes to zero at the radius.
+    Gaussian {
+        xradius: f32,
+        yradius: f32,
+        /// Standard deviation of the Gaussian.
+        sigma: f32,
+    },
+    /// Mitchell-Netravali cubic filter.
+    Mitchell {
+        xradius: f32,
+        yradius: f32,
+        b: f32,
+        c: f32,
     },
+    /// Windowed sinc (Lanczos) filter.
+    Sinc {
+        xradius: f32,
+        yradius: f32,
+        /// Number of sinc cycles before the window reaches zero.
+        tau: f32,
+    },
+    /// Tent function, linearly decreasing from the center.
+    Triangle { xradius: f32, yradius: f32 },
 }
 
 impl PixelFilter {
     pub fn new(ty: &str, params: ParamList) -> Result<PixelFilter> {
+        // Default radius depends on the filter.
+        let radius = |default: f32| -> Result<(f32, f32)> {
+            Ok((
+                params.float("xradius", default)?,
+                params.float("yradius", default)?,
+            ))
+        };
+
         let pixel_filter = match ty {
-            "triangle" => PixelFilter::Triangle {
-                float_xradius: params.float("float_xradius", 1.)?,
-                float_yradius: params.float("float_yradius", 1.)?,
-            },
-            _ => {
-                todo!();
+            "box" => {
+                let (xradius, yradius) = radius(0.5)?;
+                PixelFilter::Box { xradius, yradius }
             }
+            "gaussian" => {
+                let (xradius, yradius) = radius(1.5)?;
+                PixelFilter::Gaussian {
+                    xradius,
+                    yradius,
+                    sigma: params.float("sigma", 0.5)?,
+                }
+            }
+            "mitchell" => {
+                let (xradius, yradius) = radius(2.0)?;
+                PixelFilter::Mitchell {
+                    xradius,
+                    yradius,
+                    b: params.float("B", 1.0 / 3.0)?,
+                    c: params.float("C", 1.0 / 3.0)?,
+                }
+            }
+            "sinc" => {
+                let (xradius, yradius) = radius(4.0)?;
+                PixelFilter::Sinc {
+                    xradius,
+                    yradius,
+                    tau: params.float("tau", 3.0)?,
+                }
+            }
+            "triangle" => {
+                let (xradius, yradius) = radius(2.0)?;
+                PixelFilter::Triangle { xradius, yradius }
+            }
+            _ => return Err(Error::InvalidObjectType),
         };
+
         Ok(pixel_filter)
     }
+
+    /// Filter radius in x and y.
+    pub fn radius(&self) -> [f32; 2] {
+        match self {
+            PixelFilter::Box { xradius, yradius }
+            | PixelFilter::Gaussian {
+                xradius, yradius, ..
+            }
+            | PixelFilter::Mitchell {
+                xradius, yradius, ..
+            }
+            | PixelFilter::Sinc {
+                xradius, yradius, ..
+            }
+            | PixelFilter::Triangle { xradius, yradius } => [*xradius, *yradius],
+        }
+    }
+
+    /// Evaluate the filter at offset `(x, y)` from the pixel center.
+    ///
+    /// Values are not normalized, as in pbrt-v4.
+    pub fn evaluate(&self, x: f32, y: f32) -> f32 {
+        match *self {
+            PixelFilter::Box { xradius, yradius } => {
+                if x.abs() <= xradius && y.abs() <= yradius {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            PixelFilter::Gaussian {
+                xradius,
+                yradius,
+                sigma,
+            } => {
+                let gx = gaussian(x, sigma) - gaussian(xradius, sigma);
+                let gy = gaussian(y, sigma) - gaussian(yradius, sigma);
+                gx.max(0.0) * gy.max(0.0)
+            }
+            PixelFilter::Mitchell {
+                xradius,
+                yradius,
+                b,
+                c,
+            } => mitchell(2.0 * x / xradius, b, c) * mitchell(2.0 * y / yradius, b, c),
+            PixelFilter::Sinc {
+                xradius,
+                yradius,
+                tau,
+            } => windowed_sinc(x, xradius, tau) * windowed_sinc(y, yradius, tau),
+            PixelFilter::Triangle { xradius, yradius } => {
+                (xradius - x.abs()).max(0.0) * (yradius - y.abs()).max(0.0)
+            }
+        }
+    }
+}
+
+fn gaussian(x: f32, sigma: f32) -> f32 {
+    let norm = 1.0 / (2.0 * std::f32::consts::PI * sigma * sigma).sqrt();
+    norm * (-(x * x) / (2.0 * sigma * sigma)).exp()
+}
+
+/// 1D Mitchell-Netravali filter over `-2..=2`.
+fn mitchell(x: f32, b: f32, c: f32) -> f32 {
+    let x = x.abs();
+
+    if x <= 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b))
+            / 6.0
+    } else if x <= 2.0 {
+        ((-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    let x = x * std::f32::consts::PI;
+    if 1.0 - x * x == 1.0 {
+        1.0
+    } else {
+        x.sin() / x
+    }
+}
+
+/// Sinc windowed by a Lanczos window that reaches zero after `tau` cycles.
+fn windowed_sinc(x: f32, radius: f32, tau: f32) -> f32 {
+    if x.abs() > radius {
+        0.0
+    } else {
+        sinc(x) * sinc(x / tau)
+    }
+}
+
+/// Randomization applied to low-discrepancy sample points.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RandomizeStrategy {
+    /// Sample points are not randomized.
+    None,
+    /// Random permutation of the digits of the sample indices.
+    PermuteDigits,
+    /// Owen scrambling approximated with a hash function.
+    #[default]
+    FastOwen,
+    /// Owen scrambling.
+    Owen,
+}
+
+impl FromStr for RandomizeStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(RandomizeStrategy::None),
+            "permutedigits" => Ok(RandomizeStrategy::PermuteDigits),
+            "fastowen" => Ok(RandomizeStrategy::FastOwen),
+            "owen" => Ok(RandomizeStrategy::Owen),
+            _ => Err(Error::InvalidString),
+        }
+    }
 }
 
 // The Sampler generates samples for the image, time, lens, and Monte Carlo integration.
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub enum Sampler {
-    Halton,
-    Independent,
-    PaddedSobol,
-    Sobol,
-    Stratified,
-    #[default]
-    ZSobol,
+    /// Halton sequence, randomized with `permutedigits` by default.
+    Halton {
+        /// Number of samples taken in each pixel.
+        pixel_samples: i32,
+        /// Seed for the random number generator.
+        seed: i32,
+        /// Can't be `fastowen`.
+        randomization: RandomizeStrategy,
+    },
+    /// Uniform random samples.
+    Independent { pixel_samples: i32, seed: i32 },
+    /// Sobol' samples with independently randomized dimensions.
+    PaddedSobol {
+        pixel_samples: i32,
+        seed: i32,
+        randomization: RandomizeStrategy,
+    },
+    /// Progressive multi-jittered (0,2) samples with blue noise properties.
+    Pmj02bn { pixel_samples: i32, seed: i32 },
+    /// Sobol' sequence over the whole image.
+    Sobol {
+        pixel_samples: i32,
+        seed: i32,
+        randomization: RandomizeStrategy,
+    },
+    /// Stratified samples, `xsamples * ysamples` per pixel.
+    Stratified {
+        /// Number of strata in the x direction.
+        xsamples: i32,
+        /// Number of strata in the y direction.
+        ysamples: i32,
+        /// Whether samples are jittered within each stratum.
+        jitter: bool,
+        seed: i32,
+    },
+    /// Sobol' samples ordered along a Morton curve for better spatial distribution.
+    ZSobol {
+        pixel_samples: i32,
+        seed: i32,
+        randomization: RandomizeStrategy,
+    },
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Sampler::ZSobol {
+            pixel_samples: 16,
+            seed: 0,
+            randomization: RandomizeStrategy::FastOwen,
+        }
+    }
 }
 
 impl Sampler {
-    pub fn new(ty: &str, _params: ParamList) -> Result<Sampler> {
+    /// Create a sampler, `seed` defaults to the one set with `Option "integer seed"`.
+    pub fn new(ty: &str, params: ParamList, options: &Options) -> Result<Sampler> {
+        let pixel_samples = params.integer("pixelsamples", 16)?;
+        let seed = params.integer("seed", options.seed)?;
+
+        let randomization = |default: RandomizeStrategy| -> Result<RandomizeStrategy> {
+            params.string("randomization").map_or(Ok(default), |value| {
+                RandomizeStrategy::from_str(value).map_err(|err| err.with_param("randomization"))
+            })
+        };
+
         let sampler = match ty {
-            "halton" => Sampler::Halton,
-            "independent" => Sampler::Independent,
-            "paddedsobol" => Sampler::PaddedSobol,
-            "sobol" => Sampler::Sobol,
-            "stratified" => Sampler::Stratified,
-            "zsobol" => Sampler::ZSobol,
+            "halton" => {
+                let randomization = randomization(RandomizeStrategy::PermuteDigits)?;
+                if randomization == RandomizeStrategy::FastOwen {
+                    return Err(Error::InvalidString.with_param("randomization"));
+                }
+
+                Sampler::Halton {
+                    pixel_samples,
+                    seed,
+                    randomization,
+                }
+            }
+            "independent" => Sampler::Independent {
+                pixel_samples: params.integer("pixelsamples", 4)?,
+                seed,
+            },
+            "paddedsobol" => Sampler::PaddedSobol {
+                pixel_samples,
+                seed,
+                randomization: randomization(RandomizeStrategy::FastOwen)?,
+            },
+            "pmj02bn" => Sampler::Pmj02bn {
+                pixel_samples,
+                seed,
+            },
+            "sobol" => Sampler::Sobol {
+                pixel_samples,
+                seed,
+                randomization: randomization(RandomizeStrategy::FastOwen)?,
+            },
+            "stratified" => Sampler::Stratified {
+                xsamples: params.integer("xsamples", 4)?,
+                ysamples: params.integer("ysamples", 4)?,
+                jitter: params.boolean("jitter", true)?,
+                seed,
+            },
+            "zsobol" => Sampler::ZSobol {
+                pixel_samples,
+                seed,
+                randomization: randomization(RandomizeStrategy::FastOwen)?,
+            },
             _ => return Err(Error::InvalidObjectType),
         };
 
         Ok(sampler)
     }
+
+    /// Number of samples taken in each pixel.
+    pub fn pixel_samples(&self) -> i32 {
+        match self {
+            Sampler::Halton { pixel_samples, .. }
+            | Sampler::Independent { pixel_samples, .. }
+            | Sampler::PaddedSobol { pixel_samples, .. }
+            | Sampler::Pmj02bn { pixel_samples, .. }
+            | Sampler::Sobol { pixel_samples, .. }
+            | Sampler::ZSobol { pixel_samples, .. } => *pixel_samples,
+            Sampler::Stratified {
+                xsamples, ysamples, ..
+            } => xsamples * ysamples,
+        }
+    }
+
+    /// Seed for the random number generator.
+    pub fn seed(&self) -> i32 {
+        match self {
+            Sampler::Halton { seed, .. }
+            | Sampler::Independent { seed, .. }
+            | Sampler::PaddedSobol { seed, .. }
+            | Sampler::Pmj02bn { seed, .. }
+            | Sampler::Sobol { seed, .. }
+            | Sampler::Stratified { seed, .. }
+            | Sampler::ZSobol { seed, .. } => *seed,
+        }
+    }
+
+    pub(crate) fn seed_mut(&mut self) -> &mut i32 {
+        match self {
+            Sampler::Halton { seed, .. }
+            | Sampler::Independent { seed, .. }
+            | Sampler::PaddedSobol { seed, .. }
+            | Sampler::Pmj02bn { seed, .. }
+            | Sampler::Sobol { seed, .. }
+            | Sampler::Stratified { seed, .. }
+            | Sampler::ZSobol { seed, .. } => seed,
+        }
+    }
+
+    /// Randomization of low-discrepancy samplers, `None` for others.
+    pub fn randomization(&self) -> Option<RandomizeStrategy> {
+        match self {
+            Sampler::Halton { randomization, .. }
+            | Sampler::PaddedSobol { randomization, .. }
+            | Sampler::Sobol { randomization, .. }
+            | Sampler::ZSobol { randomization, .. } => Some(*randomization),
+            Sampler::Independent { .. } | Sampler::Pmj02bn { .. } | Sampler::Stratified { .. } => {
+                None
+            }
+        }
+    }
 }
 
 /// Light sources cast illumination in the scene.
+///
+/// Positions and directions are given in light space, see [crate::LightEntity] for the
+/// transformation to world space. `scale` modulates the emitted light, while `power` and
+/// `illuminance`, if set, normalize the light to emit the given amount.
+/// Lights without a spectrum use the standard illuminant of the color space.
 #[derive(Debug)]
 pub enum Light {
     /// The "distant" light source represents a directional light source "at infinity";
     /// In other words, it illuminates the scene with light arriving from a single direction.
-    Distant,
-    GonioPhotometric,
+    Distant {
+        /// Radiance emitted by the light (`L`).
+        spectrum: Option<Spectrum>,
+        scale: f32,
+        /// Light arrives along the direction from `from` to `to`.
+        from: [f32; 3],
+        to: [f32; 3],
+        /// Illuminance at a surface perpendicular to the light direction.
+        illuminance: Option<f32>,
+    },
+    /// Point light with emission defined by an image in equal-area octahedral parameterization.
+    GonioPhotometric {
+        /// Radiant intensity (`I`).
+        spectrum: Option<Spectrum>,
+        scale: f32,
+        power: Option<f32>,
+        /// Image that describes the emission distribution.
+        filename: Option<String>,
+    },
     /// The "infinite" light represents an infinitely far away light source that
     /// potentially casts illumination from all directions.
     Infinite {
         /// The environment map to use for the infinite area light.
         /// If no filename is provided, the light will emit the same amount of radiance from every direction.
         filename: Option<String>,
-        /// The spectral distribution of emission from the light.
+        /// The spectral distribution of emission from the light (`L`).
+        spectrum: Option<Spectrum>,
+        scale: f32,
+        /// Four corners of a quadrilateral through which light enters the scene, used for sampling.
+        portal: Option<[[f32; 3]; 4]>,
+        /// Illuminance at an upward-facing surface.
+        illuminance: Option<f32>,
+    },
+    /// Isotropic point light source.
+    Point {
+        /// Radiant intensity (`I`).
         spectrum: Option<Spectrum>,
+        scale: f32,
+        power: Option<f32>,
+        /// Position of the light.
+        from: [f32; 3],
+    },
+    /// Projects an image into the scene, like a slide projector.
+    Projection {
+        scale: f32,
+        power: Option<f32>,
+        /// Field of view of the projection, in degrees.
+        fov: f32,
+        /// The image to project.
+        filename: String,
+    },
+    /// Cone of light, with intensity falling off towards the edge.
+    Spot {
+        /// Radiant intensity (`I`).
+        spectrum: Option<Spectrum>,
+        scale: f32,
+        power: Option<f32>,
+        /// The light is at `from` and points towards `to`.
+        from: [f32; 3],
+        to: [f32; 3],
+        /// Angle from the center of the cone to its edge, in degrees.
+        cone_angle: f32,
+        /// Angle at which the falloff starts, measured from the edge of the cone.
+        cone_delta: f32,
     },
-    Point,
-    Projection,
-    Spot,
 }
 
 impl Light {
     pub fn new(ty: &str, params: ParamList) -> Result<Light> {
+        let scale = params.float("scale", 1.0)?;
+
         let light = match ty {
-            "distant" => Light::Distant,
-            "goniometric" => Light::GonioPhotometric,
-            "infinite" => Light::Infinite {
+            "distant" => Light::Distant {
+                spectrum: params.spectrum("L")?,
+                scale,
+                from: vector(&params, "from", [0.0, 0.0, 0.0])?,
+                to: vector(&params, "to", [0.0, 0.0, 1.0])?,
+                illuminance: optional_float(&params, "illuminance")?,
+            },
+            "goniometric" => Light::GonioPhotometric {
+                spectrum: params.spectrum("I")?,
+                scale,
+                power: optional_float(&params, "power")?,
                 filename: params.string("filename").map(|f| f.to_owned()),
-                spectrum: params.get("L").map(|s| s.spectrum()).transpose()?,
             },
-            "point" => Light::Point,
-            "projection" => Light::Projection,
-            "spot" => Light::Spot,
-            _ => unimplemented!(),
+            "infinite" => {
+                let portal = match params.floats("portal")? {
+                    Some(values) => {
+                        let values: [f32; 12] = values
+                            .try_into()
+                            .map_err(|_| Error::ParseSlice.with_param("portal"))?;
+
+                        let mut portal = [[0.0; 3]; 4];
+                        for (point, values) in portal.iter_mut().zip(values.chunks(3)) {
+                            point.copy_from_slice(values);
+                        }
+                        Some(portal)
+                    }
+                    None => None,
+                };
+
+                Light::Infinite {
+                    filename: params.string("filename").map(|f| f.to_owned()),
+                    spectrum: params.spectrum("L")?,
+                    scale,
+                    portal,
+                    illuminance: optional_float(&params, "illuminance")?,
+                }
+            }
+            "point" => Light::Point {
+                spectrum: params.spectrum("I")?,
+                scale,
+                power: optional_float(&params, "power")?,
+                from: vector(&params, "from", [0.0, 0.0, 0.0])?,
+            },
+            "projection" => Light::Projection {
+                scale,
+                power: optional_float(&params, "power")?,
+                fov: params.float("fov", 90.0)?,
+                filename: params
+                    .string("filename")
+                    .ok_or_else(|| Error::MissingRequiredParameter.with_param("filename"))?
+                    .to_string(),
+            },
+            "spot" => Light::Spot {
+                spectrum: params.spectrum("I")?,
+                scale,
+                power: optional_float(&params, "power")?,
+                from: vector(&params, "from", [0.0, 0.0, 0.0])?,
+                to: vector(&params, "to", [0.0, 0.0, 1.0])?,
+                cone_angle: params.float("coneangle", 30.0)?,
+                cone_delta: params.float("conedelta", 5.0)?,
+            },
+            _ => return Err(Error::InvalidObjectType),
         };
 
         Ok(light)
@@ -483,6 +1118,10 @@ pub enum AreaLight {
         spectrum: Option<Spectrum>,
         /// Scale factor that modulates the amount of light that the light source emits into the scene.
         scale: f32,
+        /// Total power emitted by the light, overrides the spectrum's brightness.
+        ///
+        /// See [crate::photometric::area_light_scale] for the normalization.
+        power: Option<f32>,
     },
 }
 
@@ -493,6 +1132,7 @@ impl Default for AreaLight {
             two_sided: false,
             spectrum: None,
             scale: 1.0,
+            power: None,
         }
     }
 }
@@ -501,84 +1141,443 @@ impl AreaLight {
     pub fn new(ty: &str, params: ParamList) -> Result<AreaLight> {
         // pbrt currently only includes a single area light implementation, "diffuse".
         if ty != "diffuse" {
-            return Err(Error::InvalidParamType);
+            return Err(Error::InvalidObjectType);
         }
         Ok(AreaLight::Diffuse {
             filename: params.string("filename").map(|s| s.to_string()),
             two_sided: params.boolean("twosided", false)?,
-            spectrum: params.get("L").map(|l| l.spectrum()).transpose()?,
+            spectrum: params.spectrum("L")?,
             scale: params.float("scale", 1.0)?,
+            power: optional_float(&params, "power")?,
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TextureType {
     Float,
     Spectrum,
 }
 
+/// Texture names to indices in `Scene::textures`.
+///
+/// Float and spectrum textures have separate namespaces.
+#[derive(Debug, Default, Clone)]
+pub struct NamedTextures {
+    pub float: HashMap<String, usize>,
+    pub spectrum: HashMap<String, usize>,
+}
+
+impl NamedTextures {
+    /// Returns the namespace of textures of type `ty`.
+    pub fn names(&self, ty: TextureType) -> &HashMap<String, usize> {
+        match ty {
+            TextureType::Float => &self.float,
+            TextureType::Spectrum => &self.spectrum,
+        }
+    }
+
+    pub fn names_mut(&mut self, ty: TextureType) -> &mut HashMap<String, usize> {
+        match ty {
+            TextureType::Float => &mut self.float,
+            TextureType::Spectrum => &mut self.spectrum,
+        }
+    }
+
+    /// Find the index of texture `name` of type `ty`.
+    pub fn find(&self, name: &str, ty: TextureType) -> Result<usize> {
+        if let Some(index) = self.names(ty).get(name) {
+            return Ok(*index);
+        }
+
+        let other = match ty {
+            TextureType::Float => TextureType::Spectrum,
+            TextureType::Spectrum => TextureType::Float,
+        };
+
+        if self.names(other).contains_key(name) {
+            Err(Error::InvalidTextureType)
+        } else {
+            Err(Error::NotFound)
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Texture {
     pub name: String,
     pub ty: TextureType,
-    pub class: String,
-    pub params: HashMap<String, TextureParam>,
+    pub class: TextureClass,
+    /// Texture to world transformation, the CTM at the time the texture was declared.
+    pub transform: Mat4,
+    /// Color space RGB parameters are specified in.
+    pub color_space: ColorSpace,
+}
+
+/// Texture parameter that may refer to another texture.
+///
+/// Float textures take `Float` inputs and spectrum textures take `Spectrum` inputs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextureInput {
+    Float(FloatInput),
+    Spectrum(SpectrumInput),
+}
+
+impl TextureInput {
+    fn texture_mut(&mut self) -> Option<&mut usize> {
+        match self {
+            TextureInput::Float(input) => input.texture_mut(),
+            TextureInput::Spectrum(input) => input.texture_mut(),
+        }
+    }
+}
+
+/// Generates 2D (s,t) texture coordinates from the surface.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextureMapping {
+    /// Uses the (u,v) parameterization of the surface, scaled and offset.
+    Uv {
+        uscale: f32,
+        vscale: f32,
+        udelta: f32,
+        vdelta: f32,
+    },
+    /// Spherical coordinates of the point in the texture space.
+    Spherical,
+    /// Cylindrical coordinates of the point in the texture space.
+    Cylindrical,
+    /// Projects the point onto the plane spanned by `v1` and `v2`.
+    Planar {
+        v1: [f32; 3],
+        v2: [f32; 3],
+        udelta: f32,
+        vdelta: f32,
+    },
+}
+
+impl TextureMapping {
+    pub fn new(params: &ParamList) -> Result<TextureMapping> {
+        let mapping = match params.string("mapping").unwrap_or("uv") {
+            "uv" => TextureMapping::Uv {
+                uscale: params.float("uscale", 1.0)?,
+                vscale: params.float("vscale", 1.0)?,
+                udelta: params.float("udelta", 0.0)?,
+                vdelta: params.float("vdelta", 0.0)?,
+            },
+            "spherical" => TextureMapping::Spherical,
+            "cylindrical" => TextureMapping::Cylindrical,
+            "planar" => TextureMapping::Planar {
+                v1: vector(params, "v1", [1.0, 0.0, 0.0])?,
+                v2: vector(params, "v2", [0.0, 1.0, 0.0])?,
+                udelta: params.float("udelta", 0.0)?,
+                vdelta: params.float("vdelta", 0.0)?,
+            },
+            _ => return Err(Error::InvalidString.with_param("mapping")),
+        };
+
+        Ok(mapping)
+    }
 }
 
-/// Parameter type.
-/// Parameter name.
-/// One or more values.
-type TextureParam = (ParamType, String, String);
+/// Texture implementations.
+#[derive(Debug)]
+pub enum TextureClass {
+    /// Bilinear interpolation between four values at the corners of the (s,t) square.
+    Bilerp {
+        v00: TextureInput,
+        v01: TextureInput,
+        v10: TextureInput,
+        v11: TextureInput,
+        mapping: TextureMapping,
+    },
+    /// Alternates between two textures in a checkerboard pattern.
+    Checkerboard {
+        /// Either 2 for a planar checkerboard or 3 for a solid one.
+        dimension: i32,
+        tex1: TextureInput,
+        tex2: TextureInput,
+        mapping: TextureMapping,
+    },
+    /// Returns the same value everywhere.
+    Constant { value: TextureInput },
+    /// Blends two textures based on the angle between the surface normal and `dir`.
+    DirectionMix {
+        tex1: TextureInput,
+        tex2: TextureInput,
+        dir: [f32; 3],
+    },
+    /// Randomly placed polka dots.
+    Dots {
+        inside: TextureInput,
+        outside: TextureInput,
+        mapping: TextureMapping,
+    },
+    /// Fractional Brownian motion noise (float only).
+    Fbm { octaves: i32, roughness: f32 },
+    /// Texture read from an image file.
+    ImageMap {
+        filename: String,
+        /// Maximum elliptical eccentricity for the EWA filter.
+        max_anisotropy: f32,
+        /// Filter used for lookups: "point", "bilinear", "trilinear" or "ewa".
+        filter: String,
+        /// Wrap mode for out of bounds lookups: "repeat", "black" or "clamp".
+        wrap: String,
+        /// Scale factor applied to texture values.
+        scale: f32,
+        /// If true, texture values are replaced with `1 - value`.
+        invert: bool,
+        /// Color encoding of 8-bit image values.
+        encoding: String,
+        mapping: TextureMapping,
+    },
+    /// Marble-like pattern based on noise (spectrum only).
+    Marble {
+        octaves: i32,
+        roughness: f32,
+        scale: f32,
+        variation: f32,
+    },
+    /// Linear interpolation between two textures.
+    Mix {
+        tex1: TextureInput,
+        tex2: TextureInput,
+        /// Weight of `tex2`, `tex1` is weighted by `1 - amount`.
+        amount: FloatInput,
+    },
+    /// Texture read from a Ptex file.
+    Ptex {
+        filename: String,
+        encoding: String,
+        scale: f32,
+    },
+    /// Product of two textures.
+    Scale {
+        tex: TextureInput,
+        scale: FloatInput,
+    },
+    /// Noise pattern resembling waves on water (float only).
+    Windy,
+    /// Noise pattern resembling wrinkles (float only).
+    Wrinkled { octaves: i32, roughness: f32 },
+}
 
 impl Texture {
-    pub fn new(name: &str, ty: &str, class: &str, _params: ParamList) -> Result<Texture> {
+    pub fn new(
+        name: &str,
+        ty: &str,
+        class: &str,
+        params: ParamList,
+        textures: &NamedTextures,
+        transform: Mat4,
+    ) -> Result<Texture> {
         let ty = match ty {
             "spectrum" => TextureType::Spectrum,
             "float" => TextureType::Float,
             _ => return Err(Error::InvalidObjectType),
         };
 
-        let mut params: HashMap<String, TextureParam> = HashMap::new();
-        for p in _params.get_data().iter() {
-            params.insert(
-                p.1.name.to_string(),
-                (p.1.ty, p.1.name.to_string(), p.1.value.to_string()),
-            );
-        }
+        let inputs = Inputs {
+            params: &params,
+            textures,
+        };
+
+        // Parameters have the same type as the texture itself.
+        let input = |name: &str, default: f32| -> Result<TextureInput> {
+            match ty {
+                TextureType::Float => inputs.float(name, default).map(TextureInput::Float),
+                TextureType::Spectrum => inputs.spectrum(name, default).map(TextureInput::Spectrum),
+            }
+        };
+
+        let class = match (class, ty) {
+            ("bilerp", _) => TextureClass::Bilerp {
+                v00: input("v00", 0.0)?,
+                v01: input("v01", 1.0)?,
+                v10: input("v10", 0.0)?,
+                v11: input("v11", 1.0)?,
+                mapping: TextureMapping::new(&params)?,
+            },
+            ("checkerboard", _) => {
+                let dimension = params.integer("dimension", 2)?;
+                if dimension != 2 && dimension != 3 {
+                    return Err(Error::InvalidOptionValue.with_param("dimension"));
+                }
+
+                TextureClass::Checkerboard {
+                    dimension,
+                    tex1: input("tex1", 1.0)?,
+                    tex2: input("tex2", 0.0)?,
+                    mapping: TextureMapping::new(&params)?,
+                }
+            }
+            ("constant", _) => TextureClass::Constant {
+                value: input("value", 1.0)?,
+            },
+            ("directionmix", _) => TextureClass::DirectionMix {
+                tex1: input("tex1", 0.0)?,
+                tex2: input("tex2", 1.0)?,
+                dir: vector(&params, "dir", [0.0, 1.0, 0.0])?,
+            },
+            ("dots", _) => TextureClass::Dots {
+                inside: input("inside", 1.0)?,
+                outside: input("outside", 0.0)?,
+                mapping: TextureMapping::new(&params)?,
+            },
+            ("fbm", TextureType::Float) => TextureClass::Fbm {
+                octaves: params.integer("octaves", 8)?,
+                roughness: params.float("roughness", 0.5)?,
+            },
+            ("imagemap", _) => {
+                let filename = params
+                    .string("filename")
+                    .ok_or_else(|| Error::MissingRequiredParameter.with_param("filename"))?;
+
+                // 8-bit PNG images are usually sRGB encoded.
+                let encoding = if filename.ends_with(".png") {
+                    "sRGB"
+                } else {
+                    "linear"
+                };
+
+                TextureClass::ImageMap {
+                    filename: filename.to_string(),
+                    max_anisotropy: params.float("maxanisotropy", 8.0)?,
+                    filter: params.string("filter").unwrap_or("bilinear").to_string(),
+                    wrap: params.string("wrap").unwrap_or("repeat").to_string(),
+                    scale: params.float("scale", 1.0)?,
+                    invert: params.boolean("invert", false)?,
+                    encoding: params.string("encoding").unwrap_or(encoding).to_string(),
+                    mapping: TextureMapping::new(&params)?,
+                }
+            }
+            ("marble", TextureType::Spectrum) => TextureClass::Marble {
+                octaves: params.integer("octaves", 8)?,
+                roughness: params.float("roughness", 0.5)?,
+                scale: params.float("scale", 1.0)?,
+                variation: params.float("variation", 0.2)?,
+            },
+            ("mix", _) => TextureClass::Mix {
+                tex1: input("tex1", 0.0)?,
+                tex2: input("tex2", 1.0)?,
+                amount: inputs.float("amount", 0.5)?,
+            },
+            ("ptex", _) => TextureClass::Ptex {
+                filename: params
+                    .string("filename")
+                    .ok_or_else(|| Error::MissingRequiredParameter.with_param("filename"))?
+                    .to_string(),
+                encoding: params.string("encoding").unwrap_or("gamma 2.2").to_string(),
+                scale: params.float("scale", 1.0)?,
+            },
+            ("scale", _) => TextureClass::Scale {
+                tex: input("tex", 1.0)?,
+                scale: inputs.float("scale", 1.0)?,
+            },
+            ("windy", TextureType::Float) => TextureClass::Windy,
+            ("wrinkled", TextureType::Float) => TextureClass::Wrinkled {
+                octaves: params.integer("octaves", 8)?,
+                roughness: params.float("roughness", 0.5)?,
+            },
+            _ => return Err(Error::InvalidObjectType),
+        };
 
         Ok(Texture {
             name: name.to_string(),
             ty,
-            class: class.to_string(),
-            params,
+            class,
+            transform,
+            color_space: params.color_space(),
         })
     }
+
+    /// Returns all texture indices referenced by the texture.
+    pub(crate) fn textures_mut(&mut self) -> Vec<&mut usize> {
+        let mut inputs: Vec<&mut TextureInput> = Vec::new();
+        let mut floats: Vec<&mut FloatInput> = Vec::new();
+
+        match &mut self.class {
+            TextureClass::Bilerp {
+                v00, v01, v10, v11, ..
+            } => inputs.extend([v00, v01, v10, v11]),
+            TextureClass::Checkerboard { tex1, tex2, .. }
+            | TextureClass::DirectionMix { tex1, tex2, .. } => inputs.extend([tex1, tex2]),
+            TextureClass::Constant { value } => inputs.push(value),
+            TextureClass::Dots {
+                inside, outside, ..
+            } => inputs.extend([inside, outside]),
+            TextureClass::Mix { tex1, tex2, amount } => {
+                inputs.extend([tex1, tex2]);
+                floats.push(amount);
+            }
+            TextureClass::Scale { tex, scale } => {
+                inputs.push(tex);
+                floats.push(scale);
+            }
+            TextureClass::Fbm { .. }
+            | TextureClass::ImageMap { .. }
+            | TextureClass::Marble { .. }
+            | TextureClass::Ptex { .. }
+            | TextureClass::Windy
+            | TextureClass::Wrinkled { .. } => {}
+        }
+
+        inputs
+            .into_iter()
+            .filter_map(TextureInput::texture_mut)
+            .chain(floats.into_iter().filter_map(FloatInput::texture_mut))
+            .collect()
+    }
 }
 
-/// The "rgb" color type is the default color type for materials.
-#[derive(Debug, Default)]
-pub enum ColorType {
-    /// The "rgb" color type is the default color type for materials.
-    Rgb {
-        /// The RGB color value.
-        rgb: [f32; 3],
-    },
-    /// "Blackbody" color type.
-    #[default]
-    Blackbody,
-    /// "spectrum" color type.
-    Spectrum {
-        // Add fields or remove this variant if not needed
-        // Example: Spectrum { values: Vec<f32> },
-    },
+/// Read a float parameter that has no default value.
+fn optional_float(params: &ParamList, name: &str) -> Result<Option<f32>> {
+    params
+        .get(name)
+        .map(|param| param.single().map_err(|err| param.error(err)))
+        .transpose()
 }
 
-impl ColorType {
-    pub fn get_rgb(&self) -> [f32; 3] {
+/// Read a 3 component vector parameter.
+fn vector(params: &ParamList, name: &str, default: [f32; 3]) -> Result<[f32; 3]> {
+    match params.floats(name)? {
+        Some(values) => values
+            .try_into()
+            .map_err(|_| Error::ParseSlice.with_param(name)),
+        None => Ok(default),
+    }
+}
+
+/// Float material parameter, either a constant value or a float texture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FloatInput {
+    Value(f32),
+    /// Index of a float texture in `Scene::textures`.
+    Texture(usize),
+}
+
+/// Spectrum material parameter, either a constant spectrum or a spectrum texture.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpectrumInput {
+    Value(Spectrum),
+    /// Index of a spectrum texture in `Scene::textures`.
+    Texture(usize),
+}
+
+impl FloatInput {
+    fn texture_mut(&mut self) -> Option<&mut usize> {
         match self {
-            ColorType::Rgb { rgb } => *rgb,
-            _ => [0.0, 0.0, 0.0],
+            FloatInput::Texture(index) => Some(index),
+            FloatInput::Value(_) => None,
+        }
+    }
+}
+
+impl SpectrumInput {
+    fn texture_mut(&mut self) -> Option<&mut usize> {
+        match self {
+            SpectrumInput::Texture(index) => Some(index),
+            SpectrumInput::Value(_) => None,
         }
     }
 }
@@ -586,66 +1585,528 @@ impl ColorType {
 /// Materials specify the light scattering properties of surfaces in the scene.
 #[derive(Debug)]
 pub struct Material {
-    pub name: String,
-    pub attributes: String,
-    pub reflectance: ColorType,
-    pub params: HashMap<String, MaterialParam>,
+    /// Name given with `MakeNamedMaterial`, `None` for materials defined with `Material`.
+    pub name: Option<String>,
+    pub ty: MaterialType,
+    /// Index of a float texture that displaces the surface along its normal.
+    pub displacement: Option<usize>,
+    /// Filename of an image with a tangent space normal map.
+    pub normal_map: Option<String>,
+    /// Color space RGB parameters are specified in.
+    pub color_space: ColorSpace,
 }
 
-/// Parameter type.
-/// Parameter name.
-/// One or more values.
-type MaterialParam = (ParamType, String, String);
+#[derive(Debug)]
+pub enum MaterialType {
+    /// Diffuse base layer with a dielectric interface on top.
+    CoatedDiffuse {
+        /// Albedo of the medium between the interface and the diffuse base.
+        albedo: SpectrumInput,
+        /// Asymmetry parameter of the Henyey-Greenstein phase function of the medium.
+        g: FloatInput,
+        /// Maximum number of scattering events between the layers.
+        max_depth: i32,
+        /// Number of random walk samples used to estimate the BSDF.
+        n_samples: i32,
+        /// Reflectance of the diffuse base.
+        reflectance: SpectrumInput,
+        /// Microfacet roughness of the interface in the u direction.
+        uroughness: FloatInput,
+        /// Microfacet roughness of the interface in the v direction.
+        vroughness: FloatInput,
+        /// Thickness of the medium between the layers.
+        thickness: FloatInput,
+        /// Index of refraction of the interface.
+        eta: Spectrum,
+        /// If true, roughness values are remapped from `[0, 1]` to microfacet distribution alpha.
+        remap_roughness: bool,
+    },
+    /// Conductor base layer with a dielectric interface on top.
+    CoatedConductor {
+        /// Index of refraction of the interface.
+        interface_eta: Spectrum,
+        interface_uroughness: FloatInput,
+        interface_vroughness: FloatInput,
+        /// Thickness of the medium between the layers.
+        thickness: FloatInput,
+        /// Index of refraction of the conductor.
+        conductor_eta: SpectrumInput,
+        /// Absorption coefficient of the conductor.
+        conductor_k: SpectrumInput,
+        conductor_uroughness: FloatInput,
+        conductor_vroughness: FloatInput,
+        /// If specified, conductor's reflectance is used instead of `conductor_eta` and `conductor_k`.
+        reflectance: Option<SpectrumInput>,
+        /// Albedo of the medium between the layers.
+        albedo: SpectrumInput,
+        /// Asymmetry parameter of the Henyey-Greenstein phase function of the medium.
+        g: FloatInput,
+        /// Maximum number of scattering events between the layers.
+        max_depth: i32,
+        /// Number of random walk samples used to estimate the BSDF.
+        n_samples: i32,
+        remap_roughness: bool,
+    },
+    /// Metals and other conductors described with complex index of refraction.
+    Conductor {
+        /// Real part of the index of refraction.
+        eta: SpectrumInput,
+        /// Absorption coefficient, the imaginary part of the index of refraction.
+        k: SpectrumInput,
+        /// If specified, the reflectance at normal incidence is used instead of `eta` and `k`.
+        reflectance: Option<SpectrumInput>,
+        uroughness: FloatInput,
+        vroughness: FloatInput,
+        remap_roughness: bool,
+    },
+    /// Interface between two dielectrics (such as glass and air).
+    Dielectric {
+        /// Index of refraction of the inside of the object.
+        eta: Spectrum,
+        uroughness: FloatInput,
+        vroughness: FloatInput,
+        remap_roughness: bool,
+    },
+    /// Lambertian reflection.
+    Diffuse { reflectance: SpectrumInput },
+    /// Lambertian reflection and transmission.
+    DiffuseTransmission {
+        reflectance: SpectrumInput,
+        transmittance: SpectrumInput,
+        /// Scale factor applied to both `reflectance` and `transmittance`.
+        scale: f32,
+    },
+    /// Scattering from hair and fur.
+    Hair {
+        /// Absorption coefficient of the hair interior.
+        sigma_a: Option<SpectrumInput>,
+        /// Hair color, used to compute `sigma_a` if it's not specified.
+        color: Option<SpectrumInput>,
+        /// Concentration of eumelanin pigment, used if neither `sigma_a` nor `color` are specified.
+        eumelanin: Option<FloatInput>,
+        /// Concentration of pheomelanin pigment.
+        pheomelanin: FloatInput,
+        /// Index of refraction of the hair interior.
+        eta: FloatInput,
+        /// Longitudinal roughness.
+        beta_m: FloatInput,
+        /// Azimuthal roughness.
+        beta_n: FloatInput,
+        /// Angle of the scales on the surface of hair, in degrees.
+        alpha: FloatInput,
+    },
+    /// Marks the boundary between two participating media, doesn't scatter light.
+    Interface,
+    /// Tabulated BSDF measured from a real-world material.
+    Measured {
+        /// Path to the file with the measured BSDF data.
+        filename: String,
+    },
+    /// Stochastic blend of two named materials.
+    Mix {
+        materials: [String; 2],
+        /// Weight of the second material, the first one is weighted by `1 - amount`.
+        amount: FloatInput,
+    },
+    /// Dielectric interface with subsurface scattering inside.
+    Subsurface {
+        /// Index of refraction of the interface.
+        eta: f32,
+        /// Asymmetry parameter of the Henyey-Greenstein phase function.
+        g: f32,
+        /// Mean free path, used together with `reflectance`.
+        mfp: SpectrumInput,
+        /// Name of measured scattering properties.
+        name: Option<String>,
+        /// If specified, scattering properties are derived from `reflectance` and `mfp`.
+        reflectance: Option<SpectrumInput>,
+        /// Absorption coefficient, in mm^-1.
+        sigma_a: SpectrumInput,
+        /// Scattering coefficient, in mm^-1.
+        sigma_s: SpectrumInput,
+        /// Scale factor applied to `sigma_a` and `sigma_s`.
+        scale: f32,
+        uroughness: FloatInput,
+        vroughness: FloatInput,
+        remap_roughness: bool,
+    },
+    /// Thin dielectric surface (such as a window pane) with two parallel interfaces.
+    ThinDielectric {
+        /// Index of refraction.
+        eta: Spectrum,
+    },
+}
 
 impl Material {
     pub fn new(
-        _name: &str,
-        _params: ParamList,
-        _texture_map: &HashMap<String, usize>,
+        name: Option<&str>,
+        ty: &str,
+        params: ParamList,
+        textures: &NamedTextures,
     ) -> Result<Material> {
         // Parameters to materials are distinctive in that textures can be used to
         // specify spatially-varying values for the parameters.
+        let inputs = Inputs {
+            params: &params,
+            textures,
+        };
 
-        let mut attrib = "".to_string();
-        match _params.get("type") {
-            Some(t) => {
-                attrib = t.single::<String>().unwrap();
+        let ty =
+            match ty {
+                "coateddiffuse" => {
+                    let (uroughness, vroughness) = inputs.roughness("")?;
+
+                    MaterialType::CoatedDiffuse {
+                        albedo: inputs.spectrum("albedo", 0.0)?,
+                        g: inputs.float("g", 0.0)?,
+                        max_depth: params.integer("maxdepth", 10)?,
+                        n_samples: params.integer("nsamples", 1)?,
+                        reflectance: inputs.spectrum("reflectance", 0.5)?,
+                        uroughness,
+                        vroughness,
+                        thickness: inputs.float("thickness", 0.01)?,
+                        eta: constant_spectrum(&params, "eta", 1.5)?,
+                        remap_roughness: params.boolean("remaproughness", true)?,
+                    }
+                }
+                "coatedconductor" => {
+                    let (interface_uroughness, interface_vroughness) =
+                        inputs.roughness("interface.")?;
+                    let (conductor_uroughness, conductor_vroughness) =
+                        inputs.roughness("conductor.")?;
+
+                    MaterialType::CoatedConductor {
+                        interface_eta: constant_spectrum(&params, "interface.eta", 1.5)?,
+                        interface_uroughness,
+                        interface_vroughness,
+                        thickness: inputs.float("thickness", 0.01)?,
+                        conductor_eta: inputs.named_spectrum("conductor.eta", "metal-Cu-eta")?,
+                        conductor_k: inputs.named_spectrum("conductor.k", "metal-Cu-k")?,
+                        conductor_uroughness,
+                        conductor_vroughness,
+                        reflectance: inputs.optional_spectrum("reflectance")?,
+                        albedo: inputs.spectrum("albedo", 0.0)?,
+                        g: inputs.float("g", 0.0)?,
+                        max_depth: params.integer("maxdepth", 10)?,
+                        n_samples: params.integer("nsamples", 1)?,
+                        remap_roughness: params.boolean("remaproughness", true)?,
+                    }
+                }
+                "conductor" => {
+                    let (uroughness, vroughness) = inputs.roughness("")?;
+
+                    MaterialType::Conductor {
+                        eta: inputs.named_spectrum("eta", "metal-Cu-eta")?,
+                        k: inputs.named_spectrum("k", "metal-Cu-k")?,
+                        reflectance: inputs.optional_spectrum("reflectance")?,
+                        uroughness,
+                        vroughness,
+                        remap_roughness: params.boolean("remaproughness", true)?,
+                    }
+                }
+                "dielectric" => {
+                    let (uroughness, vroughness) = inputs.roughness("")?;
+
+                    MaterialType::Dielectric {
+                        eta: constant_spectrum(&params, "eta", 1.5)?,
+                        uroughness,
+                        vroughness,
+                        remap_roughness: params.boolean("remaproughness", true)?,
+                    }
+                }
+                "diffuse" => MaterialType::Diffuse {
+                    reflectance: inputs.spectrum("reflectance", 0.5)?,
+                },
+                "diffusetransmission" => MaterialType::DiffuseTransmission {
+                    reflectance: inputs.spectrum("reflectance", 0.25)?,
+                    transmittance: inputs.spectrum("transmittance", 0.25)?,
+                    scale: params.float("scale", 1.0)?,
+                },
+                "hair" => {
+                    let sigma_a = inputs.optional_spectrum("sigma_a")?;
+                    let color = inputs.optional_spectrum("color")?;
+                    let mut eumelanin = inputs.optional_float("eumelanin")?;
+
+                    // Use eumelanin to define hair color if nothing else is specified.
+                    if sigma_a.is_none() && color.is_none() && eumelanin.is_none() {
+                        eumelanin = Some(FloatInput::Value(1.3));
+                    }
+
+                    MaterialType::Hair {
+                        sigma_a,
+                        color,
+                        eumelanin,
+                        pheomelanin: inputs.float("pheomelanin", 0.0)?,
+                        eta: inputs.float("eta", 1.55)?,
+                        beta_m: inputs.float("beta_m", 0.3)?,
+                        beta_n: inputs.float("beta_n", 0.3)?,
+                        alpha: inputs.float("alpha", 2.0)?,
+                    }
+                }
+                "interface" => MaterialType::Interface,
+                "measured" => MaterialType::Measured {
+                    filename: params
+                        .string("filename")
+                        .ok_or_else(|| Error::MissingRequiredParameter.with_param("filename"))?
+                        .to_string(),
+                },
+                "mix" => {
+                    let materials = params
+                        .strings("materials")
+                        .ok_or_else(|| Error::MissingRequiredParameter.with_param("materials"))?;
+
+                    let [first, second]: [&str; 2] = materials
+                        .try_into()
+                        .map_err(|_| Error::ParseSlice.with_param("materials"))?;
+
+                    MaterialType::Mix {
+                        materials: [first.to_string(), second.to_string()],
+                        amount: inputs.float("amount", 0.5)?,
+                    }
+                }
+                "subsurface" => {
+                    let (uroughness, vroughness) = inputs.roughness("")?;
+                    let color_space = params.color_space();
+
+                    MaterialType::Subsurface {
+                        eta: params.float("eta", 1.33)?,
+                        g: params.float("g", 0.0)?,
+                        mfp: inputs.spectrum("mfp", 1.0)?,
+                        name: params.string("name").map(|name| name.to_string()),
+                        reflectance: inputs.optional_spectrum("reflectance")?,
+                        sigma_a: inputs.optional_spectrum("sigma_a")?.unwrap_or(
+                            SpectrumInput::Value(Spectrum::Rgb(
+                                [0.0011, 0.0024, 0.014],
+                                color_space,
+                            )),
+                        ),
+                        sigma_s: inputs.optional_spectrum("sigma_s")?.unwrap_or(
+                            SpectrumInput::Value(Spectrum::Rgb([2.55, 3.21, 3.77], color_space)),
+                        ),
+                        scale: params.float("scale", 1.0)?,
+                        uroughness,
+                        vroughness,
+                        remap_roughness: params.boolean("remaproughness", true)?,
+                    }
+                }
+                "thindielectric" => MaterialType::ThinDielectric {
+                    eta: constant_spectrum(&params, "eta", 1.5)?,
+                },
+                _ => return Err(Error::InvalidObjectType),
+            };
+
+        let displacement = match params.get("displacement") {
+            Some(param) => Some(
+                texture_index(param, TextureType::Float, textures)
+                    .map_err(|err| param.error(err))?,
+            ),
+            None => None,
+        };
+
+        Ok(Material {
+            name: name.map(|name| name.to_string()),
+            ty,
+            displacement,
+            normal_map: params.string("normalmap").map(|s| s.to_string()),
+            color_space: params.color_space(),
+        })
+    }
+
+    /// Returns all texture indices referenced by the material.
+    pub(crate) fn textures_mut(&mut self) -> Vec<&mut usize> {
+        let mut floats: Vec<&mut FloatInput> = Vec::new();
+        let mut spectra: Vec<&mut SpectrumInput> = Vec::new();
+
+        match &mut self.ty {
+            MaterialType::CoatedDiffuse {
+                albedo,
+                g,
+                reflectance,
+                uroughness,
+                vroughness,
+                thickness,
+                ..
+            } => {
+                floats.extend([g, uroughness, vroughness, thickness]);
+                spectra.extend([albedo, reflectance]);
             }
-            None => {
-                attrib = "".to_string();
+            MaterialType::CoatedConductor {
+                interface_uroughness,
+                interface_vroughness,
+                thickness,
+                conductor_eta,
+                conductor_k,
+                conductor_uroughness,
+                conductor_vroughness,
+                reflectance,
+                albedo,
+                g,
+                ..
+            } => {
+                floats.extend([
+                    interface_uroughness,
+                    interface_vroughness,
+                    thickness,
+                    conductor_uroughness,
+                    conductor_vroughness,
+                    g,
+                ]);
+                spectra.extend([conductor_eta, conductor_k, albedo]);
+                spectra.extend(reflectance);
             }
-        }
-        // println!("{:?}", attrib);
-        let mut color = [0.0, 0.0, 0.0];
-        match _params.get("reflectance") {
-            Some(r) => match r.ty {
-                ParamType::Rgb => {
-                    color = r.rgb().unwrap();
-                }
-                _ => {}
-            },
-            None => {
-                color = [0.0, 0.0, 0.0];
+            MaterialType::Conductor {
+                eta,
+                k,
+                reflectance,
+                uroughness,
+                vroughness,
+                ..
+            } => {
+                floats.extend([uroughness, vroughness]);
+                spectra.extend([eta, k]);
+                spectra.extend(reflectance);
+            }
+            MaterialType::Dielectric {
+                uroughness,
+                vroughness,
+                ..
+            } => floats.extend([uroughness, vroughness]),
+            MaterialType::Diffuse { reflectance } => spectra.push(reflectance),
+            MaterialType::DiffuseTransmission {
+                reflectance,
+                transmittance,
+                ..
+            } => spectra.extend([reflectance, transmittance]),
+            MaterialType::Hair {
+                sigma_a,
+                color,
+                eumelanin,
+                pheomelanin,
+                eta,
+                beta_m,
+                beta_n,
+                alpha,
+            } => {
+                floats.extend([pheomelanin, eta, beta_m, beta_n, alpha]);
+                floats.extend(eumelanin);
+                spectra.extend(sigma_a);
+                spectra.extend(color);
             }
+            MaterialType::Mix { amount, .. } => floats.push(amount),
+            MaterialType::Subsurface {
+                mfp,
+                reflectance,
+                sigma_a,
+                sigma_s,
+                uroughness,
+                vroughness,
+                ..
+            } => {
+                floats.extend([uroughness, vroughness]);
+                spectra.extend([mfp, sigma_a, sigma_s]);
+                spectra.extend(reflectance);
+            }
+            MaterialType::Interface
+            | MaterialType::Measured { .. }
+            | MaterialType::ThinDielectric { .. } => {}
         }
 
-        let mut params: HashMap<String, MaterialParam> = HashMap::new();
-        for p in _params.get_data().iter() {
-            params.insert(
-                p.1.name.to_string(),
-                (p.1.ty, p.1.name.to_string(), p.1.value.to_string()),
-            );
-        }
+        floats
+            .into_iter()
+            .filter_map(FloatInput::texture_mut)
+            .chain(spectra.into_iter().filter_map(SpectrumInput::texture_mut))
+            .chain(self.displacement.as_mut())
+            .collect()
+    }
+}
 
-        Ok(Material {
-            name: _name.to_string(),
-            attributes: attrib.trim_matches('"').to_string(),
-            reflectance: ColorType::Rgb { rgb: color },
-            params,
-        })
+/// Resolve a texture reference to the texture index.
+fn texture_index(param: &Param, ty: TextureType, textures: &NamedTextures) -> Result<usize> {
+    if param.ty != ParamType::Texture {
+        return Err(Error::InvalidParamType);
+    }
+
+    let name = param.strings().next().ok_or(Error::InvalidString)?;
+    textures.find(name, ty)
+}
+
+/// Reads material parameters that may reference textures.
+struct Inputs<'a, 'b> {
+    params: &'b ParamList<'a>,
+    textures: &'b NamedTextures,
+}
+
+impl Inputs<'_, '_> {
+    fn optional_float(&self, name: &str) -> Result<Option<FloatInput>> {
+        let Some(param) = self.params.get(name) else {
+            return Ok(None);
+        };
+
+        let input = if param.ty == ParamType::Texture {
+            FloatInput::Texture(
+                texture_index(param, TextureType::Float, self.textures)
+                    .map_err(|err| param.error(err))?,
+            )
+        } else {
+            FloatInput::Value(param.single().map_err(|err| param.error(err))?)
+        };
+
+        Ok(Some(input))
+    }
+
+    fn float(&self, name: &str, default: f32) -> Result<FloatInput> {
+        Ok(self
+            .optional_float(name)?
+            .unwrap_or(FloatInput::Value(default)))
+    }
+
+    fn optional_spectrum(&self, name: &str) -> Result<Option<SpectrumInput>> {
+        let Some(param) = self.params.get(name) else {
+            return Ok(None);
+        };
+
+        let input = if param.ty == ParamType::Texture {
+            SpectrumInput::Texture(
+                texture_index(param, TextureType::Spectrum, self.textures)
+                    .map_err(|err| param.error(err))?,
+            )
+        } else {
+            SpectrumInput::Value(param.spectrum(self.params.color_space())?)
+        };
+
+        Ok(Some(input))
+    }
+
+    fn spectrum(&self, name: &str, default: f32) -> Result<SpectrumInput> {
+        Ok(self
+            .optional_spectrum(name)?
+            .unwrap_or(SpectrumInput::Value(Spectrum::Constant(default))))
+    }
+
+    fn named_spectrum(&self, name: &str, default: &str) -> Result<SpectrumInput> {
+        Ok(self
+            .optional_spectrum(name)?
+            .unwrap_or_else(|| SpectrumInput::Value(Spectrum::Named(default.to_string()))))
+    }
+
+    /// Read microfacet roughness in u and v directions,
+    /// both default to the isotropic "roughness" parameter.
+    fn roughness(&self, prefix: &str) -> Result<(FloatInput, FloatInput)> {
+        let roughness = self.float(&format!("{prefix}roughness"), 0.0)?;
+        let u = self.optional_float(&format!("{prefix}uroughness"))?;
+        let v = self.optional_float(&format!("{prefix}vroughness"))?;
+
+        Ok((u.unwrap_or(roughness), v.unwrap_or(roughness)))
     }
 }
 
+/// Spectrum parameter that can't be textured, a float value is treated as a constant spectrum.
+fn constant_spectrum(params: &ParamList, name: &str, default: f32) -> Result<Spectrum> {
+    Ok(params
+        .spectrum(name)?
+        .unwrap_or(Spectrum::Constant(default)))
+}
+
 #[derive(Debug)]
 pub enum Shape {
     /// The "cylinder" is always oriented along the z axis.
@@ -702,15 +2163,13 @@ pub enum Shape {
         uvs: Vec<f32>,
     },
     /// pbrt can also directly read triangle meshes specified in the PLY mesh file format, via the "plymesh" shape.
-    /// TODO: Support loading ply files.
-    PlyMesh { filename: String },
+    ///
+    /// The file is not loaded with the scene, use `Scene::load_ply` (requires the "ply" feature) to read it.
+    PlyMesh { alpha: f32, filename: String },
 }
 
 impl Shape {
     pub fn new(ty: &str, params: ParamList) -> Result<Self> {
-        // println!("{:?}", ty);
-        // println!("{:?}\n", params);
-
         // All shapes take an optional "alpha" parameter that can be
         // used to define a mask that cuts away regions of a surface.
         let alpha = params.float("alpha", 1.0)?;
@@ -745,16 +2204,43 @@ impl Shape {
                 }
             }
             "trianglemesh" => {
-                // TODO: Positions and indices are required, return error if not provided.
-                let indices = params.integers("indices")?.unwrap_or_default();
-                debug_assert_eq!(indices.len() % 3, 0);
+                let positions = params
+                    .floats("P")?
+                    .ok_or_else(|| Error::MissingRequiredParameter.with_param("P"))?;
+                if positions.len() % 3 != 0 {
+                    return Err(Error::ParseSlice.with_param("P"));
+                }
+
+                // Indices may be omitted for a single triangle.
+                let indices = match params.integers("indices")? {
+                    Some(indices) => indices,
+                    None if positions.len() == 9 => vec![0, 1, 2],
+                    None => return Err(Error::MissingRequiredParameter.with_param("indices")),
+                };
+
+                let vertex_count = positions.len() / 3;
+                if indices.len() % 3 != 0
+                    || indices
+                        .iter()
+                        .any(|index| usize::try_from(*index).map_or(true, |i| i >= vertex_count))
+                {
+                    return Err(Error::ParseSlice.with_param("indices"));
+                }
 
-                let positions = params.floats("P")?.unwrap_or_default();
+                // Per-vertex data is optional, but must match the number of vertices.
+                let per_vertex = |name: &str, components: usize| -> Result<Vec<f32>> {
+                    let values = params.floats(name)?.unwrap_or_default();
 
-                let normals = params.floats("N")?.unwrap_or_default();
-                let tangents = params.floats("S")?.unwrap_or_default();
+                    if !values.is_empty() && values.len() != vertex_count * components {
+                        return Err(Error::ParseSlice.with_param(name));
+                    }
 
-                let uvs = params.floats("uv")?.unwrap_or_default();
+                    Ok(values)
+                };
+
+                let normals = per_vertex("N", 3)?;
+                let tangents = per_vertex("S", 3)?;
+                let uvs = per_vertex("uv", 2)?;
 
                 Shape::TriangleMesh {
                     alpha,
@@ -768,10 +2254,10 @@ impl Shape {
             "plymesh" => {
                 let filename = params
                     .string("filename")
-                    .ok_or(Error::MissingRequiredParameter)?
+                    .ok_or_else(|| Error::MissingRequiredParameter.with_param("filename"))?
                     .to_string();
 
-                Shape::PlyMesh { filename }
+                Shape::PlyMesh { alpha, filename }
             }
             _ => return Err(Error::InvalidObjectType),
         };
@@ -780,13 +2266,206 @@ impl Shape {
     }
 }
 
-#[derive(Debug, Default)]
-pub struct Medium {}
+/// Participating media, defined with `MakeNamedMedium`.
+///
+/// Unless a `preset` is given, `sigma_a` and `sigma_s` default to pbrt's
+/// RGB (.0011, .0024, .014) and (2.55, 3.21, 3.77) when `None`.
+#[derive(Debug, Clone)]
+pub enum Medium {
+    /// Medium with constant scattering properties.
+    Homogeneous {
+        /// Name of measured scattering properties, overrides `sigma_a` and `sigma_s`.
+        preset: Option<String>,
+        /// Absorption coefficient.
+        sigma_a: Option<Spectrum>,
+        /// Scattering coefficient.
+        sigma_s: Option<Spectrum>,
+        /// Scale factor applied to `sigma_a` and `sigma_s`.
+        scale: f32,
+        /// Henyey-Greenstein asymmetry parameter.
+        g: f32,
+        /// Emitted radiance.
+        le: Option<Spectrum>,
+        /// Scale factor applied to `le`.
+        le_scale: f32,
+    },
+    /// Density sampled over a regular grid within the `p0`-`p1` box.
+    UniformGrid {
+        preset: Option<String>,
+        sigma_a: Option<Spectrum>,
+        sigma_s: Option<Spectrum>,
+        scale: f32,
+        g: f32,
+        nx: i32,
+        ny: i32,
+        nz: i32,
+        p0: [f32; 3],
+        p1: [f32; 3],
+        /// `nx * ny * nz` density values, x varies fastest.
+        density: Vec<f32>,
+        /// Temperature grid for blackbody emission, empty if not given.
+        temperature: Vec<f32>,
+        /// Temperatures below the cutoff don't emit.
+        temperature_cutoff: f32,
+        temperature_scale: f32,
+        le: Option<Spectrum>,
+        le_scale: f32,
+    },
+    /// RGB scattering coefficients sampled over a regular grid within the `p0`-`p1` box.
+    RgbGrid {
+        scale: f32,
+        g: f32,
+        nx: i32,
+        ny: i32,
+        nz: i32,
+        p0: [f32; 3],
+        p1: [f32; 3],
+        /// `nx * ny * nz` absorption coefficients, empty if not given.
+        sigma_a: Vec<[f32; 3]>,
+        /// `nx * ny * nz` scattering coefficients, empty if not given.
+        sigma_s: Vec<[f32; 3]>,
+        /// `nx * ny * nz` emitted radiance values, empty if not given.
+        le: Vec<[f32; 3]>,
+        le_scale: f32,
+        /// Color space of the RGB values.
+        color_space: ColorSpace,
+    },
+    /// Procedural cloud density within the `p0`-`p1` box.
+    Cloud {
+        preset: Option<String>,
+        sigma_a: Option<Spectrum>,
+        sigma_s: Option<Spectrum>,
+        scale: f32,
+        g: f32,
+        density: f32,
+        wispiness: f32,
+        frequency: f32,
+        p0: [f32; 3],
+        p1: [f32; 3],
+    },
+    /// Density (and optionally temperature) grids read from a NanoVDB file.
+    ///
+    /// The file is not loaded with the scene, use `Scene::load_nanovdb` (requires the "nanovdb" feature) to read it.
+    NanoVdb {
+        preset: Option<String>,
+        sigma_a: Option<Spectrum>,
+        sigma_s: Option<Spectrum>,
+        scale: f32,
+        g: f32,
+        filename: String,
+        le_scale: f32,
+        temperature_cutoff: f32,
+        temperature_scale: f32,
+    },
+}
 
 impl Medium {
-    pub fn new(_params: ParamList) -> Result<Self> {
-        // TODO: Handle medium object initialization.
-        Ok(Medium {})
+    pub fn new(ty: &str, params: ParamList) -> Result<Self> {
+        let preset = params.string("preset").map(|s| s.to_string());
+        let scale = params.float("scale", 1.0)?;
+        let g = params.float("g", 0.0)?;
+        let p0 = vector(&params, "p0", [0.0, 0.0, 0.0])?;
+        let p1 = vector(&params, "p1", [1.0, 1.0, 1.0])?;
+
+        let nx = params.integer("nx", 1)?;
+        let ny = params.integer("ny", 1)?;
+        let nz = params.integer("nz", 1)?;
+        let count = (nx.max(0) as usize)
+            .saturating_mul(ny.max(0) as usize)
+            .saturating_mul(nz.max(0) as usize);
+
+        // Grid values, one or three per voxel.
+        let grid = |name: &str, stride: usize| -> Result<Vec<f32>> {
+            let values = params.floats(name)?.unwrap_or_default();
+            if !values.is_empty() && values.len() != count.saturating_mul(stride) {
+                return Err(Error::ParseSlice.with_param(name));
+            }
+            Ok(values)
+        };
+        let rgb_grid = |name: &str| -> Result<Vec<[f32; 3]>> {
+            let values = grid(name, 3)?;
+            Ok(values.chunks_exact(3).map(|v| [v[0], v[1], v[2]]).collect())
+        };
+
+        let medium = match ty {
+            "homogeneous" => Medium::Homogeneous {
+                preset,
+                sigma_a: params.spectrum("sigma_a")?,
+                sigma_s: params.spectrum("sigma_s")?,
+                scale,
+                g,
+                le: params.spectrum("Le")?,
+                le_scale: params.float("Lescale", 1.0)?,
+            },
+            "uniformgrid" => {
+                let density = grid("density", 1)?;
+                if density.is_empty() {
+                    return Err(Error::MissingRequiredParameter.with_param("density"));
+                }
+
+                Medium::UniformGrid {
+                    preset,
+                    sigma_a: params.spectrum("sigma_a")?,
+                    sigma_s: params.spectrum("sigma_s")?,
+                    scale,
+                    g,
+                    nx,
+                    ny,
+                    nz,
+                    p0,
+                    p1,
+                    density,
+                    temperature: grid("temperature", 1)?,
+                    temperature_cutoff: params.float("temperaturecutoff", 0.0)?,
+                    temperature_scale: params.float("temperaturescale", 1.0)?,
+                    le: params.spectrum("Le")?,
+                    le_scale: params.float("Lescale", 1.0)?,
+                }
+            }
+            "rgbgrid" => Medium::RgbGrid {
+                scale,
+                g,
+                nx,
+                ny,
+                nz,
+                p0,
+                p1,
+                sigma_a: rgb_grid("sigma_a")?,
+                sigma_s: rgb_grid("sigma_s")?,
+                le: rgb_grid("Le")?,
+                le_scale: params.float("Lescale", 1.0)?,
+                color_space: params.color_space(),
+            },
+            "cloud" => Medium::Cloud {
+                preset,
+                sigma_a: params.spectrum("sigma_a")?,
+                sigma_s: params.spectrum("sigma_s")?,
+                scale,
+                g,
+                density: params.float("density", 1.0)?,
+                wispiness: params.float("wispiness", 1.0)?,
+                frequency: params.float("frequency", 5.0)?,
+                p0,
+                p1,
+            },
+            "nanovdb" => Medium::NanoVdb {
+                preset,
+                sigma_a: params.spectrum("sigma_a")?,
+                sigma_s: params.spectrum("sigma_s")?,
+                scale,
+                g,
+                filename: params
+                    .string("filename")
+                    .ok_or_else(|| Error::MissingRequiredParameter.with_param("filename"))?
+                    .to_string(),
+                le_scale: params.float("Lescale", 1.0)?,
+                temperature_cutoff: params.float("temperaturecutoff", 0.0)?,
+                temperature_scale: params.float("temperaturescale", 1.0)?,
+            },
+            _ => return Err(Error::InvalidObjectType),
+        };
+
+        Ok(medium)
     }
 }
 
@@ -812,4 +2491,557 @@ mod tests {
         assert!(CoordinateSystem::from_str("").is_err());
         assert!(CoordinateSystem::from_str("foo").is_err());
     }
+
+    #[test]
+    fn parse_media() -> Result<()> {
+        let params = param_list(&[
+            ("string preset", "\"Wholemilk\""),
+            ("float g", "0.8"),
+            ("blackbody Le", "3000"),
+        ]);
+        let medium = Medium::new("homogeneous", params)?;
+        assert!(matches!(
+            medium,
+            Medium::Homogeneous {
+                preset: Some(preset),
+                sigma_a: None,
+                le: Some(Spectrum::Blackbody(3000)),
+                scale,
+                g,
+                ..
+            } if preset == "Wholemilk" && scale == 1.0 && g == 0.8
+        ));
+
+        let params = param_list(&[
+            ("integer nx", "2"),
+            ("rgb sigma_s", "1 2 3 4 5 6"),
+            ("point3 p1", "2 1 1"),
+        ]);
+        let medium = Medium::new("rgbgrid", params)?;
+        assert!(matches!(
+            medium,
+            Medium::RgbGrid {
+                nx: 2,
+                sigma_s,
+                sigma_a,
+                p1: [2.0, 1.0, 1.0],
+                ..
+            } if sigma_s == [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]] && sigma_a.is_empty()
+        ));
+
+        let params = param_list(&[("float frequency", "3")]);
+        let medium = Medium::new("cloud", params)?;
+        assert!(matches!(
+            medium,
+            Medium::Cloud { frequency, density, .. } if frequency == 3.0 && density == 1.0
+        ));
+
+        // Grid size must match the resolution.
+        let params = param_list(&[("integer nx", "2"), ("float density", "1")]);
+        let err = Medium::new("uniformgrid", params).unwrap_err();
+        assert!(matches!(err.kind(), Error::ParseSlice));
+        assert_eq!(err.location().unwrap().param.as_deref(), Some("density"));
+
+        let err = Medium::new("uniformgrid", ParamList::default()).unwrap_err();
+        assert!(matches!(err.kind(), Error::MissingRequiredParameter));
+
+        let err = Medium::new("nanovdb", ParamList::default()).unwrap_err();
+        assert!(matches!(err.kind(), Error::MissingRequiredParameter));
+
+        let err = Medium::new("fog", ParamList::default()).unwrap_err();
+        assert!(matches!(err, Error::InvalidObjectType));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_lights() -> Result<()> {
+        let params = param_list(&[("rgb I", "1 2 3"), ("point3 from", "0 1 0")]);
+        let light = Light::new("point", params)?;
+        assert!(matches!(
+            light,
+            Light::Point {
+                spectrum: Some(Spectrum::Rgb([1.0, 2.0, 3.0], _)),
+                scale,
+                power: None,
+                from: [0.0, 1.0, 0.0],
+            } if scale == 1.0
+        ));
+
+        let params = param_list(&[("float conedelta", "10"), ("float scale", "2")]);
+        let light = Light::new("spot", params)?;
+        assert!(matches!(
+            light,
+            Light::Spot {
+                spectrum: None,
+                to: [0.0, 0.0, 1.0],
+                cone_angle,
+                cone_delta,
+                scale,
+                ..
+            } if cone_angle == 30.0 && cone_delta == 10.0 && scale == 2.0
+        ));
+
+        let params = param_list(&[("float illuminance", "5")]);
+        let light = Light::new("distant", params)?;
+        assert!(matches!(
+            light,
+            Light::Distant {
+                illuminance: Some(illuminance),
+                ..
+            } if illuminance == 5.0
+        ));
+
+        let params = param_list(&[
+            ("string filename", "\"sky.exr\""),
+            ("point3 portal", "0 0 0  1 0 0  1 1 0  0 1 0"),
+        ]);
+        let light = Light::new("infinite", params)?;
+        let Light::Infinite {
+            filename, portal, ..
+        } = light
+        else {
+            panic!("Unexpected light type, want Infinite");
+        };
+        assert_eq!(filename.as_deref(), Some("sky.exr"));
+        assert_eq!(portal.unwrap()[2], [1.0, 1.0, 0.0]);
+
+        let params = param_list(&[("point3 portal", "0 0 0")]);
+        let err = Light::new("infinite", params).unwrap_err();
+        assert!(matches!(err.kind(), Error::ParseSlice));
+
+        let params = param_list(&[("string filename", "\"slide.png\"")]);
+        let light = Light::new("projection", params)?;
+        assert!(matches!(light, Light::Projection { fov, .. } if fov == 90.0));
+
+        let err = Light::new("projection", ParamList::default()).unwrap_err();
+        assert!(matches!(err.kind(), Error::MissingRequiredParameter));
+
+        let light = Light::new("goniometric", ParamList::default())?;
+        assert!(matches!(
+            light,
+            Light::GonioPhotometric { filename: None, .. }
+        ));
+
+        let err = Light::new("sun", ParamList::default()).unwrap_err();
+        assert!(matches!(err.kind(), Error::InvalidObjectType));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_integrators() -> Result<()> {
+        let options = Options::default();
+
+        let integrator = Integrator::new("path", ParamList::default(), &options)?;
+        assert!(matches!(
+            integrator,
+            Integrator::Path {
+                max_depth: 5,
+                light_sampler: LightSampler::Bvh,
+                regularize: false,
+            }
+        ));
+
+        let params = param_list(&[("bool cossample", "false"), ("float maxdistance", "2")]);
+        let integrator = Integrator::new("ambientocclusion", params, &options)?;
+        assert!(matches!(
+            integrator,
+            Integrator::AmbientOcclusion {
+                cos_sample: false,
+                max_distance,
+            } if max_distance == 2.0
+        ));
+
+        let params = param_list(&[
+            ("integer maxdepth", "10"),
+            ("bool visualizestrategies", "true"),
+        ]);
+        let integrator = Integrator::new("bdpt", params, &options)?;
+        assert!(matches!(
+            integrator,
+            Integrator::Bdpt {
+                max_depth: 10,
+                light_sampler: LightSampler::Power,
+                visualize_strategies: true,
+                visualize_weights: false,
+                ..
+            }
+        ));
+
+        let params = param_list(&[("integer mutationsperpixel", "8")]);
+        let integrator = Integrator::new("mlt", params, &options)?;
+        assert!(matches!(
+            integrator,
+            Integrator::Mlt {
+                mutations_per_pixel: 8,
+                chains: 1000,
+                large_step_probability,
+                ..
+            } if large_step_probability == 0.3
+        ));
+
+        let params = param_list(&[("integer photonsperiteration", "1000")]);
+        let integrator = Integrator::new("sppm", params, &Options { seed: 3, ..options })?;
+        assert!(matches!(
+            integrator,
+            Integrator::Sppm {
+                photons_per_iteration: 1000,
+                seed: 3,
+                ..
+            }
+        ));
+
+        for ty in [
+            "lightpath",
+            "randomwalk",
+            "simplepath",
+            "simplevolpath",
+            "volpath",
+        ] {
+            Integrator::new(ty, ParamList::default(), &Options::default())?;
+        }
+
+        let params = param_list(&[("string lightsampler", "\"foo\"")]);
+        let err = Integrator::new("volpath", params, &Options::default()).unwrap_err();
+        assert!(matches!(err.kind(), Error::InvalidString));
+
+        let err =
+            Integrator::new("whitted", ParamList::default(), &Options::default()).unwrap_err();
+        assert!(matches!(err.kind(), Error::InvalidObjectType));
+
+        Ok(())
+    }
+
+    #[test]
+    fn pixel_filters() -> Result<()> {
+        let filter = PixelFilter::new("triangle", param_list(&[("float xradius", "1")]))?;
+        assert_eq!(filter.radius(), [1.0, 2.0]);
+        assert_eq!(filter.evaluate(0.5, 1.0), 0.5);
+        assert_eq!(filter.evaluate(1.5, 0.0), 0.0);
+
+        let filter = PixelFilter::new("box", ParamList::default())?;
+        assert_eq!(filter.radius(), [0.5, 0.5]);
+        assert_eq!(filter.evaluate(0.5, -0.5), 1.0);
+        assert_eq!(filter.evaluate(0.6, 0.0), 0.0);
+
+        let filter = PixelFilter::new("gaussian", ParamList::default())?;
+        assert_eq!(
+            filter,
+            PixelFilter::Gaussian {
+                xradius: 1.5,
+                yradius: 1.5,
+                sigma: 0.5,
+            }
+        );
+        assert!(filter.evaluate(0.0, 0.0) > filter.evaluate(0.5, 0.0));
+        assert_eq!(filter.evaluate(1.5, 0.0), 0.0);
+
+        let params = param_list(&[("float B", "0"), ("float C", "0.5")]);
+        let filter = PixelFilter::new("mitchell", params)?;
+        assert!(matches!(filter, PixelFilter::Mitchell { b, c, .. } if b == 0.0 && c == 0.5));
+        // Catmull-Rom interpolates: 1 at the center, 0 at integer offsets.
+        assert_eq!(filter.evaluate(0.0, 0.0), 1.0);
+        assert!(filter.evaluate(1.0, 0.0).abs() < 1e-6);
+        assert_eq!(filter.evaluate(2.5, 0.0), 0.0);
+
+        let filter = PixelFilter::new("sinc", ParamList::default())?;
+        assert_eq!(filter.radius(), [4.0, 4.0]);
+        assert_eq!(filter.evaluate(0.0, 0.0), 1.0);
+        assert!(filter.evaluate(1.0, 0.0).abs() < 1e-6);
+        assert_eq!(filter.evaluate(4.5, 0.0), 0.0);
+
+        let err = PixelFilter::new("lanczos", ParamList::default()).unwrap_err();
+        assert!(matches!(err.kind(), Error::InvalidObjectType));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_samplers() -> Result<()> {
+        let options = Options {
+            seed: 5,
+            ..Options::default()
+        };
+
+        let sampler = Sampler::new("halton", ParamList::default(), &options)?;
+        assert_eq!(sampler.pixel_samples(), 16);
+        assert_eq!(sampler.seed(), 5);
+        assert_eq!(
+            sampler.randomization(),
+            Some(RandomizeStrategy::PermuteDigits)
+        );
+
+        let params = param_list(&[
+            ("integer pixelsamples", "1024"),
+            ("integer seed", "2"),
+            ("string randomization", "\"owen\""),
+        ]);
+        let sampler = Sampler::new("zsobol", params, &options)?;
+        assert!(matches!(
+            sampler,
+            Sampler::ZSobol {
+                pixel_samples: 1024,
+                seed: 2,
+                randomization: RandomizeStrategy::Owen,
+            }
+        ));
+
+        let params = param_list(&[("integer xsamples", "2"), ("bool jitter", "false")]);
+        let sampler = Sampler::new("stratified", params, &options)?;
+        assert_eq!(sampler.pixel_samples(), 8);
+        assert!(matches!(sampler, Sampler::Stratified { jitter: false, .. }));
+        assert_eq!(sampler.randomization(), None);
+
+        let sampler = Sampler::new("independent", ParamList::default(), &options)?;
+        assert_eq!(sampler.pixel_samples(), 4);
+
+        let params = param_list(&[("string randomization", "\"fastowen\"")]);
+        let err = Sampler::new("halton", params, &options).unwrap_err();
+        assert!(matches!(err.kind(), Error::InvalidString));
+        assert_eq!(
+            err.location().unwrap().param.as_deref(),
+            Some("randomization")
+        );
+
+        let params = param_list(&[("string randomization", "\"foo\"")]);
+        assert!(Sampler::new("sobol", params, &options).is_err());
+
+        assert!(Sampler::new("foo", ParamList::default(), &options).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn apply_options() -> Result<()> {
+        let mut options = Options::default();
+
+        options.apply(Param::new("bool disablepixeljitter", "true")?)?;
+        options.apply(Param::new("float displacementedgescale", "2")?)?;
+        options.apply(Param::new("string msereferenceimage", "\"ref.exr\"")?)?;
+        options.apply(Param::new("string rendercoordsys", "\"world\"")?)?;
+        options.apply(Param::new("integer seed", "7")?)?;
+        options.apply(Param::new("bool wavefront", "true")?)?;
+
+        assert!(options.disable_pixel_jitter);
+        assert!(!options.disable_texture_filtering);
+        assert_eq!(options.displacement_edge_scale, 2.0);
+        assert_eq!(options.mse_reference_image.as_deref(), Some("ref.exr"));
+        assert_eq!(options.render_coord_sys, CoordinateSystem::World);
+        assert_eq!(options.seed, 7);
+        assert!(options.wavefront);
+
+        let err = options.apply(Param::new("bool foo", "true")?).unwrap_err();
+        assert!(matches!(err.kind(), Error::UnknownOption));
+
+        let err = options.apply(Param::new("float seed", "1")?).unwrap_err();
+        assert!(matches!(err.kind(), Error::InvalidParamType));
+
+        let err = options
+            .apply(Param::new("bool pixelstats", "yes")?)
+            .unwrap_err();
+        assert!(matches!(err.kind(), Error::ParseBool(_)));
+
+        let err = options
+            .apply(Param::new("string rendercoordsys", "\"foo\"")?)
+            .unwrap_err();
+        assert!(matches!(err.kind(), Error::UnknownCoordinateSystem));
+
+        Ok(())
+    }
+
+    fn param_list<'a>(params: &[(&'a str, &'a str)]) -> ParamList<'a> {
+        let mut list = ParamList::default();
+        for (name, value) in params {
+            list.add(Param::new(name, value).unwrap()).unwrap();
+        }
+        list
+    }
+
+    #[test]
+    fn parse_materials() -> Result<()> {
+        let textures = NamedTextures::default();
+
+        let params = param_list(&[("float roughness", "0.2"), ("float vroughness", "0.4")]);
+        let material = Material::new(None, "conductor", params, &textures)?;
+        let MaterialType::Conductor {
+            eta,
+            k,
+            reflectance,
+            uroughness,
+            vroughness,
+            ..
+        } = material.ty
+        else {
+            panic!("Unexpected material type, want Conductor");
+        };
+        assert_eq!(
+            eta,
+            SpectrumInput::Value(Spectrum::Named("metal-Cu-eta".to_string()))
+        );
+        assert_eq!(
+            k,
+            SpectrumInput::Value(Spectrum::Named("metal-Cu-k".to_string()))
+        );
+        assert_eq!(reflectance, None);
+        assert_eq!(uroughness, FloatInput::Value(0.2));
+        assert_eq!(vroughness, FloatInput::Value(0.4));
+
+        let params = param_list(&[("float eta", "1.33")]);
+        let material = Material::new(Some("glass"), "dielectric", params, &textures)?;
+        assert_eq!(material.name.as_deref(), Some("glass"));
+        assert!(matches!(
+            material.ty,
+            MaterialType::Dielectric {
+                eta: Spectrum::Constant(eta),
+                ..
+            } if eta == 1.33
+        ));
+
+        let params = param_list(&[("string materials", "\"a\" \"b\"")]);
+        let material = Material::new(None, "mix", params, &textures)?;
+        let MaterialType::Mix { materials, amount } = material.ty else {
+            panic!("Unexpected material type, want Mix");
+        };
+        assert_eq!(materials, ["a".to_string(), "b".to_string()]);
+        assert_eq!(amount, FloatInput::Value(0.5));
+
+        let material = Material::new(None, "hair", ParamList::default(), &textures)?;
+        assert!(matches!(
+            material.ty,
+            MaterialType::Hair {
+                eumelanin: Some(_),
+                ..
+            }
+        ));
+
+        let err = Material::new(None, "measured", ParamList::default(), &textures).unwrap_err();
+        assert!(matches!(err.kind(), Error::MissingRequiredParameter));
+
+        assert!(Material::new(None, "foo", ParamList::default(), &textures).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_material_textures() -> Result<()> {
+        let mut textures = NamedTextures::default();
+        textures.float.insert("bumps".to_string(), 0);
+        textures.spectrum.insert("checks".to_string(), 1);
+
+        let params = param_list(&[
+            ("texture reflectance", "\"checks\""),
+            ("texture roughness", "\"bumps\""),
+            ("texture displacement", "\"bumps\""),
+        ]);
+        let mut material = Material::new(None, "coateddiffuse", params, &textures)?;
+        let MaterialType::CoatedDiffuse {
+            reflectance,
+            uroughness,
+            vroughness,
+            ..
+        } = &material.ty
+        else {
+            panic!("Unexpected material type, want CoatedDiffuse");
+        };
+        assert_eq!(*reflectance, SpectrumInput::Texture(1));
+        assert_eq!(*uroughness, FloatInput::Texture(0));
+        assert_eq!(*vroughness, FloatInput::Texture(0));
+        assert_eq!(material.displacement, Some(0));
+
+        let mut indices = material
+            .textures_mut()
+            .into_iter()
+            .map(|index| *index)
+            .collect::<Vec<_>>();
+        indices.sort();
+        assert_eq!(indices, vec![0, 0, 0, 1]);
+
+        let params = param_list(&[("texture reflectance", "\"foo\"")]);
+        let err = Material::new(None, "diffuse", params, &textures).unwrap_err();
+        assert!(matches!(err.kind(), Error::NotFound));
+        assert_eq!(
+            err.location().unwrap().param.as_deref(),
+            Some("reflectance")
+        );
+
+        let params = param_list(&[("texture reflectance", "\"bumps\"")]);
+        let err = Material::new(None, "diffuse", params, &textures).unwrap_err();
+        assert!(matches!(err.kind(), Error::InvalidTextureType));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_textures() -> Result<()> {
+        let mut textures = NamedTextures::default();
+        textures.float.insert("bumps".to_string(), 0);
+
+        let params = param_list(&[
+            ("string filename", "\"wood.png\""),
+            ("string mapping", "\"planar\""),
+            ("vector3 v1", "0 0 1"),
+            ("float udelta", "0.5"),
+        ]);
+        let texture = Texture::new(
+            "wood",
+            "spectrum",
+            "imagemap",
+            params,
+            &textures,
+            Mat4::IDENTITY,
+        )?;
+        assert_eq!(texture.ty, TextureType::Spectrum);
+        let TextureClass::ImageMap {
+            encoding,
+            filter,
+            mapping,
+            ..
+        } = texture.class
+        else {
+            panic!("Unexpected texture class, want ImageMap");
+        };
+        assert_eq!(encoding, "sRGB");
+        assert_eq!(filter, "bilinear");
+        assert_eq!(
+            mapping,
+            TextureMapping::Planar {
+                v1: [0.0, 0.0, 1.0],
+                v2: [0.0, 1.0, 0.0],
+                udelta: 0.5,
+                vdelta: 0.0,
+            }
+        );
+
+        let params = param_list(&[("texture tex", "\"bumps\""), ("float scale", "2")]);
+        let transform = Mat4::from_scale(glam::Vec3::splat(2.0));
+        let mut texture = Texture::new("scaled", "float", "scale", params, &textures, transform)?;
+        assert_eq!(texture.transform, transform);
+        assert!(matches!(
+            texture.class,
+            TextureClass::Scale {
+                tex: TextureInput::Float(FloatInput::Texture(0)),
+                scale: FloatInput::Value(scale),
+            } if scale == 2.0
+        ));
+        assert_eq!(texture.textures_mut().len(), 1);
+
+        let params = param_list(&[("texture tex", "\"bumps\"")]);
+        let err =
+            Texture::new("s", "spectrum", "scale", params, &textures, Mat4::IDENTITY).unwrap_err();
+        assert!(matches!(err.kind(), Error::InvalidTextureType));
+
+        // Fbm is a float only texture.
+        assert!(Texture::new(
+            "noise",
+            "spectrum",
+            "fbm",
+            ParamList::default(),
+            &textures,
+            Mat4::IDENTITY
+        )
+        .is_err());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,726 @@
+//! Upgrade of pbrt-v3 scene files to pbrt-v4, similar to `pbrt --upgrade`.
+//!
+//! Only directives that need changes are edited, comments and formatting are kept.
+//! Materials, lights and other directives are mapped to their pbrt-v4 equivalents,
+//! parameters that can't be converted are removed and reported as [Warning]s.
+//!
+//! ```
+//! # use pbrt4::upgrade::upgrade;
+//! let upgrade = upgrade("Material \"matte\" \"color Kd\" [ .5 .5 .5 ]\nWorldEnd\n")?;
+//!
+//! assert_eq!(upgrade.text, "Material \"diffuse\" \"rgb reflectance\" [ .5 .5 .5 ]\n");
+//! assert!(upgrade.warnings.is_empty());
+//! # Ok::<(), pbrt4::Error>(())
+//! ```
+
+use std::{fmt, ops::Range};
+
+use crate::{
+    cst::{Cst, TokenKind},
+    param::{Param, ParamType},
+    Element, Error, Result, Span, Version,
+};
+
+/// Upgraded scene file.
+#[derive(Debug)]
+pub struct Upgrade {
+    /// Scene file in pbrt-v4 format.
+    pub text: String,
+    /// Things that could not be converted, sorted by position.
+    pub warnings: Vec<Warning>,
+}
+
+/// A directive or parameter that could not be converted to pbrt-v4.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    /// Position of the directive or parameter in the original file.
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.line, self.span.column, self.message
+        )
+    }
+}
+
+/// Convert pbrt-v3 scene file contents to pbrt-v4.
+///
+/// Only the given text is upgraded, included files are not read.
+/// `TransformBegin`/`TransformEnd` become attribute blocks and `WorldEnd` is removed.
+pub fn upgrade(source: &str) -> Result<Upgrade> {
+    let mut cst = Cst::with_version(source, Version::V3)?;
+
+    let mut edits = Vec::new();
+    let mut warnings = Vec::new();
+
+    for index in 0..cst.nodes().len() {
+        let mut edit = Edit::new(&cst, index)?;
+        upgrade_element(&cst.nodes()[index].element, &mut edit);
+
+        warnings.append(&mut edit.warnings);
+        edits.extend(edit.finish(&cst, index)?);
+    }
+
+    for (range, text) in edits {
+        cst.replace(range, text)?;
+    }
+
+    warnings.sort_by_key(|warning| warning.span.start);
+
+    Ok(Upgrade {
+        text: cst.to_string(),
+        warnings,
+    })
+}
+
+fn upgrade_element(element: &Element, edit: &mut Edit) {
+    match element {
+        Element::WorldEnd => edit.directive = Some(String::new()),
+        Element::TransformBegin => edit.directive = Some("AttributeBegin".to_string()),
+        Element::TransformEnd => edit.directive = Some("AttributeEnd".to_string()),
+        Element::Film { ty, .. } => {
+            if *ty == "image" {
+                edit.header(0, "rgb");
+            }
+
+            edit.rename("maxsampleluminance", "maxcomponentvalue");
+            edit.unsupported("scale", "film");
+        }
+        Element::Sampler { ty, .. } => {
+            match *ty {
+                "lowdiscrepancy" | "02sequence" => edit.header(0, "paddedsobol"),
+                "maxmindist" => edit.header(0, "pmj02bn"),
+                "random" => edit.header(0, "independent"),
+                _ => {}
+            }
+
+            edit.remove("dimensions");
+        }
+        Element::PixelFilter { ty, .. } => {
+            edit.rename("xwidth", "xradius");
+            edit.rename("ywidth", "yradius");
+
+            if *ty == "gaussian" {
+                // Gaussian falloff exp(-alpha x^2) in terms of the standard deviation.
+                if let Some(param) = edit.remove("alpha") {
+                    match param.single::<f32>() {
+                        Ok(alpha) if alpha > 0.0 => {
+                            let sigma = 1.0 / (2.0 * alpha).sqrt();
+                            edit.add("float sigma", sigma);
+                        }
+                        _ => edit.warn(param.span(), "invalid \"alpha\", removed".to_string()),
+                    }
+                }
+            }
+        }
+        Element::Integrator { ty, .. } => {
+            match *ty {
+                "directlighting" => {
+                    edit.header(0, "path");
+                    edit.remove("strategy");
+
+                    match edit.find("maxdepth") {
+                        Some(entry) => entry.value = Some("1".to_string()),
+                        None => edit.add("integer maxdepth", 1),
+                    }
+
+                    edit.warn(
+                        None,
+                        "\"directlighting\" is replaced by \"path\" with \"maxdepth\" 1"
+                            .to_string(),
+                    );
+                }
+                "whitted" => {
+                    edit.header(0, "path");
+                    edit.warn(None, "\"whitted\" is replaced by \"path\"".to_string());
+                }
+                _ => {}
+            }
+
+            if let Some(entry) = edit.find("lightsamplestrategy") {
+                if entry.param.strings().next() == Some("spatial") {
+                    entry.value = Some("\"bvh\"".to_string());
+                }
+            }
+        }
+        Element::Shape { name, .. } => {
+            edit.unsupported("shadowalpha", "shapes");
+            edit.remove("discarddegenerateUVs");
+
+            match *name {
+                "loopsubdiv" => edit.rename("nlevels", "levels"),
+                "trianglemesh" => {
+                    for name in ["uv", "st"] {
+                        if let Some(entry) = edit.find(name) {
+                            entry.ty = ParamType::Point2.name();
+                            entry.name = "uv".to_string();
+                        }
+                    }
+                }
+                "heightfield" | "nurbs" | "cone" | "paraboloid" | "hyperboloid" => {
+                    edit.warn(
+                        None,
+                        format!("shape \"{name}\" is not supported by pbrt-v4"),
+                    );
+                }
+                _ => {}
+            }
+        }
+        Element::LightSource { .. } | Element::AreaLightSource { .. } => {
+            edit.remove("nsamples");
+            edit.rename("mapname", "filename");
+
+            // Lights are scaled by a single float.
+            if let Some(param) = edit.get("scale") {
+                if param.ty != ParamType::Float {
+                    match uniform_rgb(param) {
+                        Some(value) => edit.set_float("scale", value),
+                        None => edit.unsupported("scale", "lights unless it is a uniform \"rgb\""),
+                    }
+                }
+            }
+        }
+        Element::Material { ty, .. } => {
+            if let Some(ty) = material(ty, edit) {
+                edit.header(0, ty);
+            }
+        }
+        Element::MakeNamedMaterial { params, .. } => {
+            if let Some(ty) = params.string("type") {
+                if let Some(ty) = material(ty, edit) {
+                    edit.set("type", format!("\"{ty}\""));
+                }
+            }
+        }
+        Element::Texture { ty, class, .. } => {
+            if *ty == "color" {
+                edit.header(1, "spectrum");
+            }
+
+            match *class {
+                "scale" => {
+                    edit.rename("tex1", "tex");
+                    edit.rename("tex2", "scale");
+
+                    if *ty == "color" {
+                        color_scale(edit);
+                    }
+                }
+                "imagemap" => {
+                    if let Some(param) = edit.remove("gamma") {
+                        match param.single::<bool>() {
+                            Ok(true) => edit.add("string encoding", "\"sRGB\""),
+                            Ok(false) => edit.add("string encoding", "\"linear\""),
+                            Err(_) => {
+                                edit.warn(param.span(), "invalid \"gamma\", removed".to_string())
+                            }
+                        }
+                    }
+                }
+                "uv" => edit.warn(
+                    None,
+                    "texture \"uv\" is not supported by pbrt-v4".to_string(),
+                ),
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The "scale" input of a color "scale" texture is a float texture in pbrt-v4.
+///
+/// A uniform RGB is converted to a float, otherwise the inputs are swapped
+/// if "tex" is missing or uniform.
+fn color_scale(edit: &mut Edit) {
+    let Some(scale) = edit.get("scale") else {
+        return;
+    };
+
+    if let Some(value) = uniform_rgb(scale) {
+        edit.set_float("scale", value);
+        return;
+    }
+
+    match edit.get("tex").map(uniform_rgb) {
+        None => edit.rename("scale", "tex"),
+        Some(Some(value)) => {
+            for entry in edit.params.iter_mut().filter(|entry| !entry.removed) {
+                match entry.name.as_str() {
+                    "tex" => {
+                        entry.name = "scale".to_string();
+                        entry.ty = ParamType::Float.name();
+                        entry.value = Some(value.clone());
+                    }
+                    "scale" => entry.name = "tex".to_string(),
+                    _ => {}
+                }
+            }
+        }
+        Some(None) => {
+            edit.remove("scale");
+            edit.warn(
+                scale.span(),
+                "\"tex2\" is not supported by \"scale\" textures unless it or \"tex1\" \
+                 is a uniform \"rgb\", removed"
+                    .to_string(),
+            );
+        }
+    }
+}
+
+/// First value of an RGB parameter with equal components.
+fn uniform_rgb(param: &Param) -> Option<String> {
+    let mut values = param.value.split_whitespace();
+    let first = values.next()?;
+
+    (param.ty == ParamType::Rgb && values.all(|value| value == first)).then(|| first.to_string())
+}
+
+/// Update material parameters, returns the new material type if it changed.
+fn material(ty: &str, edit: &mut Edit) -> Option<&'static str> {
+    edit.rename("bumpmap", "displacement");
+
+    let ty = match ty {
+        "matte" => {
+            edit.rename("Kd", "reflectance");
+            edit.unsupported("sigma", "\"diffuse\"");
+            "diffuse"
+        }
+        "plastic" | "substrate" | "uber" => {
+            // Defaults of pbrt-v3 differ.
+            if ty == "substrate" {
+                edit.default("float uroughness", "0.1");
+                edit.default("float vroughness", "0.1");
+            } else if edit.get("uroughness").is_none() {
+                edit.default("float roughness", "0.1");
+            }
+
+            edit.rename("Kd", "reflectance");
+            edit.rename("index", "eta");
+            for name in ["Ks", "Kr", "Kt", "opacity"] {
+                edit.unsupported(name, "\"coateddiffuse\"");
+            }
+            "coateddiffuse"
+        }
+        "translucent" => {
+            edit.rename("reflect", "reflectance");
+            edit.rename("transmit", "transmittance");
+            for name in ["Kd", "Ks", "roughness", "remaproughness"] {
+                edit.unsupported(name, "\"diffusetransmission\"");
+            }
+            "diffusetransmission"
+        }
+        "glass" => {
+            edit.rename("index", "eta");
+            edit.unsupported("Kr", "\"dielectric\"");
+            edit.unsupported("Kt", "\"dielectric\"");
+            "dielectric"
+        }
+        "mirror" => {
+            if edit.get("Kr").is_some() {
+                edit.rename("Kr", "reflectance");
+            } else {
+                edit.add("spectrum eta", "\"metal-Ag-eta\"");
+                edit.add("spectrum k", "\"metal-Ag-k\"");
+            }
+            "conductor"
+        }
+        "metal" => {
+            if edit.get("uroughness").is_none() {
+                edit.default("float roughness", "0.01");
+            }
+            "conductor"
+        }
+        "kdsubsurface" => {
+            edit.rename("Kd", "reflectance");
+            edit.unsupported("Kr", "\"subsurface\"");
+            edit.unsupported("Kt", "\"subsurface\"");
+            "subsurface"
+        }
+        "subsurface" => {
+            edit.unsupported("Kr", "\"subsurface\"");
+            edit.unsupported("Kt", "\"subsurface\"");
+            return None;
+        }
+        "mix" => {
+            let first = edit.get("namedmaterial1").and_then(|p| p.strings().next());
+            let second = edit.get("namedmaterial2").and_then(|p| p.strings().next());
+
+            if let (Some(first), Some(second)) = (first, second) {
+                edit.remove("namedmaterial1");
+                edit.remove("namedmaterial2");
+                edit.add("string materials", format!("[ \"{first}\" \"{second}\" ]"));
+            }
+            return None;
+        }
+        "none" | "" => "interface",
+        "fourier" | "disney" => {
+            edit.warn(
+                None,
+                format!("material \"{ty}\" is not supported by pbrt-v4"),
+            );
+            return None;
+        }
+        _ => return None,
+    };
+
+    Some(ty)
+}
+
+/// Planned changes of a single directive.
+struct Edit<'p, 'a> {
+    /// Position of the directive.
+    span: Span,
+    /// Replacement of the whole directive.
+    directive: Option<String>,
+    /// New values of the quoted strings after the directive name, by position.
+    header: Vec<(usize, &'static str)>,
+    params: Vec<Entry<'p, 'a>>,
+    /// New parameters, formatted as `"type name" value`.
+    added: Vec<String>,
+    warnings: Vec<Warning>,
+}
+
+struct Entry<'p, 'a> {
+    param: &'p Param<'a>,
+    ty: &'static str,
+    name: String,
+    /// New value, including brackets.
+    value: Option<String>,
+    removed: bool,
+}
+
+impl<'p, 'a> Edit<'p, 'a> {
+    fn new(cst: &'p Cst<'a>, index: usize) -> Result<Self> {
+        let node = &cst.nodes()[index];
+
+        let mut params = Vec::new();
+        for param in node
+            .element
+            .params()
+            .into_iter()
+            .flat_map(|params| params.iter())
+        {
+            let value = cst.param_value(index, param.name)?;
+
+            params.push(Entry {
+                param,
+                ty: param.ty.name(),
+                name: param.name.to_string(),
+                // Quoted booleans are not allowed anymore.
+                value: (param.ty == ParamType::Boolean && cst.source()[value].contains('"'))
+                    .then(|| param.value.to_string()),
+                removed: false,
+            });
+        }
+
+        Ok(Edit {
+            span: node.span,
+            directive: None,
+            header: Vec::new(),
+            params,
+            added: Vec::new(),
+            warnings: Vec::new(),
+        })
+    }
+
+    fn find(&mut self, name: &str) -> Option<&mut Entry<'p, 'a>> {
+        self.params
+            .iter_mut()
+            .find(|entry| !entry.removed && entry.name == name)
+    }
+
+    fn get(&self, name: &str) -> Option<&'p Param<'a>> {
+        self.params
+            .iter()
+            .find(|entry| !entry.removed && entry.name == name)
+            .map(|entry| entry.param)
+    }
+
+    fn header(&mut self, position: usize, text: &'static str) {
+        self.header.push((position, text));
+    }
+
+    fn rename(&mut self, from: &str, to: &str) {
+        if let Some(entry) = self.find(from) {
+            entry.name = to.to_string();
+        }
+    }
+
+    fn set(&mut self, name: &str, value: String) {
+        if let Some(entry) = self.find(name) {
+            entry.value = Some(value);
+        }
+    }
+
+    /// Replace a parameter with a float.
+    fn set_float(&mut self, name: &str, value: String) {
+        if let Some(entry) = self.find(name) {
+            entry.ty = ParamType::Float.name();
+            entry.value = Some(value);
+        }
+    }
+
+    fn add(&mut self, type_and_name: &str, value: impl fmt::Display) {
+        self.added.push(format!("\"{type_and_name}\" {value}"));
+    }
+
+    /// Add a parameter unless it's already there.
+    fn default(&mut self, type_and_name: &str, value: &str) {
+        let name = type_and_name.split_whitespace().last().unwrap_or_default();
+        if self.get(name).is_none() {
+            self.add(type_and_name, value);
+        }
+    }
+
+    fn remove(&mut self, name: &str) -> Option<&'p Param<'a>> {
+        let entry = self.find(name)?;
+        entry.removed = true;
+        Some(entry.param)
+    }
+
+    /// Remove a parameter that has no pbrt-v4 equivalent.
+    fn unsupported(&mut self, name: &str, target: &str) {
+        if let Some(param) = self.remove(name) {
+            let message = format!("\"{name}\" is not supported by {target}, removed");
+            self.warn(param.span(), message);
+        }
+    }
+
+    fn warn(&mut self, span: Option<Span>, message: String) {
+        self.warnings.push(Warning {
+            span: span.unwrap_or(self.span),
+            message,
+        });
+    }
+
+    /// Source edits for the planned changes.
+    fn finish(self, cst: &Cst, index: usize) -> Result<Vec<(Range<usize>, String)>> {
+        let node = &cst.nodes()[index];
+        let mut edits = Vec::new();
+
+        if let Some(text) = self.directive {
+            // Removed directives take the preceding whitespace along.
+            let start = if text.is_empty() {
+                cst.leading_whitespace(node.span.start)
+            } else {
+                node.span.start
+            };
+
+            edits.push((start..node.span.end, text));
+            return Ok(edits);
+        }
+
+        let strings = cst.tokens()[node.tokens.clone()]
+            .iter()
+            .filter(|token| token.kind == TokenKind::String);
+
+        for (position, text) in self.header {
+            let token = strings.clone().nth(position).ok_or(Error::NoToken)?;
+            edits.push((token.span.start..token.span.end, format!("\"{text}\"")));
+        }
+
+        for entry in self.params {
+            let span = entry.param.span().unwrap_or(node.span);
+            let value = cst.param_value(index, entry.param.name)?;
+
+            if entry.removed {
+                edits.push((cst.leading_whitespace(span.start)..value.end, String::new()));
+                continue;
+            }
+
+            let name = format!("\"{} {}\"", entry.ty, entry.name);
+            if cst.source()[span.start..span.end] != name {
+                edits.push((span.start..span.end, name));
+            }
+
+            if let Some(text) = entry.value {
+                edits.push((value, text));
+            }
+        }
+
+        let end = node.span.end;
+        for param in self.added {
+            edits.push((end..end, format!(" {param}")));
+        }
+
+        Ok(edits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scene;
+
+    #[test]
+    fn upgrade_scene() -> Result<()> {
+        let source = r#"LookAt 0 0 5  0 0 0  0 1 0
+Camera "perspective" "float fov" 45
+Film "image" "integer xresolution" 400 "float maxsampleluminance" 10
+Sampler "lowdiscrepancy" "integer pixelsamples" 16
+PixelFilter "triangle" "float xwidth" 2 "float ywidth" 2
+Integrator "path" "string lightsamplestrategy" "spatial"
+
+WorldBegin
+LightSource "infinite" "string mapname" "sky.exr" "color scale" [ 2 2 2 ] "integer nsamples" 4
+
+# The floor
+TransformBegin
+    Translate 0 -1 0
+    Material "matte" "color Kd" [ .5 .5 .5 ] "bool remaproughness" "true"
+TransformEnd
+
+Texture "base" "color" "checkerboard"
+Texture "checks" "color" "scale" "texture tex1" "base" "color tex2" [ .5 .5 .5 ]
+MakeNamedMaterial "gold" "string type" "metal" "float roughness" .1
+MakeNamedMaterial "mix" "string type" "mix" "string namedmaterial1" "gold"
+    "string namedmaterial2" "gold"
+Shape "trianglemesh" "point P" [ 0 0 0 1 0 0 0 1 0 ] "integer indices" [ 0 1 2 ]
+    "float uv" [ 0 0 1 0 0 1 ] "bool discarddegenerateUVs" "false"
+WorldEnd
+"#;
+
+        let expected = r#"LookAt 0 0 5  0 0 0  0 1 0
+Camera "perspective" "float fov" 45
+Film "rgb" "integer xresolution" 400 "float maxcomponentvalue" 10
+Sampler "paddedsobol" "integer pixelsamples" 16
+PixelFilter "triangle" "float xradius" 2 "float yradius" 2
+Integrator "path" "string lightsamplestrategy" "bvh"
+
+WorldBegin
+LightSource "infinite" "string filename" "sky.exr" "float scale" 2
+
+# The floor
+AttributeBegin
+    Translate 0 -1 0
+    Material "diffuse" "rgb reflectance" [ .5 .5 .5 ] "bool remaproughness" true
+AttributeEnd
+
+Texture "base" "spectrum" "checkerboard"
+Texture "checks" "spectrum" "scale" "texture tex" "base" "float scale" .5
+MakeNamedMaterial "gold" "string type" "conductor" "float roughness" .1
+MakeNamedMaterial "mix" "string type" "mix" "string materials" [ "gold" "gold" ]
+Shape "trianglemesh" "point3 P" [ 0 0 0 1 0 0 0 1 0 ] "integer indices" [ 0 1 2 ]
+    "point2 uv" [ 0 0 1 0 0 1 ]
+"#;
+
+        let upgrade = upgrade(source)?;
+        assert_eq!(upgrade.text, expected);
+        assert!(upgrade.warnings.is_empty(), "{:?}", upgrade.warnings);
+
+        // Upgraded files are valid pbrt-v4 and don't change anymore.
+        assert_eq!(super::upgrade(expected)?.text, expected);
+        assert!(Cst::parse(expected).is_ok());
+        assert!(Scene::load(expected, None).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn materials() -> Result<()> {
+        let upgrade = upgrade(
+            r#"Material "plastic" "color Kd" [ 1 0 0 ] "color Ks" [ .5 .5 .5 ]
+Material "glass" "float index" 1.33
+Material "mirror"
+Material "uber" "float uroughness" 0 "float vroughness" 0
+Material "none"
+"#,
+        )?;
+
+        let expected = r#"Material "coateddiffuse" "rgb reflectance" [ 1 0 0 ] "float roughness" 0.1
+Material "dielectric" "float eta" 1.33
+Material "conductor" "spectrum eta" "metal-Ag-eta" "spectrum k" "metal-Ag-k"
+Material "coateddiffuse" "float uroughness" 0 "float vroughness" 0
+Material "interface"
+"#;
+        assert_eq!(upgrade.text, expected);
+
+        let scene = Scene::load(&format!("WorldBegin\n{expected}"), None)?;
+        assert_eq!(scene.materials.len(), 5);
+
+        assert_eq!(upgrade.warnings.len(), 1);
+        assert_eq!(upgrade.warnings[0].span.line, 1);
+        assert_eq!(
+            upgrade.warnings[0].to_string(),
+            "1:41: \"Ks\" is not supported by \"coateddiffuse\", removed"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn scale_textures() -> Result<()> {
+        let upgrade = upgrade(
+            r#"Texture "base" "color" "checkerboard"
+Texture "a" "color" "scale" "color tex1" [ 2 2 2 ] "texture tex2" "base"
+Texture "b" "color" "scale" "color tex2" [ 1 0 0 ]
+Texture "c" "color" "scale" "texture tex1" "base" "texture tex2" "base"
+Texture "d" "float" "scale" "float tex1" 2 "float tex2" .5
+"#,
+        )?;
+
+        let expected = r#"Texture "base" "spectrum" "checkerboard"
+Texture "a" "spectrum" "scale" "float scale" 2 "texture tex" "base"
+Texture "b" "spectrum" "scale" "rgb tex" [ 1 0 0 ]
+Texture "c" "spectrum" "scale" "texture tex" "base"
+Texture "d" "float" "scale" "float tex" 2 "float scale" .5
+"#;
+        assert_eq!(upgrade.text, expected);
+        assert!(Scene::load(&format!("WorldBegin\n{expected}"), None).is_ok());
+
+        assert_eq!(upgrade.warnings.len(), 1);
+        assert_eq!(upgrade.warnings[0].span.line, 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn warnings() -> Result<()> {
+        let upgrade = upgrade(
+            r#"Integrator "whitted"
+Shape "cone" "float height" 2
+Material "disney"
+Film "image" "float scale" 2
+"#,
+        )?;
+
+        let messages = upgrade
+            .warnings
+            .iter()
+            .map(|warning| warning.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            messages,
+            [
+                "1:1: \"whitted\" is replaced by \"path\"",
+                "2:1: shape \"cone\" is not supported by pbrt-v4",
+                "3:1: material \"disney\" is not supported by pbrt-v4",
+                "4:14: \"scale\" is not supported by film, removed",
+            ]
+        );
+
+        assert_eq!(
+            upgrade.text,
+            "Integrator \"path\"\nShape \"cone\" \"float height\" 2\nMaterial \"disney\"\nFilm \"rgb\"\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn v3_only_in_v3_mode() {
+        let err = Cst::parse("WorldBegin\nWorldEnd\n").unwrap_err();
+        assert!(matches!(err.kind(), Error::UnknownDirective));
+
+        let err = Cst::parse("Material \"matte\" \"color Kd\" [ 1 1 1 ]").unwrap_err();
+        assert!(matches!(err.kind(), Error::InvalidParamType));
+    }
+}
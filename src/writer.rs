@@ -0,0 +1,1859 @@
+//! Scene writer.
+//!
+//! Typed scene data is converted back to directives, parameters that are equal
+//! to the defaults used by the loader are omitted.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter, Write as _},
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use glam::Mat4;
+
+use crate::{
+    color::ColorSpace,
+    param::{write_display, Float, Spectrum},
+    parser::Matrix,
+    types::{
+        Accelerator, AreaLight, BvhSplitMethod, Camera, CoordinateSystem, Film, FilmType,
+        FloatInput, Integrator, Light, LightSampler, Material, MaterialType, Medium, Options,
+        PixelFilter, RandomizeStrategy, Sampler, Shape, SpectrumInput, Texture, TextureClass,
+        TextureInput, TextureMapping, TextureType,
+    },
+    Result, Scene,
+};
+
+impl Scene {
+    /// Write the scene in pbrt-v4 format.
+    ///
+    /// Anonymous materials and entities that share a name get unique names.
+    /// Fails with `InvalidData` if the scene contains infinite or NaN values.
+    pub fn write_to<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_display(writer, self)
+    }
+
+    /// Write the scene to a pbrt-v4 file.
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        let write = || -> Result<()> {
+            let mut writer = BufWriter::new(File::create(path)?);
+            self.write_to(&mut writer)?;
+            writer.flush()?;
+            Ok(())
+        };
+
+        write().map_err(|err| err.with_path(path))
+    }
+}
+
+impl Display for Scene {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let names = Names::new(self);
+
+        write_options(f, &self.options)?;
+
+        if (self.start_time, self.end_time) != (0.0, 1.0) {
+            writeln!(
+                f,
+                "TransformTimes {} {}",
+                Float(self.start_time),
+                Float(self.end_time)
+            )?;
+        }
+
+        // Media are written first, as the camera may be inside one.
+        for (index, medium) in self.mediums.iter().enumerate() {
+            let directive = Directive::medium(&names.mediums[index], &medium.params);
+            let transform = (medium.transform != Mat4::IDENTITY)
+                .then_some((&medium.transform, &medium.transform));
+            write_block(f, "", &directive, directive.color_space, transform, None)?;
+        }
+
+        if let Some(camera) = &self.camera {
+            write_transform(f, "", &camera.transform, &camera.transform_end)?;
+
+            let medium = camera.medium_index.map(|index| &names.mediums[index]);
+            if let Some(medium) = medium {
+                writeln!(f, "MediumInterface \"\" \"{medium}\"")?;
+            }
+            Directive::camera(&camera.params).write(f, "")?;
+            // The medium is part of the graphics state, reset it so it doesn't leak into the world block.
+            if medium.is_some() {
+                writeln!(f, "MediumInterface \"\" \"\"")?;
+            }
+        }
+
+        if let Some(sampler) = &self.sampler {
+            Directive::sampler(sampler, self.options.seed).write(f, "")?;
+        }
+        if let Some(film) = &self.film {
+            Directive::film(film).write(f, "")?;
+        }
+        if let Some(filter) = &self.pixel_filter {
+            Directive::pixel_filter(filter).write(f, "")?;
+        }
+        if let Some(integrator) = &self.integrator {
+            Directive::integrator(integrator, self.options.seed).write(f, "")?;
+        }
+        if let Some(accelerator) = &self.accelerator {
+            Directive::accelerator(accelerator).write(f, "")?;
+        }
+
+        writeln!(f, "\nWorldBegin")?;
+
+        for (index, texture) in self.textures.iter().enumerate() {
+            let directive = Directive::texture(&names.textures[index], texture, &names);
+            let transform = (texture.transform != Mat4::IDENTITY)
+                .then_some((&texture.transform, &texture.transform));
+            write_block(f, "", &directive, texture.color_space, transform, None)?;
+        }
+
+        for (index, material) in self.materials.iter().enumerate() {
+            let directive = Directive::material(&names.materials[index], material, &names);
+            write_block(f, "", &directive, material.color_space, None, None)?;
+        }
+
+        for light in &self.lights {
+            let directive = Directive::light(&light.params);
+            let transform = (light.transform != Mat4::IDENTITY
+                || light.transform_end != Mat4::IDENTITY)
+                .then_some((&light.transform, &light.transform_end));
+            let medium = light
+                .medium_index
+                .map(|index| names.mediums[index].as_str());
+            write_block(f, "", &directive, directive.color_space, transform, medium)?;
+        }
+
+        // Objects are written in place of their first shape, so shapes keep their order.
+        let mut objects = HashMap::new();
+        for (index, object) in self.objects.iter().enumerate() {
+            match object.shape_start {
+                Some(start) => {
+                    objects.insert(start, index);
+                }
+                None => write_object(f, self, &names, index)?,
+            }
+        }
+
+        let mut index = 0;
+        while index < self.shapes.len() {
+            if let Some(&object) = objects.get(&index) {
+                write_object(f, self, &names, object)?;
+                index += self.objects[object].shape_count;
+            } else {
+                write_shape(f, self, &names, "", index)?;
+                index += 1;
+            }
+        }
+
+        for instance in &self.instances {
+            writeln!(f, "\nAttributeBegin")?;
+            write_transform(
+                f,
+                "    ",
+                &instance.instance_to_world,
+                &instance.instance_to_world_end,
+            )?;
+            if instance.reverse_orientation {
+                writeln!(f, "    ReverseOrientation")?;
+            }
+            if let Some(index) = instance.area_light_index {
+                write_area_light(f, "    ", &self.area_lights[index])?;
+            }
+            writeln!(
+                f,
+                "    ObjectInstance \"{}\"",
+                names.objects[instance.object_index]
+            )?;
+            writeln!(f, "AttributeEnd")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Unique names of scene entities that are referenced by name in scene files.
+struct Names {
+    textures: Vec<String>,
+    materials: Vec<String>,
+    mediums: Vec<String>,
+    objects: Vec<String>,
+}
+
+impl Names {
+    fn new(scene: &Scene) -> Self {
+        let textures = |ty| {
+            scene
+                .textures
+                .iter()
+                .map(|texture| (texture.ty == ty).then_some(texture.name.as_str()))
+                .collect::<Vec<_>>()
+        };
+
+        let float = unique_names(&textures(TextureType::Float), "texture");
+        let spectrum = unique_names(&textures(TextureType::Spectrum), "texture");
+
+        let textures = scene
+            .textures
+            .iter()
+            .enumerate()
+            .map(|(index, texture)| match texture.ty {
+                TextureType::Float => float[index].clone(),
+                TextureType::Spectrum => spectrum[index].clone(),
+            })
+            .collect();
+
+        let materials = scene
+            .materials
+            .iter()
+            .map(|material| material.name.as_deref())
+            .collect::<Vec<_>>();
+
+        let mediums = scene
+            .mediums
+            .iter()
+            .map(|medium| Some(medium.name.as_str()))
+            .collect::<Vec<_>>();
+
+        let objects = scene
+            .objects
+            .iter()
+            .map(|object| Some(object.name.as_str()))
+            .collect::<Vec<_>>();
+
+        Names {
+            textures,
+            materials: unique_names(&materials, "material"),
+            mediums: unique_names(&mediums, "medium"),
+            objects: unique_names(&objects, "object"),
+        }
+    }
+}
+
+/// Assign a unique name to each entity.
+///
+/// The last definition of a name keeps it, as that's the one name lookups resolve to
+/// after the whole file is loaded. Unnamed and shadowed entities get a name derived from
+/// `prefix` or the original name and the entity index.
+fn unique_names(names: &[Option<&str>], prefix: &str) -> Vec<String> {
+    let mut last = HashMap::new();
+    for (index, name) in names.iter().enumerate() {
+        if let Some(name) = name {
+            last.insert(*name, index);
+        }
+    }
+
+    names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| match name {
+            Some(name) if last[name] == index => name.to_string(),
+            _ => {
+                let base = name.unwrap_or(prefix);
+                let mut name = format!("{base}-{index}");
+                while last.contains_key(name.as_str()) {
+                    name.push('_');
+                }
+                name
+            }
+        })
+        .collect()
+}
+
+fn write_options(f: &mut Formatter<'_>, options: &Options) -> fmt::Result {
+    let defaults = Options::default();
+    let mut params = Params::default();
+
+    params.boolean(
+        "disablepixeljitter",
+        options.disable_pixel_jitter,
+        defaults.disable_pixel_jitter,
+    );
+    params.boolean(
+        "disabletexturefiltering",
+        options.disable_texture_filtering,
+        defaults.disable_texture_filtering,
+    );
+    params.boolean(
+        "disablewavelengthjitter",
+        options.disable_wavelength_jitter,
+        defaults.disable_wavelength_jitter,
+    );
+    params.float(
+        "displacementedgescale",
+        options.displacement_edge_scale,
+        defaults.displacement_edge_scale,
+    );
+    params.optional_string("msereferenceimage", options.mse_reference_image.as_deref());
+    params.optional_string("msereferenceout", options.mse_reference_out.as_deref());
+
+    let coord_sys = match options.render_coord_sys {
+        CoordinateSystem::CameraWorld => "cameraworld",
+        CoordinateSystem::Camera => "camera",
+        CoordinateSystem::World => "world",
+    };
+    params.string("rendercoordsys", coord_sys, "cameraworld");
+    params.integer("seed", options.seed, defaults.seed);
+    params.boolean(
+        "forcediffuse",
+        options.force_diffuse,
+        defaults.force_diffuse,
+    );
+    params.boolean("pixelstats", options.pixel_stats, defaults.pixel_stats);
+    params.boolean("wavefront", options.wavefront, defaults.wavefront);
+
+    for param in params.lines()? {
+        writeln!(f, "Option {param}")?;
+    }
+
+    Ok(())
+}
+
+/// Write the transformation, both the start and the end ones if they differ.
+fn write_transform(f: &mut Formatter<'_>, indent: &str, start: &Mat4, end: &Mat4) -> fmt::Result {
+    if start == end {
+        if *start != Mat4::IDENTITY {
+            writeln!(f, "{indent}Transform {}", Matrix(&start.to_cols_array()))?;
+        }
+        return Ok(());
+    }
+
+    writeln!(f, "{indent}ActiveTransform StartTime")?;
+    writeln!(f, "{indent}Transform {}", Matrix(&start.to_cols_array()))?;
+    writeln!(f, "{indent}ActiveTransform EndTime")?;
+    writeln!(f, "{indent}Transform {}", Matrix(&end.to_cols_array()))?;
+    writeln!(f, "{indent}ActiveTransform All")
+}
+
+/// Write a world directive, wrapped in an attribute block if it needs the graphics state.
+///
+/// `medium` is the exterior medium.
+fn write_block(
+    f: &mut Formatter<'_>,
+    indent: &str,
+    directive: &Directive,
+    color_space: ColorSpace,
+    transform: Option<(&Mat4, &Mat4)>,
+    medium: Option<&str>,
+) -> fmt::Result {
+    if color_space == ColorSpace::default() && transform.is_none() && medium.is_none() {
+        return directive.write(f, indent);
+    }
+
+    writeln!(f, "{indent}AttributeBegin")?;
+    if let Some((start, end)) = transform {
+        write_transform(f, &format!("{indent}    "), start, end)?;
+    }
+    if color_space != ColorSpace::default() {
+        writeln!(f, "{indent}    ColorSpace \"{color_space}\"")?;
+    }
+    if let Some(medium) = medium {
+        writeln!(f, "{indent}    MediumInterface \"\" \"{medium}\"")?;
+    }
+    directive.write(f, &format!("{indent}    "))?;
+    writeln!(f, "{indent}AttributeEnd")
+}
+
+fn write_area_light(f: &mut Formatter<'_>, indent: &str, light: &AreaLight) -> fmt::Result {
+    let directive = Directive::area_light(light);
+    if directive.color_space != ColorSpace::default() {
+        writeln!(f, "{indent}ColorSpace \"{}\"", directive.color_space)?;
+    }
+    directive.write(f, indent)
+}
+
+fn write_shape(
+    f: &mut Formatter<'_>,
+    scene: &Scene,
+    names: &Names,
+    indent: &str,
+    index: usize,
+) -> fmt::Result {
+    let shape = &scene.shapes[index];
+
+    writeln!(f, "\n{indent}AttributeBegin")?;
+
+    let inner = format!("{indent}    ");
+    write_transform(f, &inner, &shape.transform, &shape.transform_end)?;
+    if shape.reverse_orientation {
+        writeln!(f, "{inner}ReverseOrientation")?;
+    }
+    if let Some(index) = shape.material_index {
+        writeln!(f, "{inner}NamedMaterial \"{}\"", names.materials[index])?;
+    }
+    if let Some(index) = shape.area_light_index {
+        write_area_light(f, &inner, &scene.area_lights[index])?;
+    }
+    if shape.inside_medium_index.is_some() || shape.outside_medium_index.is_some() {
+        let medium = |index: Option<usize>| index.map_or("", |index| &names.mediums[index]);
+        writeln!(
+            f,
+            "{inner}MediumInterface \"{}\" \"{}\"",
+            medium(shape.inside_medium_index),
+            medium(shape.outside_medium_index)
+        )?;
+    }
+    Directive::shape(&shape.params).write(f, &inner)?;
+
+    writeln!(f, "{indent}AttributeEnd")
+}
+
+fn write_object(f: &mut Formatter<'_>, scene: &Scene, names: &Names, index: usize) -> fmt::Result {
+    let object = &scene.objects[index];
+
+    writeln!(f, "\nObjectBegin \"{}\"", names.objects[index])?;
+    if let Some(start) = object.shape_start {
+        for shape in start..start + object.shape_count {
+            write_shape(f, scene, names, "    ", shape)?;
+        }
+    }
+    writeln!(f, "ObjectEnd")
+}
+
+/// A directive with the type or name and parameters.
+struct Directive {
+    /// Directive keyword and its string arguments.
+    head: String,
+    params: Params,
+    /// Color space of the RGB parameters.
+    color_space: ColorSpace,
+}
+
+impl Directive {
+    /// Write the directive line followed by parameters, one per line.
+    fn write(&self, f: &mut Formatter<'_>, indent: &str) -> fmt::Result {
+        write!(f, "{indent}{}", self.head)?;
+        for param in self.params.lines()? {
+            write!(f, "\n{indent}    {param}")?;
+        }
+        writeln!(f)
+    }
+
+    fn new(keyword: &str, ty: &str, params: Params) -> Self {
+        Directive {
+            head: format!("{keyword} \"{ty}\""),
+            params,
+            color_space: ColorSpace::default(),
+        }
+    }
+
+    fn camera(camera: &Camera) -> Self {
+        let mut params = Params::default();
+
+        let (ty, shutter_open, shutter_close) = match camera {
+            Camera::Orthographic {
+                shutter_open,
+                shutter_close,
+            } => ("orthographic", shutter_open, shutter_close),
+            Camera::Perspective {
+                shutter_open,
+                shutter_close,
+                fov,
+            } => {
+                params.float("fov", *fov, 90.0);
+                ("perspective", shutter_open, shutter_close)
+            }
+            Camera::Realistic {
+                shutter_open,
+                shutter_close,
+                lensfile,
+                aperture_diameter,
+                focus_distance,
+                aperture,
+            } => {
+                params.optional_string("lensfile", lensfile.as_deref());
+                params.float("aperturediameter", *aperture_diameter, 1.0);
+                params.float("focusdistance", *focus_distance, 10.0);
+                params.optional_string("aperture", aperture.as_deref());
+                ("realistic", shutter_open, shutter_close)
+            }
+            Camera::Spherical {
+                shutter_open,
+                shutter_close,
+                mapping,
+            } => {
+                params.string("mapping", mapping, "equalarea");
+                ("spherical", shutter_open, shutter_close)
+            }
+        };
+
+        params.float("shutteropen", *shutter_open, 0.0);
+        params.float("shutterclose", *shutter_close, 1.0);
+
+        Directive::new("Camera", ty, params)
+    }
+
+    /// `seed` is the scene-wide seed option, the default of the sampler seed.
+    fn sampler(sampler: &Sampler, seed: i32) -> Self {
+        let mut params = Params::default();
+
+        let (ty, randomization) = match sampler {
+            Sampler::Halton { .. } => ("halton", RandomizeStrategy::PermuteDigits),
+            Sampler::Independent { .. } => ("independent", RandomizeStrategy::None),
+            Sampler::PaddedSobol { .. } => ("paddedsobol", RandomizeStrategy::FastOwen),
+            Sampler::Pmj02bn { .. } => ("pmj02bn", RandomizeStrategy::None),
+            Sampler::Sobol { .. } => ("sobol", RandomizeStrategy::FastOwen),
+            Sampler::Stratified {
+                xsamples,
+                ysamples,
+                jitter,
+                ..
+            } => {
+                params.integer("xsamples", *xsamples, 4);
+                params.integer("ysamples", *ysamples, 4);
+                params.boolean("jitter", *jitter, true);
+                ("stratified", RandomizeStrategy::None)
+            }
+            Sampler::ZSobol { .. } => ("zsobol", RandomizeStrategy::FastOwen),
+        };
+
+        if !matches!(sampler, Sampler::Stratified { .. }) {
+            let default = if ty == "independent" { 4 } else { 16 };
+            params.integer("pixelsamples", sampler.pixel_samples(), default);
+        }
+        params.integer("seed", sampler.seed(), seed);
+
+        if let Some(value) = sampler.randomization() {
+            if value != randomization {
+                let value = match value {
+                    RandomizeStrategy::None => "none",
+                    RandomizeStrategy::PermuteDigits => "permutedigits",
+                    RandomizeStrategy::FastOwen => "fastowen",
+                    RandomizeStrategy::Owen => "owen",
+                };
+                params.add("string", "randomization", format!("\"{value}\""));
+            }
+        }
+
+        Directive::new("Sampler", ty, params)
+    }
+
+    fn film(film: &Film) -> Self {
+        let mut params = Params::default();
+
+        let ty = match &film.ty {
+            FilmType::Rgb => "rgb",
+            FilmType::GBuffer { coordinate_system } => {
+                params.string("coordinatesystem", coordinate_system, "camera");
+                "gbuffer"
+            }
+            FilmType::Spectral {
+                nbuckets,
+                lambda_min,
+                lambda_max,
+            } => {
+                params.integer("nbuckets", *nbuckets, 16);
+                params.float("lambdamin", *lambda_min, 360.0);
+                params.float("lambdamax", *lambda_max, 830.0);
+                "spectral"
+            }
+        };
+
+        params.integer("xresolution", film.xresolution, 1280);
+        params.integer("yresolution", film.yresolution, 720);
+        if film.crop_window != [0.0, 1.0, 0.0, 1.0] {
+            params.floats("float", "cropwindow", &film.crop_window);
+        }
+        params.float("diagonal", film.diagonal, 35.0);
+        params.string("filename", &film.filename, "pbrt.exr");
+        params.boolean("savefp16", film.save_fp16, true);
+        params.float("iso", film.iso, 100.0);
+        params.float("whitebalance", film.white_balance, 0.0);
+        params.string("sensor", &film.sensor, "cie1931");
+        params.float("maxcomponentvalue", film.max_component_value, f32::MAX);
+
+        Directive::new("Film", ty, params)
+    }
+
+    fn pixel_filter(filter: &PixelFilter) -> Self {
+        let mut params = Params::default();
+
+        let (ty, radius) = match filter {
+            PixelFilter::Box { .. } => ("box", 0.5),
+            PixelFilter::Gaussian { .. } => ("gaussian", 1.5),
+            PixelFilter::Mitchell { .. } => ("mitchell", 2.0),
+            PixelFilter::Sinc { .. } => ("sinc", 4.0),
+            PixelFilter::Triangle { .. } => ("triangle", 2.0),
+        };
+
+        let [xradius, yradius] = filter.radius();
+        params.float("xradius", xradius, radius);
+        params.float("yradius", yradius, radius);
+
+        match filter {
+            PixelFilter::Gaussian { sigma, .. } => params.float("sigma", *sigma, 0.5),
+            PixelFilter::Mitchell { b, c, .. } => {
+                params.float("B", *b, 1.0 / 3.0);
+                params.float("C", *c, 1.0 / 3.0);
+            }
+            PixelFilter::Sinc { tau, .. } => params.float("tau", *tau, 3.0),
+            PixelFilter::Box { .. } | PixelFilter::Triangle { .. } => {}
+        }
+
+        Directive::new("PixelFilter", ty, params)
+    }
+
+    /// `seed` is the scene-wide seed option, the default of the `sppm` seed.
+    fn integrator(integrator: &Integrator, seed: i32) -> Self {
+        let mut params = Params::default();
+
+        let ty = match integrator {
+            Integrator::AmbientOcclusion {
+                cos_sample,
+                max_distance,
+            } => {
+                params.boolean("cossample", *cos_sample, true);
+                params.float("maxdistance", *max_distance, f32::INFINITY);
+                "ambientocclusion"
+            }
+            Integrator::Bdpt {
+                max_depth,
+                light_sampler,
+                regularize,
+                visualize_strategies,
+                visualize_weights,
+            } => {
+                params.integer("maxdepth", *max_depth, 5);
+                params.light_sampler(*light_sampler, LightSampler::Power);
+                params.boolean("regularize", *regularize, false);
+                params.boolean("visualizestrategies", *visualize_strategies, false);
+                params.boolean("visualizeweights", *visualize_weights, false);
+                "bdpt"
+            }
+            Integrator::LightPath { max_depth } => {
+                params.integer("maxdepth", *max_depth, 5);
+                "lightpath"
+            }
+            Integrator::Mlt {
+                max_depth,
+                bootstrap_samples,
+                chains,
+                mutations_per_pixel,
+                large_step_probability,
+                sigma,
+                regularize,
+            } => {
+                params.integer("maxdepth", *max_depth, 5);
+                params.integer("bootstrapsamples", *bootstrap_samples, 100000);
+                params.integer("chains", *chains, 1000);
+                params.integer("mutationsperpixel", *mutations_per_pixel, 100);
+                params.float("largestepprobability", *large_step_probability, 0.3);
+                params.float("sigma", *sigma, 0.01);
+                params.boolean("regularize", *regularize, false);
+                "mlt"
+            }
+            Integrator::Path {
+                max_depth,
+                light_sampler,
+                regularize,
+            } => {
+                params.integer("maxdepth", *max_depth, 5);
+                params.light_sampler(*light_sampler, LightSampler::Bvh);
+                params.boolean("regularize", *regularize, false);
+                "path"
+            }
+            Integrator::RandomWalk { max_depth } => {
+                params.integer("maxdepth", *max_depth, 5);
+                "randomwalk"
+            }
+            Integrator::SimplePath {
+                max_depth,
+                sample_lights,
+                sample_bsdf,
+            } => {
+                params.integer("maxdepth", *max_depth, 5);
+                params.boolean("samplelights", *sample_lights, true);
+                params.boolean("samplebsdf", *sample_bsdf, true);
+                "simplepath"
+            }
+            Integrator::SimpleVolPath { max_depth } => {
+                params.integer("maxdepth", *max_depth, 5);
+                "simplevolpath"
+            }
+            Integrator::Sppm {
+                max_depth,
+                photons_per_iteration,
+                radius,
+                seed: sppm_seed,
+            } => {
+                params.integer("maxdepth", *max_depth, 5);
+                params.integer("photonsperiteration", *photons_per_iteration, -1);
+                params.float("radius", *radius, 1.0);
+                params.integer("seed", *sppm_seed, seed);
+                "sppm"
+            }
+            Integrator::VolPath {
+                max_depth,
+                light_sampler,
+                regularize,
+            } => {
+                params.integer("maxdepth", *max_depth, 5);
+                params.light_sampler(*light_sampler, LightSampler::Bvh);
+                params.boolean("regularize", *regularize, false);
+                "volpath"
+            }
+        };
+
+        Directive::new("Integrator", ty, params)
+    }
+
+    fn accelerator(accelerator: &Accelerator) -> Self {
+        let mut params = Params::default();
+
+        let ty = match accelerator {
+            Accelerator::Bvh {
+                max_node_prims,
+                split_method,
+            } => {
+                let split_method = match split_method {
+                    BvhSplitMethod::Sah => "sah",
+                    BvhSplitMethod::Middle => "middle",
+                    BvhSplitMethod::Equal => "equal",
+                    BvhSplitMethod::Hlbvh => "hlbvh",
+                };
+
+                params.integer("maxnodeprims", *max_node_prims, 4);
+                params.string("splitmethod", split_method, "sah");
+                "bvh"
+            }
+            Accelerator::KdTree {
+                intersect_cost,
+                traversal_cost,
+                empty_bonus,
+                max_prims,
+                max_depth,
+            } => {
+                params.integer("intersectcost", *intersect_cost, 5);
+                params.integer("traversalcost", *traversal_cost, 1);
+                params.float("emptybonus", *empty_bonus, 0.5);
+                params.integer("maxprims", *max_prims, 1);
+                params.integer("maxdepth", *max_depth, -1);
+                "kdtree"
+            }
+        };
+
+        Directive::new("Accelerator", ty, params)
+    }
+
+    fn light(light: &Light) -> Self {
+        let mut params = Params::default();
+        let mut color_space = ColorSpace::default();
+
+        let mut spectrum = |name: &str, spectrum: &Option<Spectrum>| {
+            if let Some(spectrum) = spectrum {
+                color_space = spectrum_color_space(spectrum);
+                params.spectrum(name, spectrum);
+            }
+        };
+
+        let (ty, scale, power) = match light {
+            Light::Distant {
+                spectrum: l,
+                scale,
+                from,
+                to,
+                illuminance,
+            } => {
+                spectrum("L", l);
+                params.vector("from", from, [0.0, 0.0, 0.0]);
+                params.vector("to", to, [0.0, 0.0, 1.0]);
+                params.optional_float("illuminance", *illuminance);
+                ("distant", scale, None)
+            }
+            Light::GonioPhotometric {
+                spectrum: i,
+                scale,
+                power,
+                filename,
+            } => {
+                spectrum("I", i);
+                params.optional_string("filename", filename.as_deref());
+                ("goniometric", scale, *power)
+            }
+            Light::Infinite {
+                filename,
+                spectrum: l,
+                scale,
+                portal,
+                illuminance,
+            } => {
+                spectrum("L", l);
+                params.optional_string("filename", filename.as_deref());
+                if let Some(portal) = portal {
+                    params.floats("point3", "portal", portal.concat().as_slice());
+                }
+                params.optional_float("illuminance", *illuminance);
+                ("infinite", scale, None)
+            }
+            Light::Point {
+                spectrum: i,
+                scale,
+                power,
+                from,
+            } => {
+                spectrum("I", i);
+                params.vector("from", from, [0.0, 0.0, 0.0]);
+                ("point", scale, *power)
+            }
+            Light::Projection {
+                scale,
+                power,
+                fov,
+                filename,
+            } => {
+                params.float("fov", *fov, 90.0);
+                params.add("string", "filename", format!("\"{filename}\""));
+                ("projection", scale, *power)
+            }
+            Light::Spot {
+                spectrum: i,
+                scale,
+                power,
+                from,
+                to,
+                cone_angle,
+                cone_delta,
+            } => {
+                spectrum("I", i);
+                params.vector("from", from, [0.0, 0.0, 0.0]);
+                params.vector("to", to, [0.0, 0.0, 1.0]);
+                params.float("coneangle", *cone_angle, 30.0);
+                params.float("conedelta", *cone_delta, 5.0);
+                ("spot", scale, *power)
+            }
+        };
+
+        params.float("scale", *scale, 1.0);
+        params.optional_float("power", power);
+
+        Directive {
+            color_space,
+            ..Directive::new("LightSource", ty, params)
+        }
+    }
+
+    fn area_light(light: &AreaLight) -> Self {
+        let mut params = Params::default();
+        let mut color_space = ColorSpace::default();
+
+        let ty = match light {
+            AreaLight::Diffuse {
+                filename,
+                two_sided,
+                spectrum,
+                scale,
+                power,
+            } => {
+                params.optional_string("filename", filename.as_deref());
+                params.boolean("twosided", *two_sided, false);
+                if let Some(spectrum) = spectrum {
+                    color_space = spectrum_color_space(spectrum);
+                    params.spectrum("L", spectrum);
+                }
+                params.float("scale", *scale, 1.0);
+                params.optional_float("power", *power);
+                "diffuse"
+            }
+        };
+
+        Directive {
+            color_space,
+            ..Directive::new("AreaLightSource", ty, params)
+        }
+    }
+
+    fn medium(name: &str, medium: &Medium) -> Self {
+        let mut params = Params::default();
+        let mut color_space = ColorSpace::default();
+
+        let mut spectrum = |params: &mut Params, name: &str, spectrum: &Option<Spectrum>| {
+            if let Some(spectrum) = spectrum {
+                if let Spectrum::Rgb(_, rgb_color_space) = spectrum {
+                    color_space = *rgb_color_space;
+                }
+                params.spectrum(name, spectrum);
+            }
+        };
+
+        let (ty, scale, g) = match medium {
+            Medium::Homogeneous {
+                preset,
+                sigma_a,
+                sigma_s,
+                scale,
+                g,
+                le,
+                le_scale,
+            } => {
+                params.optional_string("preset", preset.as_deref());
+                spectrum(&mut params, "sigma_a", sigma_a);
+                spectrum(&mut params, "sigma_s", sigma_s);
+                spectrum(&mut params, "Le", le);
+                params.float("Lescale", *le_scale, 1.0);
+                ("homogeneous", scale, g)
+            }
+            Medium::UniformGrid {
+                preset,
+                sigma_a,
+                sigma_s,
+                scale,
+                g,
+                nx,
+                ny,
+                nz,
+                p0,
+                p1,
+                density,
+                temperature,
+                temperature_cutoff,
+                temperature_scale,
+                le,
+                le_scale,
+            } => {
+                params.optional_string("preset", preset.as_deref());
+                spectrum(&mut params, "sigma_a", sigma_a);
+                spectrum(&mut params, "sigma_s", sigma_s);
+                params.grid([*nx, *ny, *nz], p0, p1);
+                params.floats("float", "density", density);
+                params.floats("float", "temperature", temperature);
+                params.float("temperaturecutoff", *temperature_cutoff, 0.0);
+                params.float("temperaturescale", *temperature_scale, 1.0);
+                spectrum(&mut params, "Le", le);
+                params.float("Lescale", *le_scale, 1.0);
+                ("uniformgrid", scale, g)
+            }
+            Medium::RgbGrid {
+                scale,
+                g,
+                nx,
+                ny,
+                nz,
+                p0,
+                p1,
+                sigma_a,
+                sigma_s,
+                le,
+                le_scale,
+                color_space: rgb_color_space,
+            } => {
+                color_space = *rgb_color_space;
+                params.grid([*nx, *ny, *nz], p0, p1);
+                params.floats("rgb", "sigma_a", sigma_a.concat().as_slice());
+                params.floats("rgb", "sigma_s", sigma_s.concat().as_slice());
+                params.floats("rgb", "Le", le.concat().as_slice());
+                params.float("Lescale", *le_scale, 1.0);
+                ("rgbgrid", scale, g)
+            }
+            Medium::Cloud {
+                preset,
+                sigma_a,
+                sigma_s,
+                scale,
+                g,
+                density,
+                wispiness,
+                frequency,
+                p0,
+                p1,
+            } => {
+                params.optional_string("preset", preset.as_deref());
+                spectrum(&mut params, "sigma_a", sigma_a);
+                spectrum(&mut params, "sigma_s", sigma_s);
+                params.float("density", *density, 1.0);
+                params.float("wispiness", *wispiness, 1.0);
+                params.float("frequency", *frequency, 5.0);
+                params.vector("p0", p0, [0.0, 0.0, 0.0]);
+                params.vector("p1", p1, [1.0, 1.0, 1.0]);
+                ("cloud", scale, g)
+            }
+            Medium::NanoVdb {
+                preset,
+                sigma_a,
+                sigma_s,
+                scale,
+                g,
+                filename,
+                le_scale,
+                temperature_cutoff,
+                temperature_scale,
+            } => {
+                params.optional_string("preset", preset.as_deref());
+                spectrum(&mut params, "sigma_a", sigma_a);
+                spectrum(&mut params, "sigma_s", sigma_s);
+                params.add("string", "filename", format!("\"{filename}\""));
+                params.float("Lescale", *le_scale, 1.0);
+                params.float("temperaturecutoff", *temperature_cutoff, 0.0);
+                params.float("temperaturescale", *temperature_scale, 1.0);
+                ("nanovdb", scale, g)
+            }
+        };
+
+        params.float("scale", *scale, 1.0);
+        params.float("g", *g, 0.0);
+        params.params.insert(0, format!("\"string type\" \"{ty}\""));
+
+        Directive {
+            color_space,
+            ..Directive::new("MakeNamedMedium", name, params)
+        }
+    }
+
+    fn shape(shape: &Shape) -> Self {
+        let mut params = Params::default();
+
+        let (ty, alpha) = match shape {
+            Shape::Cylinder {
+                alpha,
+                radius,
+                zmin,
+                zmax,
+                phimax,
+            } => {
+                params.float("radius", *radius, 1.0);
+                params.float("zmin", *zmin, -1.0);
+                params.float("zmax", *zmax, 1.0);
+                params.float("phimax", *phimax, 360.0);
+                ("cylinder", alpha)
+            }
+            Shape::Disk {
+                alpha,
+                height,
+                radius,
+                innerradius,
+                phimax,
+            } => {
+                params.float("height", *height, 0.0);
+                params.float("radius", *radius, 1.0);
+                params.float("innerradius", *innerradius, 0.0);
+                params.float("phimax", *phimax, 360.0);
+                ("disk", alpha)
+            }
+            Shape::Sphere {
+                alpha,
+                radius,
+                zmin,
+                zmax,
+                phimax,
+            } => {
+                params.float("radius", *radius, 1.0);
+                params.float("zmin", *zmin, -radius);
+                params.float("zmax", *zmax, *radius);
+                params.float("phimax", *phimax, 360.0);
+                ("sphere", alpha)
+            }
+            Shape::TriangleMesh {
+                alpha,
+                indices,
+                positions,
+                normals,
+                tangents,
+                uvs,
+            } => {
+                params.integers("indices", indices);
+                params.floats("point3", "P", positions);
+                params.floats("normal", "N", normals);
+                params.floats("vector3", "S", tangents);
+                params.floats("point2", "uv", uvs);
+                ("trianglemesh", alpha)
+            }
+            Shape::PlyMesh { alpha, filename } => {
+                params.optional_string("filename", Some(filename));
+                ("plymesh", alpha)
+            }
+        };
+
+        params.float("alpha", *alpha, 1.0);
+
+        Directive::new("Shape", ty, params)
+    }
+
+    fn texture(name: &str, texture: &Texture, names: &Names) -> Self {
+        let mut params = Params::default();
+        let textures = &names.textures;
+
+        // Parameters have the same type as the texture itself.
+        let mut input = |name: &str, input: &TextureInput, default: f32| match input {
+            TextureInput::Float(input) => params.float_input(name, input, default, textures),
+            TextureInput::Spectrum(input) => {
+                params.spectrum_input(name, input, &Spectrum::Constant(default), textures)
+            }
+        };
+
+        let (class, mapping) = match &texture.class {
+            TextureClass::Bilerp {
+                v00,
+                v01,
+                v10,
+                v11,
+                mapping,
+            } => {
+                input("v00", v00, 0.0);
+                input("v01", v01, 1.0);
+                input("v10", v10, 0.0);
+                input("v11", v11, 1.0);
+                ("bilerp", Some(mapping))
+            }
+            TextureClass::Checkerboard {
+                dimension,
+                tex1,
+                tex2,
+                mapping,
+            } => {
+                input("tex1", tex1, 1.0);
+                input("tex2", tex2, 0.0);
+                params.integer("dimension", *dimension, 2);
+                ("checkerboard", Some(mapping))
+            }
+            TextureClass::Constant { value } => {
+                input("value", value, 1.0);
+                ("constant", None)
+            }
+            TextureClass::DirectionMix { tex1, tex2, dir } => {
+                input("tex1", tex1, 0.0);
+                input("tex2", tex2, 1.0);
+                if *dir != [0.0, 1.0, 0.0] {
+                    params.floats("vector3", "dir", dir);
+                }
+                ("directionmix", None)
+            }
+            TextureClass::Dots {
+                inside,
+                outside,
+                mapping,
+            } => {
+                input("inside", inside, 1.0);
+                input("outside", outside, 0.0);
+                ("dots", Some(mapping))
+            }
+            TextureClass::Fbm { octaves, roughness } => {
+                params.integer("octaves", *octaves, 8);
+                params.float("roughness", *roughness, 0.5);
+                ("fbm", None)
+            }
+            TextureClass::ImageMap {
+                filename,
+                max_anisotropy,
+                filter,
+                wrap,
+                scale,
+                invert,
+                encoding,
+                mapping,
+            } => {
+                let default_encoding = if filename.ends_with(".png") {
+                    "sRGB"
+                } else {
+                    "linear"
+                };
+
+                params.optional_string("filename", Some(filename));
+                params.float("maxanisotropy", *max_anisotropy, 8.0);
+                params.string("filter", filter, "bilinear");
+                params.string("wrap", wrap, "repeat");
+                params.float("scale", *scale, 1.0);
+                params.boolean("invert", *invert, false);
+                params.string("encoding", encoding, default_encoding);
+                ("imagemap", Some(mapping))
+            }
+            TextureClass::Marble {
+                octaves,
+                roughness,
+                scale,
+                variation,
+            } => {
+                params.integer("octaves", *octaves, 8);
+                params.float("roughness", *roughness, 0.5);
+                params.float("scale", *scale, 1.0);
+                params.float("variation", *variation, 0.2);
+                ("marble", None)
+            }
+            TextureClass::Mix { tex1, tex2, amount } => {
+                input("tex1", tex1, 0.0);
+                input("tex2", tex2, 1.0);
+                params.float_input("amount", amount, 0.5, textures);
+                ("mix", None)
+            }
+            TextureClass::Ptex {
+                filename,
+                encoding,
+                scale,
+            } => {
+                params.optional_string("filename", Some(filename));
+                params.string("encoding", encoding, "gamma 2.2");
+                params.float("scale", *scale, 1.0);
+                ("ptex", None)
+            }
+            TextureClass::Scale { tex, scale } => {
+                input("tex", tex, 1.0);
+                params.float_input("scale", scale, 1.0, textures);
+                ("scale", None)
+            }
+            TextureClass::Windy => ("windy", None),
+            TextureClass::Wrinkled { octaves, roughness } => {
+                params.integer("octaves", *octaves, 8);
+                params.float("roughness", *roughness, 0.5);
+                ("wrinkled", None)
+            }
+        };
+
+        if let Some(mapping) = mapping {
+            params.mapping(mapping);
+        }
+
+        let ty = match texture.ty {
+            TextureType::Float => "float",
+            TextureType::Spectrum => "spectrum",
+        };
+
+        Directive {
+            head: format!("Texture \"{name}\" \"{ty}\" \"{class}\""),
+            params,
+            color_space: texture.color_space,
+        }
+    }
+
+    fn material(name: &str, material: &Material, names: &Names) -> Self {
+        let mut params = Params::default();
+        let textures = &names.textures;
+
+        // Material type goes first, it's filled in once known.
+        params.params.push(String::new());
+
+        let constant = |value: f32| Spectrum::Constant(value);
+
+        let ty = match &material.ty {
+            MaterialType::CoatedDiffuse {
+                albedo,
+                g,
+                max_depth,
+                n_samples,
+                reflectance,
+                uroughness,
+                vroughness,
+                thickness,
+                eta,
+                remap_roughness,
+            } => {
+                params.spectrum_input("albedo", albedo, &constant(0.0), textures);
+                params.float_input("g", g, 0.0, textures);
+                params.integer("maxdepth", *max_depth, 10);
+                params.integer("nsamples", *n_samples, 1);
+                params.spectrum_input("reflectance", reflectance, &constant(0.5), textures);
+                params.float_input("uroughness", uroughness, 0.0, textures);
+                params.float_input("vroughness", vroughness, 0.0, textures);
+                params.float_input("thickness", thickness, 0.01, textures);
+                params.default_spectrum("eta", eta, &constant(1.5));
+                params.boolean("remaproughness", *remap_roughness, true);
+                "coateddiffuse"
+            }
+            MaterialType::CoatedConductor {
+                interface_eta,
+                interface_uroughness,
+                interface_vroughness,
+                thickness,
+                conductor_eta,
+                conductor_k,
+                conductor_uroughness,
+                conductor_vroughness,
+                reflectance,
+                albedo,
+                g,
+                max_depth,
+                n_samples,
+                remap_roughness,
+            } => {
+                params.default_spectrum("interface.eta", interface_eta, &constant(1.5));
+                params.float_input("interface.uroughness", interface_uroughness, 0.0, textures);
+                params.float_input("interface.vroughness", interface_vroughness, 0.0, textures);
+                params.float_input("thickness", thickness, 0.01, textures);
+                params.spectrum_input(
+                    "conductor.eta",
+                    conductor_eta,
+                    &Spectrum::Named("metal-Cu-eta".to_string()),
+                    textures,
+                );
+                params.spectrum_input(
+                    "conductor.k",
+                    conductor_k,
+                    &Spectrum::Named("metal-Cu-k".to_string()),
+                    textures,
+                );
+                params.float_input("conductor.uroughness", conductor_uroughness, 0.0, textures);
+                params.float_input("conductor.vroughness", conductor_vroughness, 0.0, textures);
+                params.optional_spectrum_input("reflectance", reflectance.as_ref(), textures);
+                params.spectrum_input("albedo", albedo, &constant(0.0), textures);
+                params.float_input("g", g, 0.0, textures);
+                params.integer("maxdepth", *max_depth, 10);
+                params.integer("nsamples", *n_samples, 1);
+                params.boolean("remaproughness", *remap_roughness, true);
+                "coatedconductor"
+            }
+            MaterialType::Conductor {
+                eta,
+                k,
+                reflectance,
+                uroughness,
+                vroughness,
+                remap_roughness,
+            } => {
+                params.spectrum_input(
+                    "eta",
+                    eta,
+                    &Spectrum::Named("metal-Cu-eta".to_string()),
+                    textures,
+                );
+                params.spectrum_input("k", k, &Spectrum::Named("metal-Cu-k".to_string()), textures);
+                params.optional_spectrum_input("reflectance", reflectance.as_ref(), textures);
+                params.float_input("uroughness", uroughness, 0.0, textures);
+                params.float_input("vroughness", vroughness, 0.0, textures);
+                params.boolean("remaproughness", *remap_roughness, true);
+                "conductor"
+            }
+            MaterialType::Dielectric {
+                eta,
+                uroughness,
+                vroughness,
+                remap_roughness,
+            } => {
+                params.default_spectrum("eta", eta, &constant(1.5));
+                params.float_input("uroughness", uroughness, 0.0, textures);
+                params.float_input("vroughness", vroughness, 0.0, textures);
+                params.boolean("remaproughness", *remap_roughness, true);
+                "dielectric"
+            }
+            MaterialType::Diffuse { reflectance } => {
+                params.spectrum_input("reflectance", reflectance, &constant(0.5), textures);
+                "diffuse"
+            }
+            MaterialType::DiffuseTransmission {
+                reflectance,
+                transmittance,
+                scale,
+            } => {
+                params.spectrum_input("reflectance", reflectance, &constant(0.25), textures);
+                params.spectrum_input("transmittance", transmittance, &constant(0.25), textures);
+                params.float("scale", *scale, 1.0);
+                "diffusetransmission"
+            }
+            MaterialType::Hair {
+                sigma_a,
+                color,
+                eumelanin,
+                pheomelanin,
+                eta,
+                beta_m,
+                beta_n,
+                alpha,
+            } => {
+                params.optional_spectrum_input("sigma_a", sigma_a.as_ref(), textures);
+                params.optional_spectrum_input("color", color.as_ref(), textures);
+                params.optional_float_input("eumelanin", eumelanin.as_ref(), textures);
+                params.float_input("pheomelanin", pheomelanin, 0.0, textures);
+                params.float_input("eta", eta, 1.55, textures);
+                params.float_input("beta_m", beta_m, 0.3, textures);
+                params.float_input("beta_n", beta_n, 0.3, textures);
+                params.float_input("alpha", alpha, 2.0, textures);
+                "hair"
+            }
+            MaterialType::Interface => "interface",
+            MaterialType::Measured { filename } => {
+                params.optional_string("filename", Some(filename));
+                "measured"
+            }
+            MaterialType::Mix { materials, amount } => {
+                params.add(
+                    "string",
+                    "materials",
+                    format!("[ \"{}\" \"{}\" ]", materials[0], materials[1]),
+                );
+                params.float_input("amount", amount, 0.5, textures);
+                "mix"
+            }
+            MaterialType::Subsurface {
+                eta,
+                g,
+                mfp,
+                name,
+                reflectance,
+                sigma_a,
+                sigma_s,
+                scale,
+                uroughness,
+                vroughness,
+                remap_roughness,
+            } => {
+                let color_space = material.color_space;
+
+                params.float("eta", *eta, 1.33);
+                params.float("g", *g, 0.0);
+                params.spectrum_input("mfp", mfp, &constant(1.0), textures);
+                params.optional_string("name", name.as_deref());
+                params.optional_spectrum_input("reflectance", reflectance.as_ref(), textures);
+                params.spectrum_input(
+                    "sigma_a",
+                    sigma_a,
+                    &Spectrum::Rgb([0.0011, 0.0024, 0.014], color_space),
+                    textures,
+                );
+                params.spectrum_input(
+                    "sigma_s",
+                    sigma_s,
+                    &Spectrum::Rgb([2.55, 3.21, 3.77], color_space),
+                    textures,
+                );
+                params.float("scale", *scale, 1.0);
+                params.float_input("uroughness", uroughness, 0.0, textures);
+                params.float_input("vroughness", vroughness, 0.0, textures);
+                params.boolean("remaproughness", *remap_roughness, true);
+                "subsurface"
+            }
+            MaterialType::ThinDielectric { eta } => {
+                params.default_spectrum("eta", eta, &constant(1.5));
+                "thindielectric"
+            }
+        };
+
+        params.params[0] = format!("\"string type\" \"{ty}\"");
+        if let Some(index) = material.displacement {
+            params.texture("displacement", &textures[index]);
+        }
+        params.optional_string("normalmap", material.normal_map.as_deref());
+
+        Directive {
+            head: format!("MakeNamedMaterial \"{name}\""),
+            params,
+            color_space: material.color_space,
+        }
+    }
+}
+
+/// Color space of an RGB spectrum, or the default one.
+fn spectrum_color_space(spectrum: &Spectrum) -> ColorSpace {
+    match spectrum {
+        Spectrum::Rgb(_, color_space) => *color_space,
+        _ => ColorSpace::default(),
+    }
+}
+
+/// Parameter list built from typed values.
+#[derive(Default)]
+struct Params {
+    params: Vec<String>,
+    /// Set when a value can't be written, such as an infinite float.
+    invalid: bool,
+}
+
+impl Params {
+    fn add(&mut self, ty: &str, name: &str, value: impl Display) {
+        let mut param = String::new();
+        match write!(param, "\"{ty} {name}\" {value}") {
+            Ok(()) => self.params.push(param),
+            Err(_) => self.invalid = true,
+        }
+    }
+
+    /// Returns the written parameters, fails if any of the values can't be written.
+    fn lines(&self) -> std::result::Result<&[String], fmt::Error> {
+        match self.invalid {
+            true => Err(fmt::Error),
+            false => Ok(&self.params),
+        }
+    }
+
+    fn float(&mut self, name: &str, value: f32, default: f32) {
+        if value != default {
+            self.add("float", name, Float(value));
+        }
+    }
+
+    fn integer(&mut self, name: &str, value: i32, default: i32) {
+        if value != default {
+            self.add("integer", name, value);
+        }
+    }
+
+    fn boolean(&mut self, name: &str, value: bool, default: bool) {
+        if value != default {
+            self.add("bool", name, value);
+        }
+    }
+
+    fn string(&mut self, name: &str, value: &str, default: &str) {
+        if value != default {
+            self.add("string", name, format!("\"{value}\""));
+        }
+    }
+
+    fn light_sampler(&mut self, value: LightSampler, default: LightSampler) {
+        let name = |sampler| match sampler {
+            LightSampler::Uniform => "uniform",
+            LightSampler::Power => "power",
+            LightSampler::Bvh => "bvh",
+            LightSampler::Exhaustive => "exhaustive",
+        };
+
+        self.string("lightsampler", name(value), name(default));
+    }
+
+    fn optional_float(&mut self, name: &str, value: Option<f32>) {
+        if let Some(value) = value {
+            self.add("float", name, Float(value));
+        }
+    }
+
+    fn vector(&mut self, name: &str, value: &[f32; 3], default: [f32; 3]) {
+        if *value != default {
+            self.floats("point3", name, value);
+        }
+    }
+
+    /// Resolution and bounds of a medium grid.
+    fn grid(&mut self, [nx, ny, nz]: [i32; 3], p0: &[f32; 3], p1: &[f32; 3]) {
+        self.integer("nx", nx, 1);
+        self.integer("ny", ny, 1);
+        self.integer("nz", nz, 1);
+        self.vector("p0", p0, [0.0, 0.0, 0.0]);
+        self.vector("p1", p1, [1.0, 1.0, 1.0]);
+    }
+
+    fn optional_string(&mut self, name: &str, value: Option<&str>) {
+        if let Some(value) = value {
+            self.add("string", name, format!("\"{value}\""));
+        }
+    }
+
+    fn texture(&mut self, name: &str, texture: &str) {
+        self.add("texture", name, format!("\"{texture}\""));
+    }
+
+    /// Write a list of values, nothing is written for an empty list.
+    fn floats(&mut self, ty: &str, name: &str, values: &[f32]) {
+        self.list(ty, name, values.iter().map(|value| Float(*value)));
+    }
+
+    fn integers(&mut self, name: &str, values: &[i32]) {
+        self.list("integer", name, values.iter());
+    }
+
+    fn list<T: Display>(&mut self, ty: &str, name: &str, values: impl ExactSizeIterator<Item = T>) {
+        if values.len() == 0 {
+            return;
+        }
+
+        let mut list = String::from("[");
+        for value in values {
+            if write!(list, " {value}").is_err() {
+                self.invalid = true;
+                return;
+            }
+        }
+        list.push_str(" ]");
+
+        self.add(ty, name, list);
+    }
+
+    fn spectrum(&mut self, name: &str, spectrum: &Spectrum) {
+        match spectrum {
+            Spectrum::Rgb(rgb, _) => self.floats("rgb", name, rgb),
+            Spectrum::Blackbody(temperature) => self.add("blackbody", name, temperature),
+            Spectrum::Constant(value) => self.add("float", name, Float(*value)),
+            Spectrum::Named(named) => self.add("spectrum", name, format!("\"{named}\"")),
+            Spectrum::Sampled(samples) => {
+                let values = samples
+                    .iter()
+                    .flat_map(|(lambda, value)| [*lambda, *value])
+                    .collect::<Vec<_>>();
+                self.floats("spectrum", name, &values);
+            }
+        }
+    }
+
+    fn default_spectrum(&mut self, name: &str, spectrum: &Spectrum, default: &Spectrum) {
+        if spectrum != default {
+            self.spectrum(name, spectrum);
+        }
+    }
+
+    fn float_input(&mut self, name: &str, input: &FloatInput, default: f32, textures: &[String]) {
+        match input {
+            FloatInput::Value(value) => self.float(name, *value, default),
+            FloatInput::Texture(index) => self.texture(name, &textures[*index]),
+        }
+    }
+
+    fn optional_float_input(
+        &mut self,
+        name: &str,
+        input: Option<&FloatInput>,
+        textures: &[String],
+    ) {
+        match input {
+            Some(FloatInput::Value(value)) => self.add("float", name, Float(*value)),
+            Some(FloatInput::Texture(index)) => self.texture(name, &textures[*index]),
+            None => {}
+        }
+    }
+
+    fn spectrum_input(
+        &mut self,
+        name: &str,
+        input: &SpectrumInput,
+        default: &Spectrum,
+        textures: &[String],
+    ) {
+        match input {
+            SpectrumInput::Value(value) => self.default_spectrum(name, value, default),
+            SpectrumInput::Texture(index) => self.texture(name, &textures[*index]),
+        }
+    }
+
+    fn optional_spectrum_input(
+        &mut self,
+        name: &str,
+        input: Option<&SpectrumInput>,
+        textures: &[String],
+    ) {
+        match input {
+            Some(SpectrumInput::Value(value)) => self.spectrum(name, value),
+            Some(SpectrumInput::Texture(index)) => self.texture(name, &textures[*index]),
+            None => {}
+        }
+    }
+
+    fn mapping(&mut self, mapping: &TextureMapping) {
+        match mapping {
+            TextureMapping::Uv {
+                uscale,
+                vscale,
+                udelta,
+                vdelta,
+            } => {
+                self.float("uscale", *uscale, 1.0);
+                self.float("vscale", *vscale, 1.0);
+                self.float("udelta", *udelta, 0.0);
+                self.float("vdelta", *vdelta, 0.0);
+            }
+            TextureMapping::Spherical => self.string("mapping", "spherical", "uv"),
+            TextureMapping::Cylindrical => self.string("mapping", "cylindrical", "uv"),
+            TextureMapping::Planar {
+                v1,
+                v2,
+                udelta,
+                vdelta,
+            } => {
+                self.string("mapping", "planar", "uv");
+                if *v1 != [1.0, 0.0, 0.0] {
+                    self.floats("vector3", "v1", v1);
+                }
+                if *v2 != [0.0, 1.0, 0.0] {
+                    self.floats("vector3", "v2", v2);
+                }
+                self.float("udelta", *udelta, 0.0);
+                self.float("vdelta", *vdelta, 0.0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+
+    const SCENE: &str = r#"
+Option "bool disablepixeljitter" true
+Option "integer seed" 3
+LookAt 0 5 -10  0 0 0  0 1 0
+MakeNamedMedium "fog" "string type" "homogeneous" "rgb sigma_s" [ 0.1 0.1 0.1 ] "float g" 0.5
+MediumInterface "" "fog"
+Camera "perspective" "float fov" 45
+Sampler "halton" "integer pixelsamples" 64 "string randomization" "owen"
+PixelFilter "mitchell" "float xradius" 1.5 "float C" 0.5
+Film "rgb" "string filename" "out.exr" "integer xresolution" 400
+Integrator "volpath" "integer maxdepth" 8 "string lightsampler" "power"
+
+WorldBegin
+
+Texture "checks" "spectrum" "checkerboard" "float uscale" 8 "rgb tex1" [ 1 0 0 ]
+AttributeBegin
+    Scale 2 2 2
+    Texture "bumps" "float" "wrinkled" "integer octaves" 4
+AttributeEnd
+Texture "checks" "spectrum" "scale" "texture tex" "checks" "float scale" 0.5
+
+MakeNamedMaterial "floor" "string type" "diffuse" "texture reflectance" "checks"
+MakeNamedMedium "smoke" "string type" "uniformgrid" "integer nx" 2 "float density" [ 0.5 1 ]
+AttributeBegin
+    ColorSpace "rec2020"
+    LightSource "infinite" "rgb L" [ 0.5 0.5 0.5 ]
+    Material "coateddiffuse" "float roughness" 0.1 "texture displacement" "bumps"
+    Shape "sphere" "float radius" 2
+AttributeEnd
+
+ObjectBegin "tree"
+    NamedMaterial "floor"
+    Shape "cylinder" "float zmax" 3
+    Shape "trianglemesh" "point3 P" [ 0 0 0 1 0 0 0 1 0 ] "integer indices" [ 0 1 2 ]
+ObjectEnd
+
+AttributeBegin
+    AreaLightSource "diffuse" "blackbody L" 5500 "bool twosided" true "float power" 10
+    ReverseOrientation
+    MediumInterface "smoke" "fog"
+    ActiveTransform EndTime
+    Translate 0 1 0
+    ActiveTransform All
+    Shape "disk"
+AttributeEnd
+
+Translate 3 0 0
+ObjectInstance "tree"
+LightSource "spot" "point3 from" [ 0 4 0 ] "point3 to" [ 0 0 0 ] "float coneangle" 45 "float power" 100
+
+AttributeBegin
+    ActiveTransform EndTime
+    Translate 0 2 0
+    LightSource "point"
+AttributeEnd
+"#;
+
+    #[test]
+    fn write_scene() -> Result<()> {
+        let scene = Scene::load(SCENE, None)?;
+        let output = scene.to_string();
+
+        // Shadowed and anonymous entities are renamed.
+        assert!(output.contains("Texture \"checks-0\" \"spectrum\" \"checkerboard\""));
+        assert!(output.contains("\"texture tex\" \"checks-0\""));
+        assert!(output.contains(concat!(
+            "    MakeNamedMaterial \"material-1\"\n",
+            "        \"string type\" \"coateddiffuse\"\n",
+        )));
+
+        // Defaults are omitted.
+        assert!(!output.contains("maxcomponentvalue"));
+        assert!(output.contains("Shape \"disk\"\n"));
+
+        let written = Scene::load(&output, None)?;
+
+        assert_eq!(written.shapes.len(), scene.shapes.len());
+        assert_eq!(written.textures.len(), scene.textures.len());
+        assert_eq!(written.materials.len(), scene.materials.len());
+        assert_eq!(written.objects[0].shape_start, scene.objects[0].shape_start);
+        assert_eq!(written.instances.len(), 1);
+        assert!(written.options.disable_pixel_jitter);
+        assert_eq!(written.options.seed, 3);
+        assert_eq!(written.pixel_filter, scene.pixel_filter);
+        assert!(matches!(
+            written.integrator,
+            Some(Integrator::VolPath {
+                max_depth: 8,
+                light_sampler: LightSampler::Power,
+                regularize: false,
+            })
+        ));
+        assert!(output.contains(
+            "PixelFilter \"mitchell\"\n    \"float xradius\" 1.5\n    \"float C\" 0.5\n"
+        ));
+        assert!(matches!(
+            written.sampler,
+            Some(Sampler::Halton {
+                pixel_samples: 64,
+                seed: 3,
+                randomization: RandomizeStrategy::Owen,
+            })
+        ));
+
+        for (a, b) in written.shapes.iter().zip(&scene.shapes) {
+            assert_eq!(a.transform, b.transform);
+            assert_eq!(a.transform_end, b.transform_end);
+            assert_eq!(a.reverse_orientation, b.reverse_orientation);
+            assert_eq!(a.material_index, b.material_index);
+            assert_eq!(a.area_light_index, b.area_light_index);
+            assert_eq!(a.inside_medium_index, b.inside_medium_index);
+            assert_eq!(a.outside_medium_index, b.outside_medium_index);
+        }
+
+        // The camera medium carries over to the world block.
+        assert_eq!(written.camera.as_ref().unwrap().medium_index, Some(0));
+        assert_eq!(written.lights[0].medium_index, Some(0));
+        assert_eq!(written.shapes[3].inside_medium_index, Some(1));
+        assert_eq!(written.mediums[0].transform, scene.mediums[0].transform);
+        assert!(matches!(
+            &written.mediums[1].params,
+            Medium::UniformGrid { nx: 2, density, .. } if density == &[0.5, 1.0]
+        ));
+
+        assert_eq!(
+            written.camera.as_ref().map(|camera| camera.transform),
+            scene.camera.as_ref().map(|camera| camera.transform)
+        );
+        assert_eq!(written.textures[1].transform, scene.textures[1].transform);
+        assert_eq!(written.materials[1].color_space, ColorSpace::Rec2020);
+        assert!(matches!(
+            written.lights[0].params,
+            Light::Infinite {
+                spectrum: Some(Spectrum::Rgb(_, ColorSpace::Rec2020)),
+                ..
+            }
+        ));
+
+        let light = &written.lights[1];
+        assert_eq!(
+            light.transform,
+            Mat4::from_translation(Vec3::new(3.0, 0.0, 0.0))
+        );
+        assert!(matches!(
+            light.params,
+            Light::Spot {
+                from: [0.0, 4.0, 0.0],
+                to: [0.0, 0.0, 0.0],
+                cone_angle,
+                power: Some(power),
+                ..
+            } if cone_angle == 45.0 && power == 100.0
+        ));
+
+        let light = &written.lights[2];
+        assert_eq!(light.transform, scene.lights[2].transform);
+        assert_eq!(light.transform_end, scene.lights[2].transform_end);
+        assert_ne!(light.transform, light.transform_end);
+
+        // Writing a written scene gives the same output.
+        assert_eq!(written.to_string(), output);
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_non_finite() -> Result<()> {
+        let mut scene = Scene::load(SCENE, None)?;
+        scene.start_time = f32::NAN;
+        let err = scene.write_to(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut scene = Scene::load(SCENE, None)?;
+        scene.options.displacement_edge_scale = f32::INFINITY;
+        assert!(scene.write_to(&mut Vec::new()).is_err());
+
+        let mut scene = Scene::load(SCENE, None)?;
+        for shape in &mut scene.shapes {
+            if let Shape::TriangleMesh { positions, .. } = &mut shape.params {
+                positions[4] = f32::NEG_INFINITY;
+            }
+        }
+        assert!(scene.write_to(&mut Vec::new()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn unique_names() {
+        let names = super::unique_names(&[Some("a"), None, Some("a"), Some("b-1")], "b");
+        assert_eq!(names, ["a-0", "b-1_", "a", "b-1"]);
+    }
+}
@@ -29,13 +29,13 @@ fn disney_cloud() {
 
     // Infinite light
     {
-        let infinite = &scene.lights[0];
+        let infinite = &scene.lights[0].params;
 
         let Light::Infinite { spectrum , ..} = infinite else {
             panic!("Unexpected light type at 0, want Infinite");
         };
 
-        let Spectrum::Rgb(rgb) = spectrum.unwrap() else {
+        let Some(Spectrum::Rgb(rgb, _)) = *spectrum else {
             panic!("Unexpected spectrum value type");
         };
 
@@ -44,8 +44,8 @@ fn disney_cloud() {
 
     // Distant light
     {
-        let distant = &scene.lights[1];
-        assert!(matches!(distant, Light::Distant));
+        let distant = &scene.lights[1].params;
+        assert!(matches!(distant, Light::Distant { .. }));
     }
 
     assert_eq!(scene.materials.len(), 2);